@@ -1,8 +1,9 @@
 extern crate synthrs;
 
+use synthrs::g711::Law;
 use synthrs::synthesizer::{make_samples, quantize_samples};
 use synthrs::wave::sine_wave;
-use synthrs::writer::write_wav_file;
+use synthrs::writer::{write_wav_file, write_wav_g711_file, WavSpec};
 
 fn main() {
     write_wav_file(
@@ -68,4 +69,30 @@ fn main() {
         })),
     )
     .expect("failed");
+
+    // 8kHz G.711 μ-law, as used by telephone systems and IVR prompts
+    write_wav_g711_file(
+        "out/dialtone_ulaw.wav",
+        &WavSpec::new(1, 8000),
+        Law::MuLaw,
+        &make_samples(15.0, 8000, |t: f64| -> f64 {
+            0.5 * (sine_wave(350.0)(t) + sine_wave(440.0)(t))
+        }),
+    )
+    .expect("failed");
+
+    // A-law for European systems
+    write_wav_g711_file(
+        "out/ring_alaw.wav",
+        &WavSpec::new(1, 8000),
+        Law::ALaw,
+        &make_samples(15.0, 8000, |t: f64| -> f64 {
+            if t % 6.0 < 2.0 {
+                0.50 * (sine_wave(440.0)(t) + sine_wave(480.0)(t))
+            } else {
+                0.0
+            }
+        }),
+    )
+    .expect("failed");
 }
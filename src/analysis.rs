@@ -0,0 +1,385 @@
+//! Frequency response analysis for filters.
+//!
+//! Frequencies are given as a fraction of the sample rate, the same as the `cutoff` arguments
+//! in `crate::filter` (see `crate::filter::cutoff_from_frequency`), so 0.5 is the Nyquist
+//! frequency.
+//!
+//! Stateless FIR kernels, such as those created by `crate::filter::lowpass_filter`, can be
+//! analysed directly:
+//!
+//! ```
+//! use synthrs::analysis::fir_response;
+//! use synthrs::filter::{cutoff_from_frequency, lowpass_filter};
+//!
+//! let lowpass = lowpass_filter(cutoff_from_frequency(1000.0, 44_100), 0.01);
+//! let response = fir_response(&lowpass, 512);
+//!
+//! // Use `response.frequencies_hz(44_100)` and `response.magnitude_db` to plot the response
+//! assert!(response.magnitude_db_at(cutoff_from_frequency(100.0, 44_100)).abs() < 0.1);
+//! assert!(response.magnitude_db_at(cutoff_from_frequency(5000.0, 44_100)) < -60.0);
+//! ```
+//!
+//! IIR filters are described by their feedforward (`b`) and feedback (`a`) coefficients, with
+//! `a[0]` normally being 1:
+//!
+//! ```
+//! use synthrs::analysis::iir_response;
+//!
+//! // One-pole low-pass: y[n] = 0.1x[n] + 0.9y[n - 1]
+//! let response = iir_response(&[0.1], &[1.0, -0.9], 256);
+//! assert!(response.magnitude_db[0].abs() < 1e-9);
+//! ```
+//!
+//! Stateful filters are analysed empirically by probing them with an impulse:
+//!
+//! ```
+//! use synthrs::analysis::probe_response;
+//! use synthrs::filter::Comb;
+//!
+//! let mut comb = Comb::new(0.001, 44_100, 0.5, 0.5, 0.5);
+//! let response = probe_response(|sample| comb.tick(sample), 4096, 512);
+//! ```
+
+use std::f64::consts::PI;
+
+use num::complex::Complex;
+
+/// The frequency response of a filter, sampled at evenly-spaced frequencies from DC to Nyquist.
+#[derive(Clone, Debug)]
+pub struct FrequencyResponse {
+    /// Frequencies as a fraction of the sample rate, from 0 to 0.5
+    pub frequencies: Vec<f64>,
+    /// Magnitude in dB
+    pub magnitude_db: Vec<f64>,
+    /// Phase in radians, wrapped to [-π, π]
+    pub phase: Vec<f64>,
+    /// Group delay in samples
+    pub group_delay: Vec<f64>,
+}
+
+impl FrequencyResponse {
+    /// Frequencies in hertz for a given sample rate.
+    pub fn frequencies_hz(&self, sample_rate: usize) -> Vec<f64> {
+        self.frequencies
+            .iter()
+            .map(|f| f * sample_rate as f64)
+            .collect()
+    }
+
+    /// Linear magnitude.
+    pub fn magnitude(&self) -> Vec<f64> {
+        self.magnitude_db
+            .iter()
+            .map(|db| 10.0f64.powf(db / 20.0))
+            .collect()
+    }
+
+    /// Phase in radians, unwrapped so that it has no discontinuities of 2π.
+    pub fn unwrapped_phase(&self) -> Vec<f64> {
+        let mut offset = 0.0;
+        let mut previous = match self.phase.first() {
+            Some(&phase) => phase,
+            None => return Vec::new(),
+        };
+
+        self.phase
+            .iter()
+            .map(|&phase| {
+                let delta = phase - previous;
+                if delta > PI {
+                    offset -= 2.0 * PI;
+                } else if delta < -PI {
+                    offset += 2.0 * PI;
+                }
+                previous = phase;
+                phase + offset
+            })
+            .collect()
+    }
+
+    /// Magnitude in dB at the sampled frequency closest to `frequency`.
+    pub fn magnitude_db_at(&self, frequency: f64) -> f64 {
+        self.magnitude_db[self.index_of(frequency)]
+    }
+
+    /// Group delay in samples at the sampled frequency closest to `frequency`.
+    pub fn group_delay_at(&self, frequency: f64) -> f64 {
+        self.group_delay[self.index_of(frequency)]
+    }
+
+    fn index_of(&self, frequency: f64) -> usize {
+        let last = self.frequencies.len() - 1;
+        ((frequency / 0.5 * last as f64).round().max(0.0) as usize).min(last)
+    }
+}
+
+/// Evaluates the transfer function of an FIR kernel at `frequency`.
+pub fn fir_response_at(kernel: &[f64], frequency: f64) -> Complex<f64> {
+    polynomial_at(kernel, frequency)
+}
+
+/// Evaluates the transfer function B(z) / A(z) of an IIR filter at `frequency`.
+pub fn iir_response_at(b: &[f64], a: &[f64], frequency: f64) -> Complex<f64> {
+    polynomial_at(b, frequency) / polynomial_at(a, frequency)
+}
+
+/// Computes the frequency response of an FIR kernel at `points` frequencies.
+pub fn fir_response(kernel: &[f64], points: usize) -> FrequencyResponse {
+    iir_response(kernel, &[1.0], points)
+}
+
+/// Computes the frequency response of an IIR filter with feedforward coefficients `b` and
+/// feedback coefficients `a` at `points` frequencies.
+pub fn iir_response(b: &[f64], a: &[f64], points: usize) -> FrequencyResponse {
+    assert!(points >= 2);
+    let frequencies: Vec<f64> = (0..points)
+        .map(|i| 0.5 * i as f64 / (points - 1) as f64)
+        .collect();
+
+    let mut magnitude_db = Vec::with_capacity(points);
+    let mut phase = Vec::with_capacity(points);
+    let mut group_delay = Vec::with_capacity(points);
+
+    for &frequency in &frequencies {
+        let response = iir_response_at(b, a, frequency);
+        magnitude_db.push(20.0 * response.norm().log10());
+        phase.push(response.arg());
+        group_delay
+            .push(polynomial_group_delay(b, frequency) - polynomial_group_delay(a, frequency));
+    }
+
+    FrequencyResponse {
+        frequencies,
+        magnitude_db,
+        phase,
+        group_delay,
+    }
+}
+
+/// Computes the first `length` samples of the impulse response of an IIR filter.
+pub fn iir_impulse_response(b: &[f64], a: &[f64], length: usize) -> Vec<f64> {
+    let mut filter = DirectForm::new(b, a);
+    impulse_response(|sample| filter.tick(sample), length)
+}
+
+/// Computes the first `length` samples of the step response of an IIR filter.
+pub fn iir_step_response(b: &[f64], a: &[f64], length: usize) -> Vec<f64> {
+    let mut filter = DirectForm::new(b, a);
+    step_response(|sample| filter.tick(sample), length)
+}
+
+/// Computes the step response of an FIR kernel. The last value is the DC gain.
+pub fn fir_step_response(kernel: &[f64]) -> Vec<f64> {
+    kernel
+        .iter()
+        .scan(0.0, |acc, &h| {
+            *acc += h;
+            Some(*acc)
+        })
+        .collect()
+}
+
+/// Records `length` samples of the response of a stateful filter to a unit impulse.
+///
+/// ```
+/// use synthrs::analysis::impulse_response;
+/// use synthrs::filter::AllPass;
+///
+/// let mut allpass = AllPass::new(0.01, 44_100, 0.5);
+/// let response = impulse_response(|sample| allpass.tick(sample), 1024);
+/// ```
+pub fn impulse_response<F>(mut tick: F, length: usize) -> Vec<f64>
+where
+    F: FnMut(f64) -> f64,
+{
+    (0..length)
+        .map(|i| tick(if i == 0 { 1.0 } else { 0.0 }))
+        .collect()
+}
+
+/// Records `length` samples of the response of a stateful filter to a unit step.
+pub fn step_response<F>(mut tick: F, length: usize) -> Vec<f64>
+where
+    F: FnMut(f64) -> f64,
+{
+    (0..length).map(|_| tick(1.0)).collect()
+}
+
+/// Estimates the frequency response of a stateful filter by probing it with an impulse and
+/// analysing the first `length` samples of its response. `length` should be long enough for the
+/// response to have decayed.
+pub fn probe_response<F>(tick: F, length: usize, points: usize) -> FrequencyResponse
+where
+    F: FnMut(f64) -> f64,
+{
+    fir_response(&impulse_response(tick, length), points)
+}
+
+// Evaluates sum(c[n] * e^(-jωn))
+fn polynomial_at(coefficients: &[f64], frequency: f64) -> Complex<f64> {
+    let step = Complex::from_polar(1.0, -2.0 * PI * frequency);
+    let mut z = Complex::new(1.0, 0.0);
+    let mut sum = Complex::new(0.0, 0.0);
+
+    for &c in coefficients {
+        sum += z * c;
+        z *= step;
+    }
+
+    sum
+}
+
+// Group delay of a polynomial: Re(sum(n * c[n] * e^(-jωn)) / sum(c[n] * e^(-jωn)))
+fn polynomial_group_delay(coefficients: &[f64], frequency: f64) -> f64 {
+    let ramped: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .map(|(n, c)| n as f64 * c)
+        .collect();
+    let denominator = polynomial_at(coefficients, frequency);
+
+    if denominator.norm() < 1e-12 {
+        return 0.0;
+    }
+
+    (polynomial_at(&ramped, frequency) / denominator).re
+}
+
+// Direct form I filter used for computing IIR time-domain responses
+struct DirectForm {
+    b: Vec<f64>,
+    a: Vec<f64>,
+    inputs: Vec<f64>,
+    outputs: Vec<f64>,
+}
+
+impl DirectForm {
+    fn new(b: &[f64], a: &[f64]) -> DirectForm {
+        assert!(!a.is_empty() && a[0] != 0.0);
+        DirectForm {
+            b: b.iter().map(|x| x / a[0]).collect(),
+            a: a.iter().map(|x| x / a[0]).collect(),
+            inputs: vec![0.0; b.len()],
+            outputs: vec![0.0; a.len()],
+        }
+    }
+
+    fn tick(&mut self, input: f64) -> f64 {
+        if !self.inputs.is_empty() {
+            self.inputs.rotate_right(1);
+            self.inputs[0] = input;
+        }
+        self.outputs.rotate_right(1);
+
+        let feedforward = self
+            .b
+            .iter()
+            .zip(self.inputs.iter())
+            .fold(0.0, |acc, (b, x)| acc + b * x);
+        let feedback = self
+            .a
+            .iter()
+            .zip(self.outputs.iter())
+            .skip(1)
+            .fold(0.0, |acc, (a, y)| acc + a * y);
+
+        let output = feedforward - feedback;
+        self.outputs[0] = output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{cutoff_from_frequency, lowpass_filter, Comb};
+
+    #[test]
+    fn test_fir_response() {
+        let lowpass = lowpass_filter(cutoff_from_frequency(2000.0, 44_100), 0.01);
+        let response = fir_response(&lowpass, 1024);
+
+        assert_eq!(response.frequencies.len(), 1024);
+        assert!((response.frequencies[1023] - 0.5).abs() < 1e-12);
+        assert!(
+            response
+                .magnitude_db_at(cutoff_from_frequency(500.0, 44_100))
+                .abs()
+                < 0.01
+        );
+        assert!(
+            (response.magnitude_db_at(cutoff_from_frequency(2000.0, 44_100)) + 6.0).abs() < 0.5
+        );
+        assert!(response.magnitude_db_at(cutoff_from_frequency(4000.0, 44_100)) < -70.0);
+
+        // Symmetric kernels have a constant group delay of half their length
+        let expected_delay = (lowpass.len() - 1) as f64 / 2.0;
+        assert!((response.group_delay_at(0.01) - expected_delay).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_iir_response() {
+        let (b, a) = ([0.1], [1.0, -0.9]);
+        let response = iir_response(&b, &a, 256);
+
+        // DC gain is 0.1 / (1 - 0.9) = 1, Nyquist gain is 0.1 / 1.9
+        assert!(response.magnitude_db[0].abs() < 1e-9);
+        assert!((response.magnitude()[255] - 0.1 / 1.9).abs() < 1e-9);
+        // Group delay at DC is 0.9 / (1 - 0.9)
+        assert!((response.group_delay[0] - 9.0).abs() < 1e-9);
+
+        let impulse = iir_impulse_response(&b, &a, 4);
+        let expected = [0.1, 0.09, 0.081, 0.0729];
+        for (x, y) in impulse.iter().zip(expected.iter()) {
+            assert!((x - y).abs() < 1e-12);
+        }
+
+        let step = iir_step_response(&b, &a, 200);
+        assert!((step[199] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_probe_response_matches_analytic_response() {
+        let lowpass = lowpass_filter(0.1, 0.05);
+        let analytic = fir_response(&lowpass, 64);
+
+        let mut history = vec![0.0; lowpass.len()];
+        let probed = probe_response(
+            |sample| {
+                history.rotate_right(1);
+                history[0] = sample;
+                history.iter().zip(lowpass.iter()).map(|(x, h)| x * h).sum()
+            },
+            lowpass.len(),
+            64,
+        );
+
+        for (x, y) in analytic.magnitude_db.iter().zip(probed.magnitude_db.iter()) {
+            assert!((x - y).abs() < 1e-6);
+        }
+
+        assert!((fir_step_response(&lowpass).last().unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_comb_response_has_peaks_at_delay_harmonics() {
+        // 10 sample delay line (delay rounding adds a sample)
+        let mut comb = Comb::new(9.0 / 44_100.0, 44_100, 1.0, 0.0, 0.5);
+        let impulse = impulse_response(|sample| comb.tick(sample), 512);
+        assert_eq!(impulse.iter().position(|&x| x != 0.0), Some(10));
+
+        let response = fir_response(&impulse, 101);
+        assert!(response.magnitude_db_at(0.1) > response.magnitude_db_at(0.05) + 6.0);
+        assert!(response.magnitude_db_at(0.2) > response.magnitude_db_at(0.15) + 6.0);
+    }
+
+    #[test]
+    fn test_unwrapped_phase() {
+        // A pure delay has linear phase
+        let response = fir_response(&[0.0, 0.0, 0.0, 1.0], 101);
+        let unwrapped = response.unwrapped_phase();
+        for (frequency, phase) in response.frequencies.iter().zip(unwrapped.iter()) {
+            assert!((phase + 2.0 * PI * frequency * 3.0).abs() < 1e-9);
+        }
+    }
+}
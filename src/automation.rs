@@ -0,0 +1,428 @@
+//! Parameter smoothing and automation.
+//!
+//! Changing a parameter such as `Comb::feedback` or a filter cutoff abruptly while audio is
+//! running causes zipper noise. A `SmoothedValue` glides towards its target over a set time
+//! instead, one `tick` per sample.
+//!
+//! An `AutomationLane` describes how a parameter changes over a timeline, as breakpoints in
+//! seconds or MIDI ticks, and is evaluated at every sample when rendering with
+//! `make_samples_with_automation` or
+//! `crate::synthesizer::make_samples_from_midi_with_automation`.
+//!
+//! ```
+//! use synthrs::automation::{Smoothing, SmoothedValue};
+//! use synthrs::filter::Comb;
+//!
+//! let mut comb = Comb::new(0.025, 44_100, 0.5, 0.5, 0.2);
+//! let mut feedback = SmoothedValue::new(0.2, Smoothing::Linear, 0.05, 44_100);
+//!
+//! let output: Vec<f64> = (0..44_100)
+//!     .map(|i| {
+//!         if i == 22_050 {
+//!             feedback.set_target(0.8);
+//!         }
+//!         comb.feedback = feedback.tick();
+//!         comb.tick(if i % 4410 == 0 { 1.0 } else { 0.0 })
+//!     })
+//!     .collect();
+//! ```
+
+use crate::midi::MidiSong;
+
+/// How a `SmoothedValue` moves towards its target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Exponential approach, reaching about 63% of the way to the target after the smoothing
+    /// time. Cheap and natural sounding, but never quite arrives.
+    OnePole,
+    /// Constant-rate ramp, arriving at the target exactly after the smoothing time.
+    Linear,
+}
+
+/// A parameter value that is smoothed over time when changed.
+#[derive(Clone, Debug)]
+pub struct SmoothedValue {
+    current: f64,
+    target: f64,
+    step: f64,
+    steps_remaining: usize,
+    coefficient: f64,
+    // Coefficient for the smoothing time, used from the next `set_target`
+    next_coefficient: f64,
+    ramp_length: usize,
+    pub smoothing: Smoothing,
+}
+
+impl SmoothedValue {
+    /// `time` is the smoothing time in seconds: the time constant for `Smoothing::OnePole`,
+    /// or the ramp length for `Smoothing::Linear`.
+    pub fn new(value: f64, smoothing: Smoothing, time: f64, sample_rate: usize) -> SmoothedValue {
+        let mut smoothed = SmoothedValue {
+            current: value,
+            target: value,
+            step: 0.0,
+            steps_remaining: 0,
+            coefficient: 0.0,
+            next_coefficient: 0.0,
+            ramp_length: 0,
+            smoothing,
+        };
+        smoothed.set_time(time, sample_rate);
+        smoothed.coefficient = smoothed.next_coefficient;
+        smoothed
+    }
+
+    /// Changes the smoothing time in seconds. Takes effect on the next `set_target`, so a change
+    /// already in progress keeps its speed.
+    pub fn set_time(&mut self, time: f64, sample_rate: usize) {
+        let samples = (time * sample_rate as f64).max(0.0);
+        self.ramp_length = samples.round() as usize;
+        self.next_coefficient = if samples > 0.0 {
+            (-1.0 / samples).exp()
+        } else {
+            0.0
+        };
+    }
+
+    /// Starts moving towards `target`.
+    pub fn set_target(&mut self, target: f64) {
+        self.target = target;
+        self.coefficient = self.next_coefficient;
+        if self.ramp_length == 0 {
+            self.current = target;
+            self.steps_remaining = 0;
+        } else {
+            self.steps_remaining = self.ramp_length;
+            self.step = (target - self.current) / self.ramp_length as f64;
+        }
+    }
+
+    /// Jumps to `value` without smoothing.
+    pub fn set_immediate(&mut self, value: f64) {
+        self.current = value;
+        self.target = value;
+        self.steps_remaining = 0;
+    }
+
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    /// Current value, without advancing.
+    pub fn value(&self) -> f64 {
+        self.current
+    }
+
+    /// Returns `true` while still moving towards the target.
+    pub fn is_smoothing(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Advances by one sample, returning the new value.
+    pub fn tick(&mut self) -> f64 {
+        if !self.is_smoothing() {
+            return self.current;
+        }
+
+        match self.smoothing {
+            Smoothing::OnePole => {
+                self.current = self.target + (self.current - self.target) * self.coefficient;
+                if (self.current - self.target).abs() < 1e-9 {
+                    self.current = self.target;
+                }
+            }
+            Smoothing::Linear => {
+                if self.steps_remaining <= 1 {
+                    self.current = self.target;
+                    self.steps_remaining = 0;
+                } else {
+                    self.current += self.step;
+                    self.steps_remaining -= 1;
+                }
+            }
+        }
+
+        self.current
+    }
+}
+
+/// Shape of the segment from one breakpoint to the next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// Exponential interpolation, which sounds even for frequencies and gains. Falls back to
+    /// linear when the segment crosses or touches zero.
+    Exponential,
+    /// Holds the value until the next breakpoint.
+    Step,
+}
+
+/// Units of breakpoint times in an `AutomationLane`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeBase {
+    Seconds,
+    /// MIDI ticks at a fixed tempo
+    Ticks {
+        bpm: f64,
+        ticks_per_beat: f64,
+    },
+}
+
+impl TimeBase {
+    /// Ticks at the tempo and resolution of a MIDI song.
+    pub fn from_song(song: &MidiSong) -> TimeBase {
+        TimeBase::Ticks {
+            bpm: song.bpm,
+            ticks_per_beat: song.time_unit as f64,
+        }
+    }
+
+    /// Converts a time in seconds to these units.
+    pub fn from_seconds(self, seconds: f64) -> f64 {
+        match self {
+            TimeBase::Seconds => seconds,
+            TimeBase::Ticks {
+                bpm,
+                ticks_per_beat,
+            } => seconds * bpm * ticks_per_beat / 60.0,
+        }
+    }
+}
+
+/// A point in an `AutomationLane`. `curve` is the shape of the segment leading to the next point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub time: f64,
+    pub value: f64,
+    pub curve: Curve,
+}
+
+/// A timeline of breakpoints for a single parameter.
+///
+/// Before the first breakpoint the lane holds the first value, and after the last it holds the
+/// last value.
+///
+/// ```
+/// use synthrs::automation::{AutomationLane, Curve, TimeBase};
+///
+/// // Sweep a cutoff from 200Hz to 5kHz over the first two beats at 120BPM, then drop back
+/// let mut cutoff = AutomationLane::new(TimeBase::Ticks { bpm: 120.0, ticks_per_beat: 480.0 });
+/// cutoff.add_point(0.0, 200.0, Curve::Exponential);
+/// cutoff.add_point(960.0, 5000.0, Curve::Step);
+/// cutoff.add_point(1920.0, 200.0, Curve::Step);
+///
+/// assert!((cutoff.value_at_seconds(0.5).unwrap() - 1000.0).abs() < 1e-9);
+/// assert_eq!(cutoff.value_at_seconds(1.5), Some(5000.0));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutomationLane {
+    points: Vec<Breakpoint>,
+    pub time_base: TimeBase,
+}
+
+impl AutomationLane {
+    pub fn new(time_base: TimeBase) -> AutomationLane {
+        AutomationLane {
+            points: Vec::new(),
+            time_base,
+        }
+    }
+
+    /// Adds a breakpoint, keeping points in time order. Points at the same time are kept in
+    /// insertion order, allowing instantaneous jumps.
+    pub fn add_point(&mut self, time: f64, value: f64, curve: Curve) {
+        let index = self.points.partition_point(|point| point.time <= time);
+        self.points.insert(index, Breakpoint { time, value, curve });
+    }
+
+    /// Adds a breakpoint, for chaining.
+    pub fn with_point(mut self, time: f64, value: f64, curve: Curve) -> AutomationLane {
+        self.add_point(time, value, curve);
+        self
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Value at `time` in the lane's time base, or `None` if the lane is empty.
+    pub fn value_at(&self, time: f64) -> Option<f64> {
+        let first = self.points.first()?;
+        let index = self.points.partition_point(|point| point.time <= time);
+
+        if index == 0 {
+            return Some(first.value);
+        }
+        let from = self.points[index - 1];
+        let to = match self.points.get(index) {
+            Some(to) => to,
+            None => return Some(from.value),
+        };
+
+        let x = (time - from.time) / (to.time - from.time);
+        let value = match from.curve {
+            Curve::Step => from.value,
+            Curve::Exponential if from.value * to.value > 0.0 => {
+                from.value * (to.value / from.value).powf(x)
+            }
+            Curve::Linear | Curve::Exponential => from.value + (to.value - from.value) * x,
+        };
+
+        Some(value)
+    }
+
+    /// Value at `seconds` from the start, or `None` if the lane is empty.
+    pub fn value_at_seconds(&self, seconds: f64) -> Option<f64> {
+        self.value_at(self.time_base.from_seconds(seconds))
+    }
+
+    /// Sample-accurate values for `num_samples` samples from the start, using `default` if the
+    /// lane is empty.
+    pub fn render(&self, sample_rate: usize, num_samples: usize, default: f64) -> Vec<f64> {
+        (0..num_samples)
+            .map(|i| {
+                self.value_at_seconds(i as f64 / sample_rate as f64)
+                    .unwrap_or(default)
+            })
+            .collect()
+    }
+}
+
+/// Values of all lanes at `seconds`, with empty lanes giving 0.0.
+pub(crate) fn values_at_seconds(lanes: &[AutomationLane], seconds: f64, values: &mut Vec<f64>) {
+    values.clear();
+    values.extend(
+        lanes
+            .iter()
+            .map(|lane| lane.value_at_seconds(seconds).unwrap_or(0.0)),
+    );
+}
+
+/// Like `crate::synthesizer::make_samples`, with the waveform also given the value of each
+/// automation lane at every sample, in the same order as `lanes`.
+///
+/// ```
+/// use synthrs::automation::{make_samples_with_automation, AutomationLane, Curve, TimeBase};
+/// use synthrs::wave::sine_wave;
+///
+/// // A one second fade in
+/// let gain = AutomationLane::new(TimeBase::Seconds)
+///     .with_point(0.0, 0.0, Curve::Linear)
+///     .with_point(1.0, 1.0, Curve::Linear);
+///
+/// let samples = make_samples_with_automation(2.0, 44_100, &[gain], |t, values| {
+///     values[0] * sine_wave(440.0)(t)
+/// });
+/// ```
+pub fn make_samples_with_automation<F>(
+    length: f64,
+    sample_rate: usize,
+    lanes: &[AutomationLane],
+    mut waveform: F,
+) -> Vec<f64>
+where
+    F: FnMut(f64, &[f64]) -> f64,
+{
+    let num_samples = (sample_rate as f64 * length).floor() as usize;
+    let mut values = Vec::with_capacity(lanes.len());
+
+    (0..num_samples)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            values_at_seconds(lanes, t, &mut values);
+            waveform(t, &values)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_smoothed_value() {
+        let mut linear = SmoothedValue::new(0.0, Smoothing::Linear, 1.0, 4);
+        linear.set_target(1.0);
+        let ramp: Vec<f64> = (0..6).map(|_| linear.tick()).collect();
+        assert_eq!(ramp, vec![0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+        assert!(!linear.is_smoothing());
+
+        let mut one_pole = SmoothedValue::new(0.0, Smoothing::OnePole, 0.01, 1000);
+        one_pole.set_target(1.0);
+        for _ in 0..10 {
+            one_pole.tick();
+        }
+        assert!((one_pole.value() - (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+        for _ in 0..1000 {
+            one_pole.tick();
+        }
+        assert_eq!(one_pole.value(), 1.0);
+
+        // A new time does not speed up a change in progress
+        let mut one_pole = SmoothedValue::new(0.0, Smoothing::OnePole, 0.01, 1000);
+        one_pole.set_target(1.0);
+        one_pole.set_time(0.0, 1000);
+        one_pole.tick();
+        assert!((one_pole.value() - (1.0 - (-0.1f64).exp())).abs() < 1e-9);
+        one_pole.set_target(0.0);
+        assert_eq!(one_pole.tick(), 0.0);
+
+        let mut instant = SmoothedValue::new(0.0, Smoothing::Linear, 0.0, 1000);
+        instant.set_target(0.5);
+        assert_eq!(instant.value(), 0.5);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_automation_lane() {
+        let lane = AutomationLane::new(TimeBase::Seconds)
+            .with_point(2.0, 100.0, Curve::Exponential)
+            .with_point(0.0, 0.0, Curve::Linear)
+            .with_point(1.0, 1.0, Curve::Step)
+            .with_point(4.0, 10_000.0, Curve::Linear);
+
+        assert_eq!(AutomationLane::new(TimeBase::Seconds).value_at(1.0), None);
+        assert_eq!(lane.value_at(-1.0), Some(0.0));
+        assert_eq!(lane.value_at(0.5), Some(0.5));
+        assert_eq!(lane.value_at(1.5), Some(1.0));
+        assert_eq!(lane.value_at(2.0), Some(100.0));
+        assert!((lane.value_at(3.0).unwrap() - 1000.0).abs() < 1e-9);
+        assert_eq!(lane.value_at(5.0), Some(10_000.0));
+
+        // Exponential through zero is linear
+        let lane = AutomationLane::new(TimeBase::Seconds)
+            .with_point(0.0, -1.0, Curve::Exponential)
+            .with_point(1.0, 1.0, Curve::Linear);
+        assert_eq!(lane.value_at(0.5), Some(0.0));
+
+        // Coincident points jump
+        let lane = AutomationLane::new(TimeBase::Seconds)
+            .with_point(1.0, 0.0, Curve::Linear)
+            .with_point(1.0, 1.0, Curve::Linear)
+            .with_point(2.0, 1.0, Curve::Linear);
+        assert_eq!(lane.value_at(0.999), Some(0.0));
+        assert_eq!(lane.value_at(1.0), Some(1.0));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_ticks_and_render() {
+        let lane = AutomationLane::new(TimeBase::Ticks {
+            bpm: 60.0,
+            ticks_per_beat: 100.0,
+        })
+        .with_point(0.0, 0.0, Curve::Linear)
+        .with_point(100.0, 1.0, Curve::Linear);
+
+        assert_eq!(lane.value_at_seconds(0.5), Some(0.5));
+        assert_eq!(lane.render(4, 6, 0.0), vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0]);
+
+        let samples = make_samples_with_automation(1.0, 4, &[lane], |t, values| t + values[0]);
+        assert_eq!(samples, vec![0.0, 0.5, 1.0, 1.5]);
+    }
+}
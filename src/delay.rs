@@ -0,0 +1,411 @@
+//! Echo and multi-tap delay effects.
+//!
+//! Unlike the raw `crate::filter::DelayLine`, these mix repeats of the input back in with
+//! feedback. Delay times can be synced to a tempo, such as the `bpm` of a
+//! `crate::midi::MidiSong`, using `NoteValue`.
+//!
+//! ```
+//! use synthrs::delay::{Echo, NoteValue};
+//! use synthrs::midi::read_midi_file;
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sawtooth_wave;
+//!
+//! let song = read_midi_file("tests/assets/test.mid").unwrap();
+//! let samples = make_samples(0.5, 44_100, sawtooth_wave(220.0));
+//!
+//! // Up to 2 seconds of delay, with 0.5 feedback
+//! let mut echo = Echo::new(44_100, 2.0, 0.5);
+//! // Repeat every dotted eighth note
+//! echo.set_delay(NoteValue::Eighth.dotted_seconds(song.bpm));
+//!
+//! let echoed: Vec<f64> = samples
+//!     .into_iter()
+//!     .map(|sample| echo.tick(sample))
+//!     .collect();
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::filter::{pan_gains, FractionalDelayLine, Interpolation};
+use crate::processor::{decay_tail, Processor};
+
+/// Note values for tempo-synced delay times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteValue {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl NoteValue {
+    /// Length in beats, where a beat is a quarter note.
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteValue::Whole => 4.0,
+            NoteValue::Half => 2.0,
+            NoteValue::Quarter => 1.0,
+            NoteValue::Eighth => 0.5,
+            NoteValue::Sixteenth => 0.25,
+            NoteValue::ThirtySecond => 0.125,
+        }
+    }
+
+    /// Length in seconds at `bpm` beats per minute.
+    ///
+    /// ```
+    /// use synthrs::delay::NoteValue;
+    ///
+    /// assert_eq!(NoteValue::Eighth.seconds(120.0), 0.25);
+    /// ```
+    pub fn seconds(self, bpm: f64) -> f64 {
+        self.beats() * 60.0 / bpm
+    }
+
+    /// Length in seconds of the dotted note, one and a half times as long.
+    pub fn dotted_seconds(self, bpm: f64) -> f64 {
+        self.seconds(bpm) * 1.5
+    }
+
+    /// Length in seconds of the triplet note, two thirds as long.
+    pub fn triplet_seconds(self, bpm: f64) -> f64 {
+        self.seconds(bpm) * 2.0 / 3.0
+    }
+}
+
+/// A stateful echo with feedback and a low-pass damping filter in the feedback path, so that
+/// each repeat is darker than the last.
+///
+/// In stereo with `ping_pong` set, the input is summed to mono and repeats alternate between
+/// the left and right channels.
+#[derive(Clone, Debug)]
+pub struct Echo {
+    left: FractionalDelayLine,
+    right: FractionalDelayLine,
+    damping_state: (f64, f64),
+    /// Amount of each repeat fed back into the delay, 0.5 by default
+    pub feedback: f64,
+    /// Damping of the feedback path from 0.0 (none) to 1.0, 0.0 by default
+    pub damping: f64,
+    /// Wet (echoed) signal multiplier, 0.5 by default
+    pub wet: f64,
+    /// Dry (original) signal multiplier, 1.0 by default
+    pub dry: f64,
+    /// Alternate repeats between channels in `tick_stereo`
+    pub ping_pong: bool,
+}
+
+impl Echo {
+    /// Creates a new echo holding up to `max_delay` seconds. The delay is initially set to
+    /// `max_delay`.
+    pub fn new(sample_rate: usize, max_delay: f64, feedback: f64) -> Echo {
+        let delay_line = FractionalDelayLine::new(max_delay, sample_rate, Interpolation::Linear);
+
+        Echo {
+            left: delay_line.clone(),
+            right: delay_line,
+            damping_state: (0.0, 0.0),
+            feedback,
+            damping: 0.0,
+            wet: 0.5,
+            dry: 1.0,
+            ping_pong: false,
+        }
+    }
+
+    /// Current delay in seconds.
+    pub fn delay(&self) -> f64 {
+        self.left.delay_samples() / self.left.sample_rate as f64
+    }
+
+    /// Sets the delay in seconds, up to the `max_delay` given on creation.
+    pub fn set_delay(&mut self, delay: f64) {
+        self.left.set_delay(delay);
+        self.right.set_delay(delay);
+    }
+
+    /// Sets the delay to a note value at `bpm` beats per minute.
+    pub fn set_tempo(&mut self, bpm: f64, note: NoteValue) {
+        self.set_delay(note.seconds(bpm));
+    }
+
+    pub fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+        self.damping_state = (0.0, 0.0);
+    }
+
+    fn damp(damping: f64, state: &mut f64, input: f64) -> f64 {
+        *state = input * (1.0 - damping) + *state * damping;
+        *state
+    }
+
+    /// Processes a mono sample.
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let delayed = self.left.read();
+        let damped = Echo::damp(self.damping, &mut self.damping_state.0, delayed);
+        self.left.write(input + self.feedback * damped);
+
+        self.dry * input + self.wet * delayed
+    }
+
+    /// Processes a stereo sample.
+    pub fn tick_stereo(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let (left, right) = input;
+        let delayed_left = self.left.read();
+        let delayed_right = self.right.read();
+        let damped_left = Echo::damp(self.damping, &mut self.damping_state.0, delayed_left);
+        let damped_right = Echo::damp(self.damping, &mut self.damping_state.1, delayed_right);
+
+        if self.ping_pong {
+            self.left
+                .write((left + right) / 2.0 + self.feedback * damped_right);
+            self.right.write(self.feedback * damped_left);
+        } else {
+            self.left.write(left + self.feedback * damped_left);
+            self.right.write(right + self.feedback * damped_right);
+        }
+
+        (
+            self.dry * left + self.wet * delayed_left,
+            self.dry * right + self.wet * delayed_right,
+        )
+    }
+}
+
+impl Processor for Echo {
+    fn tick(&mut self, input: f64) -> f64 {
+        Echo::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Echo::reset(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        decay_tail(self.left.delay_samples().ceil(), self.feedback)
+    }
+}
+
+/// A tap of a `MultiTapDelay`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tap {
+    /// Delay in seconds
+    pub delay: f64,
+    pub gain: f64,
+    /// Stereo position from -1.0 (left) to 1.0 (right)
+    pub pan: f64,
+}
+
+impl Tap {
+    pub fn new(delay: f64, gain: f64, pan: f64) -> Tap {
+        Tap { delay, gain, pan }
+    }
+}
+
+/// A stateful delay with several taps read from one delay line, each with its own gain and
+/// stereo position. The longest tap is fed back into the delay line.
+///
+/// ```
+/// use synthrs::delay::{MultiTapDelay, NoteValue, Tap};
+///
+/// let bpm = 120.0;
+/// let mut delay = MultiTapDelay::new(44_100, 2.0, 0.3);
+/// delay.taps.push(Tap::new(NoteValue::Eighth.seconds(bpm), 0.6, -0.5));
+/// delay.taps.push(Tap::new(NoteValue::Quarter.seconds(bpm), 0.4, 0.5));
+///
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+/// let stereo: Vec<(f64, f64)> = samples
+///     .into_iter()
+///     .map(|sample| delay.tick_stereo(sample))
+///     .collect();
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultiTapDelay {
+    delay_line: FractionalDelayLine,
+    pub taps: Vec<Tap>,
+    /// Amount of the longest tap fed back into the delay
+    pub feedback: f64,
+    /// Wet (delayed) signal multiplier, 1.0 by default
+    pub wet: f64,
+    /// Dry (original) signal multiplier, 1.0 by default
+    pub dry: f64,
+}
+
+impl MultiTapDelay {
+    /// Creates a new multi-tap delay with no taps, holding up to `max_delay` seconds.
+    pub fn new(sample_rate: usize, max_delay: f64, feedback: f64) -> MultiTapDelay {
+        MultiTapDelay {
+            delay_line: FractionalDelayLine::new(max_delay, sample_rate, Interpolation::Linear),
+            taps: Vec::new(),
+            feedback,
+            wet: 1.0,
+            dry: 1.0,
+        }
+    }
+
+    // Sums the taps in mono, left and right, along with the longest tap for feedback
+    fn read_taps(&self) -> (f64, f64, f64, f64) {
+        let sample_rate = self.delay_line.sample_rate as f64;
+        let mut mono = 0.0;
+        let mut left = 0.0;
+        let mut right = 0.0;
+        let mut longest = (0.0, 0.0);
+
+        for tap in self.taps.iter() {
+            let delayed = self.delay_line.read_at(tap.delay * sample_rate);
+            let output = tap.gain * delayed;
+            let (left_gain, right_gain) = pan_gains(tap.pan);
+            mono += output;
+            left += output * left_gain;
+            right += output * right_gain;
+
+            if tap.delay >= longest.0 {
+                longest = (tap.delay, delayed);
+            }
+        }
+
+        (mono, left, right, longest.1)
+    }
+
+    /// Processes a sample, mixing all taps to mono regardless of pan.
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let (mono, _, _, longest) = self.read_taps();
+        self.delay_line.write(input + self.feedback * longest);
+
+        self.dry * input + self.wet * mono
+    }
+
+    /// Processes a mono sample into a stereo pair, panning each tap.
+    pub fn tick_stereo(&mut self, input: f64) -> (f64, f64) {
+        let (_, left, right, longest) = self.read_taps();
+        self.delay_line.write(input + self.feedback * longest);
+
+        let dry = self.dry * input * (PI / 4.0).cos();
+        (dry + self.wet * left, dry + self.wet * right)
+    }
+}
+
+impl Processor for MultiTapDelay {
+    fn tick(&mut self, input: f64) -> f64 {
+        MultiTapDelay::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+    }
+
+    fn tail_length(&self) -> usize {
+        let longest = self.taps.iter().fold(0.0f64, |max, tap| tap.delay.max(max));
+        decay_tail(
+            (longest * self.delay_line.sample_rate as f64).ceil() + 1.0,
+            self.feedback,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn impulse(i: usize) -> f64 {
+        if i == 0 {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    #[test]
+    fn test_note_values() {
+        assert_eq!(NoteValue::Quarter.seconds(60.0), 1.0);
+        assert_eq!(NoteValue::Whole.seconds(120.0), 2.0);
+        assert_eq!(NoteValue::Quarter.dotted_seconds(60.0), 1.5);
+        assert!((NoteValue::Quarter.triplet_seconds(60.0) - 2.0 / 3.0).abs() < 1e-12);
+
+        let mut echo = Echo::new(1000, 1.0, 0.5);
+        echo.set_tempo(120.0, NoteValue::Sixteenth);
+        assert!((echo.delay() - 0.125).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_echo_repeats() {
+        let mut echo = Echo::new(1000, 0.01, 0.5);
+        echo.wet = 1.0;
+        echo.dry = 0.0;
+
+        let output: Vec<f64> = (0..40).map(|i| echo.tick(impulse(i))).collect();
+        for (i, sample) in output.iter().enumerate() {
+            let expected = match i {
+                10 => 1.0,
+                20 => 0.5,
+                30 => 0.25,
+                _ => 0.0,
+            };
+            assert!((sample - expected).abs() < 1e-9);
+        }
+
+        echo.reset();
+        assert!((0..40).all(|_| echo.tick(0.0) == 0.0));
+    }
+
+    #[test]
+    fn test_echo_damping() {
+        // An alternating signal is at Nyquist, and is damped on every repeat
+        let repeat_energy = |damping: f64| {
+            let mut echo = Echo::new(1000, 0.01, 0.9);
+            echo.damping = damping;
+            echo.dry = 0.0;
+            (0..100)
+                .map(|i| echo.tick(if i < 10 { (-1.0f64).powi(i) } else { 0.0 }))
+                .skip(20)
+                .map(|x| x * x)
+                .sum::<f64>()
+        };
+
+        assert!(repeat_energy(0.5) < repeat_energy(0.0) * 0.1);
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let mut echo = Echo::new(1000, 0.01, 0.5);
+        echo.ping_pong = true;
+        echo.wet = 1.0;
+        echo.dry = 0.0;
+
+        let output: Vec<(f64, f64)> = (0..40)
+            .map(|i| echo.tick_stereo((impulse(i), 0.0)))
+            .collect();
+        assert!((output[10].0 - 0.5).abs() < 1e-9);
+        assert!(output[10].1.abs() < 1e-9);
+        assert!(output[20].0.abs() < 1e-9);
+        assert!((output[20].1 - 0.25).abs() < 1e-9);
+        assert!((output[30].0 - 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_multi_tap_delay() {
+        let mut delay = MultiTapDelay::new(1000, 0.05, 0.5);
+        delay.dry = 0.0;
+        delay.taps.push(Tap::new(0.005, 0.8, -1.0));
+        delay.taps.push(Tap::new(0.012, 0.4, 1.0));
+
+        let output: Vec<f64> = (0..30).map(|i| delay.tick(impulse(i))).collect();
+        assert!((output[5] - 0.8).abs() < 1e-9);
+        assert!((output[12] - 0.4).abs() < 1e-9);
+        // Fed back from the longest tap
+        assert!((output[17] - 0.8 * 0.5).abs() < 1e-9);
+        assert!((output[24] - 0.4 * 0.5).abs() < 1e-9);
+
+        let mut delay = MultiTapDelay::new(1000, 0.05, 0.0);
+        delay.dry = 0.0;
+        delay.taps.push(Tap::new(0.005, 1.0, -1.0));
+        delay.taps.push(Tap::new(0.012, 1.0, 1.0));
+
+        let output: Vec<(f64, f64)> = (0..30).map(|i| delay.tick_stereo(impulse(i))).collect();
+        assert!((output[5].0 - 1.0).abs() < 1e-9 && output[5].1.abs() < 1e-9);
+        assert!(output[12].0.abs() < 1e-9 && (output[12].1 - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,563 @@
+//! Waveshaping distortion and bitcrushing.
+//!
+//! A `Waveshaper` maps each sample through a nonlinear transfer curve, which adds harmonics.
+//! Harmonics above the Nyquist frequency fold back down as inharmonic aliasing, so `Distortion`
+//! can run the waveshaper at 2x, 4x or 8x the sample rate using an `Oversampler`.
+//!
+//! ```
+//! use synthrs::distortion::{Distortion, Oversampling, Waveshaper};
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sawtooth_wave;
+//!
+//! let samples = make_samples(0.5, 44_100, sawtooth_wave(220.0));
+//!
+//! // Drive the signal 8x into a tanh soft clipper, at 4x oversampling
+//! let mut distortion = Distortion::new(Waveshaper::Tanh, 8.0, Oversampling::X4);
+//!
+//! let distorted: Vec<f64> = samples
+//!     .into_iter()
+//!     .map(|sample| distortion.tick(sample))
+//!     .collect();
+//! ```
+//!
+//! `Bitcrusher` reduces bit depth and sample rate for lo-fi and chiptune sounds.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use crate::filter::{group_delay, kaiser_lowpass_filter};
+use crate::processor::Processor;
+
+/// Nonlinear transfer curves.
+#[derive(Clone, Debug)]
+pub enum Waveshaper {
+    /// Hyperbolic tangent soft clipping
+    Tanh,
+    /// Arctangent soft clipping, scaled to [-1, 1]
+    Arctan,
+    /// Cubic soft clipping: `1.5x - 0.5x^3`, hard clipped outside [-1, 1]
+    Cubic,
+    /// Hard clipping to [-1, 1]
+    HardClip,
+    /// Folds the signal back down whenever it exceeds the given threshold
+    Foldback(f64),
+    /// Asymmetric, tube-style soft clipping with the given bias. The bias makes positive and
+    /// negative half-cycles clip differently, adding even harmonics.
+    Tube(f64),
+    /// Sum of Chebyshev polynomials of the first kind, with the given amplitude for each order
+    /// starting from T0. Driving `Tk` with a full-scale sine produces exactly the kth harmonic.
+    Chebyshev(Vec<f64>),
+    /// User-supplied transfer curve, sampled evenly over [-1, 1] and linearly interpolated.
+    /// Inputs outside [-1, 1] are clamped.
+    Curve(Vec<f64>),
+    /// User-supplied transfer function
+    Function(fn(f64) -> f64),
+}
+
+impl Waveshaper {
+    /// Maps a sample through the transfer curve.
+    pub fn shape(&self, x: f64) -> f64 {
+        match self {
+            Waveshaper::Tanh => x.tanh(),
+            Waveshaper::Arctan => x.atan() * 2.0 / PI,
+            Waveshaper::Cubic => {
+                let x = x.clamp(-1.0, 1.0);
+                1.5 * x - 0.5 * x * x * x
+            }
+            Waveshaper::HardClip => x.clamp(-1.0, 1.0),
+            Waveshaper::Foldback(threshold) => {
+                let threshold = threshold.abs().max(f64::EPSILON);
+                if x > threshold || x < -threshold {
+                    ((x - threshold).rem_euclid(4.0 * threshold) - 2.0 * threshold).abs()
+                        - threshold
+                } else {
+                    x
+                }
+            }
+            Waveshaper::Tube(bias) => (x + bias).tanh() - bias.tanh(),
+            Waveshaper::Chebyshev(amplitudes) => {
+                let x = x.clamp(-1.0, 1.0);
+                // T0 = 1, T1 = x, Tk+1 = 2xTk - Tk-1
+                let mut previous = 1.0;
+                let mut current = x;
+                let mut sum = 0.0;
+                for (order, amplitude) in amplitudes.iter().enumerate() {
+                    let t = match order {
+                        0 => 1.0,
+                        1 => x,
+                        _ => {
+                            let next = 2.0 * x * current - previous;
+                            previous = current;
+                            current = next;
+                            next
+                        }
+                    };
+                    sum += amplitude * t;
+                }
+                sum
+            }
+            Waveshaper::Curve(table) => match table.len() {
+                0 => 0.0,
+                1 => table[0],
+                len => {
+                    let position = (x.clamp(-1.0, 1.0) + 1.0) / 2.0 * (len - 1) as f64;
+                    let index = (position.floor() as usize).min(len - 2);
+                    let fraction = position - index as f64;
+                    table[index] * (1.0 - fraction) + table[index + 1] * fraction
+                }
+            },
+            Waveshaper::Function(f) => f(x),
+        }
+    }
+}
+
+/// Oversampling factor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Oversampling {
+    None,
+    X2,
+    X4,
+    X8,
+}
+
+impl Oversampling {
+    pub fn factor(self) -> usize {
+        match self {
+            Oversampling::None => 1,
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+            Oversampling::X8 => 8,
+        }
+    }
+}
+
+/// Polyphase FIR upsampler and downsampler.
+///
+/// Each input sample is upsampled into `factor` samples with `upsample`, processed at the
+/// higher rate, and brought back down to one sample with `downsample`. The anti-imaging and
+/// anti-aliasing filters pass up to 0.45 of the original sample rate, and reject 90dB above the
+/// original Nyquist frequency.
+///
+/// ```
+/// use synthrs::distortion::{Oversampler, Oversampling};
+///
+/// let mut oversampler = Oversampler::new(Oversampling::X4);
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+///
+/// let processed: Vec<f64> = samples
+///     .into_iter()
+///     .map(|sample| {
+///         let upsampled: Vec<f64> = oversampler.upsample(sample).iter().map(|x| x.tanh()).collect();
+///         oversampler.downsample(&upsampled)
+///     })
+///     .collect();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Oversampler {
+    factor: usize,
+    // Kernel split into `factor` phases: phases[p][k] = kernel[k * factor + p]
+    phases: Vec<Vec<f64>>,
+    kernel: Vec<f64>,
+    input_history: VecDeque<f64>,
+    output_history: VecDeque<f64>,
+    upsampled: Vec<f64>,
+}
+
+impl Oversampler {
+    pub fn new(oversampling: Oversampling) -> Oversampler {
+        let factor = oversampling.factor();
+
+        let mut kernel = if factor == 1 {
+            vec![1.0]
+        } else {
+            let relative = 1.0 / factor as f64;
+            kaiser_lowpass_filter(0.475 * relative, 0.05 * relative, 0.01, 90.0)
+        };
+        // Pad so the combined delay of both filters is a whole number of original samples
+        while (kernel.len() - 1) % factor != 0 {
+            kernel.insert(0, 0.0);
+            kernel.push(0.0);
+        }
+
+        let phases: Vec<Vec<f64>> = (0..factor)
+            .map(|p| {
+                kernel
+                    .iter()
+                    .skip(p)
+                    .step_by(factor)
+                    .map(|h| h * factor as f64)
+                    .collect()
+            })
+            .collect();
+        let phase_length = phases.iter().map(|p| p.len()).max().unwrap_or(1);
+
+        Oversampler {
+            factor,
+            input_history: vec![0.0; phase_length].into_iter().collect(),
+            output_history: vec![0.0; kernel.len() + factor - 1].into_iter().collect(),
+            phases,
+            kernel,
+            upsampled: vec![0.0; factor],
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Latency of an upsample/downsample round trip, in samples at the original rate.
+    pub fn latency(&self) -> usize {
+        2 * group_delay(&self.kernel) / self.factor
+    }
+
+    /// Upsamples one sample into `factor` samples.
+    pub fn upsample(&mut self, input: f64) -> &[f64] {
+        self.input_history.pop_back();
+        self.input_history.push_front(input);
+
+        for (output, phase) in self.upsampled.iter_mut().zip(self.phases.iter()) {
+            *output = phase
+                .iter()
+                .zip(self.input_history.iter())
+                .fold(0.0, |acc, (h, x)| acc + h * x);
+        }
+
+        &self.upsampled
+    }
+
+    /// Clears the filter histories.
+    pub fn reset(&mut self) {
+        self.input_history.iter_mut().for_each(|x| *x = 0.0);
+        self.output_history.iter_mut().for_each(|x| *x = 0.0);
+    }
+
+    /// Downsamples `factor` samples into one sample.
+    pub fn downsample(&mut self, input: &[f64]) -> f64 {
+        for &sample in input {
+            self.output_history.pop_back();
+            self.output_history.push_front(sample);
+        }
+
+        // Only the output that is kept is computed, aligned with the first of the input samples
+        self.kernel
+            .iter()
+            .zip(self.output_history.iter().skip(self.factor - 1))
+            .fold(0.0, |acc, (h, x)| acc + h * x)
+    }
+}
+
+/// A stateful waveshaping distortion, optionally oversampled.
+#[derive(Clone, Debug)]
+pub struct Distortion {
+    oversampler: Oversampler,
+    shaped: Vec<f64>,
+    pub waveshaper: Waveshaper,
+    /// Gain applied before the waveshaper
+    pub drive: f64,
+    /// Gain applied after the waveshaper
+    pub output_gain: f64,
+}
+
+impl Distortion {
+    pub fn new(waveshaper: Waveshaper, drive: f64, oversampling: Oversampling) -> Distortion {
+        Distortion {
+            shaped: vec![0.0; oversampling.factor()],
+            oversampler: Oversampler::new(oversampling),
+            waveshaper,
+            drive,
+            output_gain: 1.0,
+        }
+    }
+
+    /// Latency in samples introduced by oversampling.
+    pub fn latency(&self) -> usize {
+        self.oversampler.latency()
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        if self.oversampler.factor() == 1 {
+            return self.output_gain * self.waveshaper.shape(self.drive * input);
+        }
+
+        let upsampled = self.oversampler.upsample(input);
+        for (shaped, &sample) in self.shaped.iter_mut().zip(upsampled.iter()) {
+            *shaped = self.waveshaper.shape(self.drive * sample);
+        }
+
+        self.output_gain * self.oversampler.downsample(&self.shaped)
+    }
+}
+
+impl Processor for Distortion {
+    fn tick(&mut self, input: f64) -> f64 {
+        Distortion::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.oversampler.reset();
+    }
+
+    fn latency(&self) -> usize {
+        Distortion::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        Distortion::latency(self) + 1
+    }
+}
+
+/// Lo-fi bit depth and sample rate reduction.
+///
+/// Samples are quantised to steps of `2^-(bits - 1)`, so `bits` need not be an integer: 3.5
+/// bits gives about 11 levels between -1.0 and 1.0. The quantised signal is held for
+/// `sample_rate / rate` samples to emulate a lower sample rate without any anti-aliasing.
+///
+/// ```
+/// use synthrs::distortion::Bitcrusher;
+///
+/// // 4-bit, 8kHz
+/// let mut bitcrusher = Bitcrusher::new(44_100, 4.0, 8_000.0);
+/// let samples: Vec<f64> = vec![0.1, 0.2, 0.3, 0.4];
+///
+/// let crushed = samples.into_iter().map(|sample| bitcrusher.tick(sample));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Bitcrusher {
+    sample_rate: usize,
+    phase: f64,
+    held: f64,
+    /// Bit depth, including the sign bit
+    pub bits: f64,
+    /// Rate in Hz at which samples are taken and held
+    pub rate: f64,
+    /// Adds triangular dither of one quantisation step before quantising
+    pub dither: bool,
+    /// Wet/dry mix, from 0.0 (dry) to 1.0 (wet)
+    pub mix: f64,
+}
+
+impl Bitcrusher {
+    pub fn new(sample_rate: usize, bits: f64, rate: f64) -> Bitcrusher {
+        Bitcrusher {
+            sample_rate,
+            phase: 1.0,
+            held: 0.0,
+            bits,
+            rate,
+            dither: false,
+            mix: 1.0,
+        }
+    }
+
+    /// Quantises a sample to the current bit depth, with dither if enabled.
+    pub fn crush(&self, input: f64) -> f64 {
+        let step = 2.0f64.powf(1.0 - self.bits.max(1.0));
+        let dither = if self.dither {
+            (rand::random::<f64>() - rand::random::<f64>()) * step
+        } else {
+            0.0
+        };
+
+        (((input + dither) / step).round() * step).clamp(-1.0, 1.0)
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 1.0;
+        self.held = 0.0;
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        if self.phase >= 1.0 {
+            self.phase -= self.phase.floor();
+            self.held = self.crush(input);
+        }
+        self.phase += self.rate / self.sample_rate as f64;
+
+        self.mix * self.held + (1.0 - self.mix) * input
+    }
+}
+
+impl Processor for Bitcrusher {
+    fn tick(&mut self, input: f64) -> f64 {
+        Bitcrusher::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Bitcrusher::reset(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        if self.rate > 0.0 {
+            (self.sample_rate as f64 / self.rate).ceil() as usize
+        } else {
+            usize::MAX
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fft::real_fft;
+    use crate::filter::Window;
+    use crate::wave::sine_wave;
+
+    const SIZE: usize = 4096;
+
+    // Power spectrum of a windowed, bin-centred test signal
+    fn spectrum(samples: &[f64]) -> Vec<f64> {
+        real_fft(&Window::BlackmanHarris.apply(samples), SIZE)
+            .iter()
+            .take(SIZE / 2)
+            .map(|bin| bin.norm_sqr())
+            .collect()
+    }
+
+    fn band_power(spectrum: &[f64], bin: usize) -> f64 {
+        spectrum[bin.saturating_sub(4)..(bin + 5).min(spectrum.len())]
+            .iter()
+            .sum()
+    }
+
+    fn render(distortion: &mut Distortion, frequency: f64) -> Vec<f64> {
+        let sine = sine_wave(frequency);
+        let output: Vec<f64> = (0..SIZE + 2000)
+            .map(|i| distortion.tick(0.9 * sine(i as f64 / 44_100.0)))
+            .collect();
+        output[2000..].to_vec()
+    }
+
+    #[test]
+    fn test_waveshapers() {
+        assert!((Waveshaper::HardClip.shape(2.0) - 1.0).abs() < 1e-12);
+        assert!((Waveshaper::Cubic.shape(1.0) - 1.0).abs() < 1e-12);
+        assert!((Waveshaper::Arctan.shape(1e9) - 1.0).abs() < 1e-6);
+        assert!((Waveshaper::Foldback(0.5).shape(0.7) - 0.3).abs() < 1e-12);
+        assert!((Waveshaper::Foldback(0.5).shape(-0.7) + 0.3).abs() < 1e-12);
+        assert!(Waveshaper::Tube(0.3).shape(0.0).abs() < 1e-12);
+
+        // T3(x) = 4x^3 - 3x
+        let chebyshev = Waveshaper::Chebyshev(vec![0.0, 0.0, 0.0, 1.0]);
+        assert!((chebyshev.shape(0.5) - (4.0 * 0.125 - 1.5)).abs() < 1e-12);
+
+        let curve = Waveshaper::Curve(vec![-1.0, 0.0, 0.5]);
+        assert!((curve.shape(0.5) - 0.25).abs() < 1e-12);
+        assert!((curve.shape(-2.0) + 1.0).abs() < 1e-12);
+
+        let function = Waveshaper::Function(|x| x * 2.0);
+        assert!((function.shape(0.25) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_harmonic_content() {
+        // About 1kHz, centred on an FFT bin
+        let bin = 93;
+        let frequency = bin as f64 * 44_100.0 / SIZE as f64;
+
+        // Symmetric clipping only adds odd harmonics
+        let mut tanh = Distortion::new(Waveshaper::Tanh, 4.0, Oversampling::X2);
+        let spectrum_tanh = spectrum(&render(&mut tanh, frequency));
+        let fundamental = band_power(&spectrum_tanh, bin);
+        assert!(band_power(&spectrum_tanh, 3 * bin) > fundamental * 1e-3);
+        assert!(band_power(&spectrum_tanh, 2 * bin) < fundamental * 1e-8);
+
+        // Asymmetric clipping adds even harmonics too
+        let mut tube = Distortion::new(Waveshaper::Tube(0.5), 4.0, Oversampling::X2);
+        let spectrum_tube = spectrum(&render(&mut tube, frequency));
+        let fundamental = band_power(&spectrum_tube, bin);
+        assert!(band_power(&spectrum_tube, 2 * bin) > fundamental * 1e-3);
+
+        // Chebyshev polynomials add exactly the harmonics asked for
+        let mut chebyshev = Distortion::new(
+            Waveshaper::Chebyshev(vec![0.0, 0.0, 0.0, 1.0]),
+            1.0 / 0.9,
+            Oversampling::X4,
+        );
+        let spectrum_chebyshev = spectrum(&render(&mut chebyshev, frequency));
+        let third = band_power(&spectrum_chebyshev, 3 * bin);
+        assert!(band_power(&spectrum_chebyshev, bin) < third * 1e-6);
+        assert!(band_power(&spectrum_chebyshev, 5 * bin) < third * 1e-6);
+    }
+
+    #[test]
+    fn test_oversampling_reduces_aliasing() {
+        // A high fundamental, so that most harmonics are above Nyquist
+        let bin = 465;
+        let frequency = bin as f64 * 44_100.0 / SIZE as f64;
+
+        let aliasing = |oversampling: Oversampling| -> f64 {
+            let mut distortion = Distortion::new(Waveshaper::Tanh, 4.0, oversampling);
+            let spectrum = spectrum(&render(&mut distortion, frequency));
+            let total: f64 = spectrum.iter().sum();
+            let harmonics: f64 = (1..=4).map(|h| band_power(&spectrum, h * bin)).sum();
+            (total - harmonics) / total
+        };
+
+        let without = aliasing(Oversampling::None);
+        let with = aliasing(Oversampling::X8);
+        assert!(without > 1e-3);
+        assert!(with < without * 1e-3);
+    }
+
+    #[test]
+    fn test_oversampler_is_transparent_in_the_passband() {
+        let mut oversampler = Oversampler::new(Oversampling::X4);
+        let latency = oversampler.latency();
+        let sine = sine_wave(1000.0);
+
+        let output: Vec<f64> = (0..2000)
+            .map(|i| {
+                let upsampled = oversampler.upsample(sine(i as f64 / 44_100.0)).to_vec();
+                oversampler.downsample(&upsampled)
+            })
+            .collect();
+
+        for (i, sample) in output.iter().enumerate().skip(latency + 200) {
+            let expected = sine((i - latency) as f64 / 44_100.0);
+            assert!((sample - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_bitcrusher_bit_depth() {
+        let mut bitcrusher = Bitcrusher::new(44_100, 2.0, 44_100.0);
+        let sine = sine_wave(440.0);
+        let mut levels: Vec<f64> = (0..1000)
+            .map(|i| bitcrusher.tick(sine(i as f64 / 44_100.0)))
+            .collect();
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        levels.dedup();
+        assert_eq!(levels, vec![-1.0, -0.5, 0.0, 0.5, 1.0]);
+
+        // Fractional bit depths sit between their neighbours
+        let crush = |bits: f64| Bitcrusher::new(44_100, bits, 44_100.0).crush(0.35);
+        assert!((crush(3.0) - 0.25).abs() < 1e-12);
+        assert!((crush(3.5) - 2.0f64.powf(-1.5)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bitcrusher_sample_and_hold() {
+        let mut bitcrusher = Bitcrusher::new(44_100, 16.0, 11_025.0);
+        let input: Vec<f64> = (0..12).map(|i| i as f64 / 16.0).collect();
+        let output: Vec<f64> = input.iter().map(|&x| bitcrusher.tick(x)).collect();
+        assert_eq!(
+            output,
+            vec![0.0, 0.0, 0.0, 0.0, 0.25, 0.25, 0.25, 0.25, 0.5, 0.5, 0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn test_bitcrusher_dither_and_mix() {
+        // Dither turns a constant below one step into noise averaging out to the input
+        let mut bitcrusher = Bitcrusher::new(44_100, 4.0, 44_100.0);
+        bitcrusher.dither = true;
+        let output: Vec<f64> = (0..20_000).map(|_| bitcrusher.tick(0.05)).collect();
+        let mean = output.iter().sum::<f64>() / output.len() as f64;
+        assert!((mean - 0.05).abs() < 0.005);
+        assert!(output.iter().any(|&x| x != output[0]));
+
+        let mut dry = Bitcrusher::new(44_100, 1.0, 100.0);
+        dry.mix = 0.0;
+        assert_eq!(dry.tick(0.3), 0.3);
+    }
+}
@@ -0,0 +1,748 @@
+//! Dynamics processors: compressor, limiter, expander and noise gate.
+//!
+//! Unlike `crate::synthesizer::peak_normalize`, these are stateful and work one sample at a
+//! time with `tick`, so they do not need the whole buffer up front. Levels and gains are in dB.
+//!
+//! Each processor can be keyed from a separate sidechain signal with `tick_sidechain`, and
+//! reports its current gain reduction with `gain_reduction_db` for metering.
+//!
+//! ```
+//! use synthrs::dynamics::{Compressor, Limiter};
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sine_wave;
+//!
+//! let samples = make_samples(0.5, 44_100, |t| 2.0 * sine_wave(440.0)(t));
+//!
+//! // -18dB threshold, 4:1 ratio, 6dB knee, 5ms attack, 100ms release
+//! let mut compressor = Compressor::new(44_100, -18.0, 4.0, 6.0, 0.005, 0.1);
+//! compressor.makeup_gain = 6.0;
+//!
+//! // -1dB ceiling, 5ms lookahead, 50ms release
+//! let mut limiter = Limiter::new(44_100, -1.0, 0.005, 0.05);
+//!
+//! let processed: Vec<f64> = samples
+//!     .into_iter()
+//!     .map(|sample| limiter.tick(compressor.tick(sample)))
+//!     .collect();
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::processor::Processor;
+
+// Levels below this are treated as silence to avoid taking the log of zero
+const MIN_LEVEL_DB: f64 = -200.0;
+
+/// Converts a linear amplitude to dB.
+pub fn amplitude_to_db(amplitude: f64) -> f64 {
+    (20.0 * amplitude.abs().log10()).max(MIN_LEVEL_DB)
+}
+
+/// Converts dB to a linear amplitude.
+pub fn db_to_amplitude(db: f64) -> f64 {
+    10.0f64.powf(db / 20.0)
+}
+
+/// One-pole smoothing coefficient reaching ~63% of a step after `time` seconds.
+fn time_coefficient(time: f64, sample_rate: usize) -> f64 {
+    if time <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (time * sample_rate as f64)).exp()
+    }
+}
+
+// Delays the audio path so that gain changes can anticipate transients
+#[derive(Clone, Debug)]
+struct Lookahead {
+    buf: VecDeque<f64>,
+    length: usize,
+}
+
+impl Lookahead {
+    fn new(length: usize) -> Lookahead {
+        Lookahead {
+            buf: vec![0.0; length].into_iter().collect(),
+            length,
+        }
+    }
+
+    fn tick(&mut self, input: f64) -> f64 {
+        if self.length == 0 {
+            return input;
+        }
+        self.buf.push_back(input);
+        self.buf.pop_front().unwrap_or(0.0)
+    }
+
+    fn reset(&mut self) {
+        self.buf.iter_mut().for_each(|x| *x = 0.0);
+    }
+}
+
+// Smooths gain reduction in the log domain, with separate coefficients for increasing and
+// decreasing reduction
+#[derive(Clone, Debug)]
+struct GainSmoother {
+    increasing_coefficient: f64,
+    decreasing_coefficient: f64,
+    gain_reduction: f64,
+}
+
+impl GainSmoother {
+    fn tick(&mut self, target: f64) -> f64 {
+        let coefficient = if target > self.gain_reduction {
+            self.increasing_coefficient
+        } else {
+            self.decreasing_coefficient
+        };
+        self.gain_reduction = coefficient * self.gain_reduction + (1.0 - coefficient) * target;
+        self.gain_reduction
+    }
+}
+
+/// Static gain curve of a downward compressor, returning the output level for an input level.
+/// `knee` is the width of the soft knee in dB, centred on the `threshold`.
+pub fn compressor_curve(level: f64, threshold: f64, ratio: f64, knee: f64) -> f64 {
+    let overshoot = level - threshold;
+
+    if 2.0 * overshoot < -knee {
+        level
+    } else if knee > 0.0 && 2.0 * overshoot.abs() <= knee {
+        level + (1.0 / ratio - 1.0) * (overshoot + knee / 2.0).powi(2) / (2.0 * knee)
+    } else {
+        threshold + overshoot / ratio
+    }
+}
+
+/// Static gain curve of a downward expander, returning the output level for an input level.
+/// `knee` is the width of the soft knee in dB, centred on the `threshold`.
+pub fn expander_curve(level: f64, threshold: f64, ratio: f64, knee: f64) -> f64 {
+    let overshoot = level - threshold;
+
+    if 2.0 * overshoot > knee {
+        level
+    } else if knee > 0.0 && 2.0 * overshoot.abs() <= knee {
+        level - (ratio - 1.0) * (overshoot - knee / 2.0).powi(2) / (2.0 * knee)
+    } else {
+        threshold + overshoot * ratio
+    }
+}
+
+/// A stateful feed-forward compressor. Levels above the threshold are reduced by the ratio.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    smoother: GainSmoother,
+    lookahead: Lookahead,
+    sample_rate: usize,
+    /// Threshold in dB
+    pub threshold: f64,
+    /// Compression ratio, eg. 4.0 for 4:1
+    pub ratio: f64,
+    /// Soft knee width in dB
+    pub knee: f64,
+    /// Gain applied after compression, in dB
+    pub makeup_gain: f64,
+}
+
+impl Compressor {
+    /// Creates a new compressor. `attack` and `release` are in seconds.
+    pub fn new(
+        sample_rate: usize,
+        threshold: f64,
+        ratio: f64,
+        knee: f64,
+        attack: f64,
+        release: f64,
+    ) -> Compressor {
+        Compressor {
+            smoother: GainSmoother {
+                increasing_coefficient: time_coefficient(attack, sample_rate),
+                decreasing_coefficient: time_coefficient(release, sample_rate),
+                gain_reduction: 0.0,
+            },
+            lookahead: Lookahead::new(0),
+            sample_rate,
+            threshold,
+            ratio,
+            knee,
+            makeup_gain: 0.0,
+        }
+    }
+
+    /// Time in seconds for gain reduction to respond to a louder signal.
+    pub fn set_attack(&mut self, attack: f64) {
+        self.smoother.increasing_coefficient = time_coefficient(attack, self.sample_rate);
+    }
+
+    /// Time in seconds for gain reduction to recover once the signal gets quieter.
+    pub fn set_release(&mut self, release: f64) {
+        self.smoother.decreasing_coefficient = time_coefficient(release, self.sample_rate);
+    }
+
+    /// Delays the output by `lookahead` seconds so that gain reduction starts before a
+    /// transient reaches the output. This is also the latency of the compressor.
+    pub fn set_lookahead(&mut self, lookahead: f64) {
+        self.lookahead = Lookahead::new((lookahead * self.sample_rate as f64).round() as usize);
+    }
+
+    /// Latency in samples, introduced by lookahead.
+    pub fn latency(&self) -> usize {
+        self.lookahead.length
+    }
+
+    /// Current gain reduction in dB (positive values are attenuation), excluding makeup gain.
+    pub fn gain_reduction_db(&self) -> f64 {
+        self.smoother.gain_reduction
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        self.tick_sidechain(input, input)
+    }
+
+    /// Compresses `input` based on the level of `sidechain`.
+    pub fn tick_sidechain(&mut self, input: f64, sidechain: f64) -> f64 {
+        let level = amplitude_to_db(sidechain);
+        let target = level - compressor_curve(level, self.threshold, self.ratio, self.knee);
+        let gain_reduction = self.smoother.tick(target);
+
+        self.lookahead.tick(input) * db_to_amplitude(self.makeup_gain - gain_reduction)
+    }
+}
+
+impl Processor for Compressor {
+    fn tick(&mut self, input: f64) -> f64 {
+        Compressor::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.lookahead.reset();
+        self.smoother.gain_reduction = 0.0;
+    }
+
+    fn latency(&self) -> usize {
+        Compressor::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        Compressor::latency(self)
+    }
+}
+
+/// A stateful brickwall lookahead limiter. The output never exceeds the ceiling.
+///
+/// Gain reduction is ramped in over the lookahead time, so the lookahead also acts as the
+/// attack time.
+#[derive(Clone, Debug)]
+pub struct Limiter {
+    lookahead: Lookahead,
+    // Sliding minimum of the required gain over the lookahead window: (index, gain)
+    minimum: VecDeque<(usize, f64)>,
+    // Moving average of the minimum gain over the lookahead window
+    average: VecDeque<f64>,
+    average_sum: f64,
+    window: usize,
+    index: usize,
+    release_coefficient: f64,
+    gain: f64,
+    sample_rate: usize,
+    /// Maximum output level in dB
+    pub ceiling: f64,
+}
+
+impl Limiter {
+    /// Creates a new limiter. `lookahead` and `release` are in seconds.
+    pub fn new(sample_rate: usize, ceiling: f64, lookahead: f64, release: f64) -> Limiter {
+        let lookahead_samples = (lookahead * sample_rate as f64).round() as usize;
+        let window = lookahead_samples + 1;
+
+        Limiter {
+            lookahead: Lookahead::new(lookahead_samples),
+            minimum: VecDeque::with_capacity(window),
+            average: vec![1.0; window].into_iter().collect(),
+            average_sum: window as f64,
+            window,
+            index: 0,
+            release_coefficient: time_coefficient(release, sample_rate),
+            gain: 1.0,
+            sample_rate,
+            ceiling,
+        }
+    }
+
+    /// Time in seconds for gain reduction to recover.
+    pub fn set_release(&mut self, release: f64) {
+        self.release_coefficient = time_coefficient(release, self.sample_rate);
+    }
+
+    /// Latency in samples, introduced by lookahead.
+    pub fn latency(&self) -> usize {
+        self.lookahead.length
+    }
+
+    /// Current gain reduction in dB (positive values are attenuation).
+    pub fn gain_reduction_db(&self) -> f64 {
+        -amplitude_to_db(self.gain)
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        self.tick_sidechain(input, input)
+    }
+
+    /// Limits `input` based on the level of `sidechain`. The gain needed to keep `sidechain`
+    /// under the ceiling is applied to `input` once both have passed through the lookahead.
+    pub fn tick_sidechain(&mut self, input: f64, sidechain: f64) -> f64 {
+        let ceiling = db_to_amplitude(self.ceiling);
+        let required = if sidechain.abs() > ceiling {
+            ceiling / sidechain.abs()
+        } else {
+            1.0
+        };
+
+        // Hold the smallest required gain for the whole lookahead window...
+        while let Some(&(_, gain)) = self.minimum.back() {
+            if gain >= required {
+                self.minimum.pop_back();
+            } else {
+                break;
+            }
+        }
+        self.minimum.push_back((self.index, required));
+        while let Some(&(index, _)) = self.minimum.front() {
+            if index + self.window <= self.index {
+                self.minimum.pop_front();
+            } else {
+                break;
+            }
+        }
+        let held = self.minimum.front().map_or(1.0, |&(_, gain)| gain);
+        self.index += 1;
+
+        // ...and ramp into it over the same window, so the gain has fully reached the required
+        // gain by the time the sample that needs it leaves the lookahead delay
+        self.average.push_back(held);
+        self.average_sum += held - self.average.pop_front().unwrap_or(1.0);
+        if self.index.is_multiple_of(self.window) {
+            // Stop floating-point error in the running sum from accumulating
+            self.average_sum = self.average.iter().sum();
+        }
+        let target = self.average_sum / self.window as f64;
+
+        self.gain = if target < self.gain {
+            target
+        } else {
+            self.release_coefficient * self.gain + (1.0 - self.release_coefficient) * target
+        };
+
+        self.lookahead.tick(input) * self.gain
+    }
+}
+
+impl Processor for Limiter {
+    fn tick(&mut self, input: f64) -> f64 {
+        Limiter::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.lookahead.reset();
+        self.minimum.clear();
+        self.average.iter_mut().for_each(|x| *x = 1.0);
+        self.average_sum = self.window as f64;
+        self.index = 0;
+        self.gain = 1.0;
+    }
+
+    fn latency(&self) -> usize {
+        Limiter::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        Limiter::latency(self)
+    }
+}
+
+/// A stateful downward expander. Levels below the threshold are reduced by the ratio, making
+/// quiet sounds quieter.
+#[derive(Clone, Debug)]
+pub struct Expander {
+    smoother: GainSmoother,
+    lookahead: Lookahead,
+    sample_rate: usize,
+    /// Threshold in dB
+    pub threshold: f64,
+    /// Expansion ratio, eg. 2.0 for 1:2
+    pub ratio: f64,
+    /// Soft knee width in dB
+    pub knee: f64,
+    /// Gain applied after expansion, in dB
+    pub makeup_gain: f64,
+}
+
+impl Expander {
+    /// Creates a new expander. `attack` is the time in seconds for the expander to open when
+    /// the signal rises above the threshold, `release` the time to close again.
+    pub fn new(
+        sample_rate: usize,
+        threshold: f64,
+        ratio: f64,
+        knee: f64,
+        attack: f64,
+        release: f64,
+    ) -> Expander {
+        Expander {
+            smoother: GainSmoother {
+                increasing_coefficient: time_coefficient(release, sample_rate),
+                decreasing_coefficient: time_coefficient(attack, sample_rate),
+                gain_reduction: 0.0,
+            },
+            lookahead: Lookahead::new(0),
+            sample_rate,
+            threshold,
+            ratio,
+            knee,
+            makeup_gain: 0.0,
+        }
+    }
+
+    /// Time in seconds for the expander to open.
+    pub fn set_attack(&mut self, attack: f64) {
+        self.smoother.decreasing_coefficient = time_coefficient(attack, self.sample_rate);
+    }
+
+    /// Time in seconds for the expander to close.
+    pub fn set_release(&mut self, release: f64) {
+        self.smoother.increasing_coefficient = time_coefficient(release, self.sample_rate);
+    }
+
+    /// Delays the output by `lookahead` seconds, so that the expander opens before a transient
+    /// reaches the output.
+    pub fn set_lookahead(&mut self, lookahead: f64) {
+        self.lookahead = Lookahead::new((lookahead * self.sample_rate as f64).round() as usize);
+    }
+
+    /// Latency in samples, introduced by lookahead.
+    pub fn latency(&self) -> usize {
+        self.lookahead.length
+    }
+
+    /// Current gain reduction in dB (positive values are attenuation), excluding makeup gain.
+    pub fn gain_reduction_db(&self) -> f64 {
+        self.smoother.gain_reduction
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        self.tick_sidechain(input, input)
+    }
+
+    /// Expands `input` based on the level of `sidechain`.
+    pub fn tick_sidechain(&mut self, input: f64, sidechain: f64) -> f64 {
+        let level = amplitude_to_db(sidechain);
+        let target = level - expander_curve(level, self.threshold, self.ratio, self.knee);
+        let gain_reduction = self.smoother.tick(target);
+
+        self.lookahead.tick(input) * db_to_amplitude(self.makeup_gain - gain_reduction)
+    }
+}
+
+impl Processor for Expander {
+    fn tick(&mut self, input: f64) -> f64 {
+        Expander::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.lookahead.reset();
+        self.smoother.gain_reduction = 0.0;
+    }
+
+    fn latency(&self) -> usize {
+        Expander::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        Expander::latency(self)
+    }
+}
+
+/// A stateful noise gate. The gate opens when the signal rises above the threshold, and closes
+/// once it falls below `threshold - hysteresis` for longer than the hold time. When closed, the
+/// signal is attenuated by `range` dB.
+#[derive(Clone, Debug)]
+pub struct NoiseGate {
+    smoother: GainSmoother,
+    lookahead: Lookahead,
+    hold_samples: usize,
+    hold_remaining: usize,
+    open: bool,
+    sample_rate: usize,
+    /// Level in dB at which the gate opens
+    pub threshold: f64,
+    /// The gate closes at `threshold - hysteresis` dB
+    pub hysteresis: f64,
+    /// Attenuation in dB when the gate is closed
+    pub range: f64,
+}
+
+impl NoiseGate {
+    /// Creates a new noise gate. `attack`, `hold` and `release` are in seconds.
+    /// The gate fully mutes when closed, with no hysteresis.
+    pub fn new(
+        sample_rate: usize,
+        threshold: f64,
+        attack: f64,
+        hold: f64,
+        release: f64,
+    ) -> NoiseGate {
+        NoiseGate {
+            smoother: GainSmoother {
+                increasing_coefficient: time_coefficient(release, sample_rate),
+                decreasing_coefficient: time_coefficient(attack, sample_rate),
+                gain_reduction: -MIN_LEVEL_DB,
+            },
+            lookahead: Lookahead::new(0),
+            hold_samples: (hold * sample_rate as f64).round() as usize,
+            hold_remaining: 0,
+            open: false,
+            sample_rate,
+            threshold,
+            hysteresis: 0.0,
+            range: -MIN_LEVEL_DB,
+        }
+    }
+
+    /// Time in seconds for the gate to open.
+    pub fn set_attack(&mut self, attack: f64) {
+        self.smoother.decreasing_coefficient = time_coefficient(attack, self.sample_rate);
+    }
+
+    /// Time in seconds the gate stays open after the signal falls below the threshold.
+    pub fn set_hold(&mut self, hold: f64) {
+        self.hold_samples = (hold * self.sample_rate as f64).round() as usize;
+    }
+
+    /// Time in seconds for the gate to close.
+    pub fn set_release(&mut self, release: f64) {
+        self.smoother.increasing_coefficient = time_coefficient(release, self.sample_rate);
+    }
+
+    /// Delays the output by `lookahead` seconds, so that the gate opens before a transient
+    /// reaches the output.
+    pub fn set_lookahead(&mut self, lookahead: f64) {
+        self.lookahead = Lookahead::new((lookahead * self.sample_rate as f64).round() as usize);
+    }
+
+    /// Latency in samples, introduced by lookahead.
+    pub fn latency(&self) -> usize {
+        self.lookahead.length
+    }
+
+    /// Whether the gate is currently open.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Current gain reduction in dB (positive values are attenuation).
+    pub fn gain_reduction_db(&self) -> f64 {
+        self.smoother.gain_reduction
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        self.tick_sidechain(input, input)
+    }
+
+    /// Gates `input` based on the level of `sidechain`.
+    pub fn tick_sidechain(&mut self, input: f64, sidechain: f64) -> f64 {
+        let level = amplitude_to_db(sidechain);
+
+        if level >= self.threshold {
+            self.open = true;
+            self.hold_remaining = self.hold_samples;
+        } else if level < self.threshold - self.hysteresis {
+            if self.hold_remaining > 0 {
+                self.hold_remaining -= 1;
+            } else {
+                self.open = false;
+            }
+        }
+
+        let target = if self.open { 0.0 } else { self.range };
+        let gain_reduction = self.smoother.tick(target);
+
+        self.lookahead.tick(input) * db_to_amplitude(-gain_reduction)
+    }
+}
+
+impl Processor for NoiseGate {
+    fn tick(&mut self, input: f64) -> f64 {
+        NoiseGate::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.lookahead.reset();
+        self.smoother.gain_reduction = self.range;
+        self.hold_remaining = 0;
+        self.open = false;
+    }
+
+    fn latency(&self) -> usize {
+        NoiseGate::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        NoiseGate::latency(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settle<F: FnMut(f64) -> f64>(mut tick: F, input: f64, samples: usize) -> f64 {
+        (0..samples).fold(0.0, |_, _| tick(input))
+    }
+
+    #[test]
+    fn test_gain_curves() {
+        assert!((compressor_curve(-30.0, -20.0, 4.0, 0.0) + 30.0).abs() < 1e-12);
+        assert!((compressor_curve(-4.0, -20.0, 4.0, 0.0) + 16.0).abs() < 1e-12);
+        // At the threshold, a soft knee reduces by (1 / ratio - 1) * knee / 8
+        assert!((compressor_curve(-20.0, -20.0, 4.0, 8.0) + 20.75).abs() < 1e-12);
+        // The soft knee joins the straight sections continuously
+        assert!((compressor_curve(-16.0, -20.0, 4.0, 8.0) + 19.0).abs() < 1e-12);
+
+        assert!((expander_curve(-10.0, -20.0, 2.0, 0.0) + 10.0).abs() < 1e-12);
+        assert!((expander_curve(-30.0, -20.0, 2.0, 0.0) + 40.0).abs() < 1e-12);
+        assert!((expander_curve(-24.0, -20.0, 2.0, 8.0) + 28.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_compressor() {
+        let mut compressor = Compressor::new(44_100, -20.0, 4.0, 0.0, 0.001, 0.1);
+        let input = db_to_amplitude(-4.0);
+        let output = settle(|s| compressor.tick(s), input, 44_100);
+        assert!((amplitude_to_db(output) + 16.0).abs() < 0.01);
+        assert!((compressor.gain_reduction_db() - 12.0).abs() < 0.01);
+
+        compressor.makeup_gain = 6.0;
+        let output = compressor.tick(input);
+        assert!((amplitude_to_db(output) + 10.0).abs() < 0.01);
+
+        // Below the threshold nothing happens once released
+        compressor.makeup_gain = 0.0;
+        let quiet = db_to_amplitude(-30.0);
+        let output = settle(|s| compressor.tick(s), quiet, 44_100);
+        assert!((amplitude_to_db(output) + 30.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_compressor_attack_time() {
+        // 10ms attack: ~63% of the final gain reduction after 441 samples
+        let mut compressor = Compressor::new(44_100, -20.0, 4.0, 0.0, 0.01, 0.1);
+        let input = db_to_amplitude(-4.0);
+        settle(|s| compressor.tick(s), input, 441);
+        assert!((compressor.gain_reduction_db() - 12.0 * (1.0 - (-1.0f64).exp())).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_compressor_sidechain_and_lookahead() {
+        let mut compressor = Compressor::new(1000, -20.0, 1000.0, 0.0, 0.0, 0.0);
+        compressor.set_lookahead(0.005);
+        assert_eq!(compressor.latency(), 5);
+
+        // A loud sidechain ducks a quiet input, and the output is delayed by the lookahead
+        let outputs: Vec<f64> = (0..10)
+            .map(|i| {
+                let sidechain = if i >= 5 { 1.0 } else { 0.0 };
+                compressor.tick_sidechain(0.01 * (i + 1) as f64, sidechain)
+            })
+            .collect();
+        assert!(outputs[4].abs() < 1e-12);
+        assert!((outputs[5] - 0.01 * db_to_amplitude(-19.98)).abs() < 1e-9);
+        assert!((outputs[9] - 0.05 * db_to_amplitude(-19.98)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_limiter_never_exceeds_ceiling() {
+        let mut limiter = Limiter::new(44_100, -6.0, 0.002, 0.05);
+        let ceiling = db_to_amplitude(-6.0);
+        let latency = limiter.latency();
+        assert_eq!(latency, 88);
+
+        let input: Vec<f64> = (0..44_100)
+            .map(|i| {
+                let t = i as f64 / 44_100.0;
+                let burst = if (i / 4000) % 2 == 0 { 4.0 } else { 0.2 };
+                burst * (2.0 * std::f64::consts::PI * 220.0 * t).sin()
+            })
+            .collect();
+        let output: Vec<f64> = input.iter().map(|&s| limiter.tick(s)).collect();
+
+        assert!(output.iter().all(|s| s.abs() <= ceiling + 1e-12));
+
+        // Gain is reduced before the loud part arrives, rather than clipping it
+        for (i, sample) in output.iter().enumerate().skip(latency) {
+            let original = input[i - latency];
+            if original.abs() > ceiling {
+                assert!(sample.abs() < original.abs());
+            }
+        }
+
+        // Quiet passages recover towards unity gain
+        settle(|s| limiter.tick(s), 0.1, 22_050);
+        assert!(limiter.gain_reduction_db() < 0.01);
+    }
+
+    #[test]
+    fn test_limiter_sidechain() {
+        let mut limiter = Limiter::new(1000, -6.0, 0.004, 0.0);
+        let ceiling = db_to_amplitude(-6.0);
+        assert_eq!(limiter.latency(), 4);
+
+        // A loud sidechain ducks a quiet input in time for the delayed peak
+        let outputs: Vec<f64> = (0..10)
+            .map(|i| {
+                let sidechain = if i == 5 { 2.0 } else { 0.0 };
+                limiter.tick_sidechain(0.1, sidechain)
+            })
+            .collect();
+        assert!((outputs[4] - 0.1).abs() < 1e-12);
+        assert!((outputs[9] - 0.1 * ceiling / 2.0).abs() < 1e-12);
+
+        // A quiet sidechain leaves a loud input untouched
+        let mut limiter = Limiter::new(1000, -6.0, 0.004, 0.0);
+        let output = settle(|s| limiter.tick_sidechain(s, 0.0), 2.0, 100);
+        assert!((output - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_expander() {
+        let mut expander = Expander::new(44_100, -20.0, 2.0, 0.0, 0.001, 0.001);
+        let loud = db_to_amplitude(-10.0);
+        let output = settle(|s| expander.tick(s), loud, 4410);
+        assert!((output - loud).abs() < 1e-6);
+
+        let quiet = db_to_amplitude(-30.0);
+        let output = settle(|s| expander.tick(s), quiet, 4410);
+        assert!((amplitude_to_db(output) + 40.0).abs() < 0.01);
+        assert!((expander.gain_reduction_db() - 10.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_noise_gate() {
+        let mut gate = NoiseGate::new(1000, -40.0, 0.0, 0.01, 0.0);
+        gate.range = 60.0;
+
+        assert!((gate.tick(0.001) - 0.000_001).abs() < 1e-12);
+        assert!(!gate.is_open());
+
+        assert!((gate.tick(0.5) - 0.5).abs() < 1e-12);
+        assert!(gate.is_open());
+
+        // Held open for 10 samples after the signal drops
+        for _ in 0..10 {
+            assert!((gate.tick(0.001) - 0.001).abs() < 1e-12);
+        }
+        assert!((gate.tick(0.001) - 0.000_001).abs() < 1e-12);
+        assert!(!gate.is_open());
+        assert!((gate.gain_reduction_db() - 60.0).abs() < 1e-9);
+    }
+}
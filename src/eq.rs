@@ -0,0 +1,825 @@
+//! Parametric and graphic equalisers built from biquad sections.
+//!
+//! Biquad coefficients follow the Audio EQ Cookbook by Robert Bristow-Johnson:
+//! https://www.w3.org/TR/audio-eq-cookbook/
+//!
+//! Band frequencies are given in hertz. Changes to a band's frequency, gain and Q are smoothed
+//! over a few milliseconds so they don't click.
+//!
+//! ```
+//! use synthrs::eq::{BandType, EqBand, ParametricEq, Slope};
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sawtooth_wave;
+//!
+//! let samples = make_samples(0.5, 44_100, sawtooth_wave(220.0));
+//!
+//! let mut eq = ParametricEq::new(44_100);
+//! // Cut rumble below 80Hz at 24dB/octave
+//! eq.add_band(EqBand::new(BandType::HighPass(Slope::Db24), 80.0, 0.0, 0.707));
+//! // Boost 3dB around 2kHz
+//! let presence = eq.add_band(EqBand::new(BandType::Peaking, 2000.0, 3.0, 1.0));
+//!
+//! let mut equalised: Vec<f64> = Vec::new();
+//! for (i, sample) in samples.into_iter().enumerate() {
+//!     if i == 10_000 {
+//!         // Smoothly cut instead
+//!         eq.set_gain(presence, -3.0);
+//!     }
+//!     equalised.push(eq.tick(sample));
+//! }
+//!
+//! assert!((eq.magnitude_db_at(2000.0) + 3.0).abs() < 0.1);
+//! ```
+
+use std::f64::consts::PI;
+
+use num::complex::Complex;
+
+use crate::analysis::{iir_response, iir_response_at, FrequencyResponse};
+use crate::processor::{decay_tail, Processor};
+
+/// Normalised biquad coefficients, with `a0` equal to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl BiquadCoefficients {
+    fn normalised(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> BiquadCoefficients {
+        BiquadCoefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    // Frequency kept between 1Hz and just below Nyquist
+    fn clamp_frequency(frequency: f64, sample_rate: usize) -> f64 {
+        frequency.clamp(1.0, 0.499 * sample_rate as f64)
+    }
+
+    fn clamp_q(q: f64) -> f64 {
+        q.max(0.01)
+    }
+
+    // Angular frequency and alpha
+    fn omega(frequency: f64, q: f64, sample_rate: usize) -> (f64, f64) {
+        let frequency = BiquadCoefficients::clamp_frequency(frequency, sample_rate);
+        let w0 = 2.0 * PI * frequency / sample_rate as f64;
+        (w0, w0.sin() / (2.0 * BiquadCoefficients::clamp_q(q)))
+    }
+
+    pub fn lowpass(frequency: f64, q: f64, sample_rate: usize) -> BiquadCoefficients {
+        let (w0, alpha) = BiquadCoefficients::omega(frequency, q, sample_rate);
+        let cos = w0.cos();
+        BiquadCoefficients::normalised(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn highpass(frequency: f64, q: f64, sample_rate: usize) -> BiquadCoefficients {
+        let (w0, alpha) = BiquadCoefficients::omega(frequency, q, sample_rate);
+        let cos = w0.cos();
+        BiquadCoefficients::normalised(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn peaking(frequency: f64, q: f64, gain_db: f64, sample_rate: usize) -> BiquadCoefficients {
+        let (w0, alpha) = BiquadCoefficients::omega(frequency, q, sample_rate);
+        let a = 10.0f64.powf(gain_db / 40.0);
+        let cos = w0.cos();
+        BiquadCoefficients::normalised(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    pub fn low_shelf(
+        frequency: f64,
+        q: f64,
+        gain_db: f64,
+        sample_rate: usize,
+    ) -> BiquadCoefficients {
+        let (w0, alpha) = BiquadCoefficients::omega(frequency, q, sample_rate);
+        let a = 10.0f64.powf(gain_db / 40.0);
+        let cos = w0.cos();
+        let beta = 2.0 * a.sqrt() * alpha;
+        BiquadCoefficients::normalised(
+            a * ((a + 1.0) - (a - 1.0) * cos + beta),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - beta,
+        )
+    }
+
+    pub fn high_shelf(
+        frequency: f64,
+        q: f64,
+        gain_db: f64,
+        sample_rate: usize,
+    ) -> BiquadCoefficients {
+        let (w0, alpha) = BiquadCoefficients::omega(frequency, q, sample_rate);
+        let a = 10.0f64.powf(gain_db / 40.0);
+        let cos = w0.cos();
+        let beta = 2.0 * a.sqrt() * alpha;
+        BiquadCoefficients::normalised(
+            a * ((a + 1.0) + (a - 1.0) * cos + beta),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            (a + 1.0) - (a - 1.0) * cos + beta,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - beta,
+        )
+    }
+
+    /// Feedforward (`b`) and feedback (`a`) coefficients, as used by `crate::analysis`.
+    pub fn polynomials(&self) -> ([f64; 3], [f64; 3]) {
+        ([self.b0, self.b1, self.b2], [1.0, self.a1, self.a2])
+    }
+
+    /// Evaluates the transfer function at `frequency`, as a fraction of the sample rate.
+    pub fn response_at(&self, frequency: f64) -> Complex<f64> {
+        let (b, a) = self.polynomials();
+        iir_response_at(&b, &a, frequency)
+    }
+}
+
+/// A stateful biquad filter in transposed direct form II.
+///
+/// ```
+/// use synthrs::eq::{Biquad, BiquadCoefficients};
+///
+/// let mut biquad = Biquad::new(BiquadCoefficients::lowpass(1000.0, 0.707, 44_100));
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+///
+/// let filtered = samples.into_iter().map(|sample| biquad.tick(sample));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    state: (f64, f64),
+    pub coefficients: BiquadCoefficients,
+}
+
+impl Biquad {
+    pub fn new(coefficients: BiquadCoefficients) -> Biquad {
+        Biquad {
+            state: (0.0, 0.0),
+            coefficients,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = (0.0, 0.0);
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let c = self.coefficients;
+        let output = c.b0 * input + self.state.0;
+        self.state.0 = c.b1 * input - c.a1 * output + self.state.1;
+        self.state.1 = c.b2 * input - c.a2 * output;
+        output
+    }
+}
+
+impl Processor for Biquad {
+    fn tick(&mut self, input: f64) -> f64 {
+        Biquad::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Biquad::reset(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        // Largest pole radius, from the roots of z^2 + a1z + a2
+        let (a1, a2) = (self.coefficients.a1, self.coefficients.a2);
+        let discriminant = a1 * a1 - 4.0 * a2;
+        let radius = if discriminant < 0.0 {
+            a2.abs().sqrt()
+        } else {
+            (a1.abs() + discriminant.sqrt()) / 2.0
+        };
+        decay_tail(1.0, radius)
+    }
+}
+
+/// Roll-off of high-pass and low-pass bands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slope {
+    Db12,
+    Db24,
+    Db36,
+    Db48,
+}
+
+impl Slope {
+    /// Number of biquad sections needed for the slope.
+    pub fn sections(self) -> usize {
+        match self {
+            Slope::Db12 => 1,
+            Slope::Db24 => 2,
+            Slope::Db36 => 3,
+            Slope::Db48 => 4,
+        }
+    }
+}
+
+/// Shapes of an EQ band.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BandType {
+    /// Boosts or cuts around the band frequency
+    Peaking,
+    /// Boosts or cuts below the band frequency
+    LowShelf,
+    /// Boosts or cuts above the band frequency
+    HighShelf,
+    /// Cuts above the band frequency. Slopes steeper than 12dB/octave are Butterworth, and
+    /// ignore the band's Q.
+    LowPass(Slope),
+    /// Cuts below the band frequency. Slopes steeper than 12dB/octave are Butterworth, and
+    /// ignore the band's Q.
+    HighPass(Slope),
+}
+
+/// Settings of an EQ band. Gain is ignored for high-pass and low-pass bands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBand {
+    pub band_type: BandType,
+    /// Frequency in hertz
+    pub frequency: f64,
+    pub gain_db: f64,
+    pub q: f64,
+}
+
+impl EqBand {
+    pub fn new(band_type: BandType, frequency: f64, gain_db: f64, q: f64) -> EqBand {
+        EqBand {
+            band_type,
+            frequency,
+            gain_db,
+            q,
+        }
+    }
+
+    /// Coefficients of the biquad sections making up the band.
+    pub fn coefficients(&self, sample_rate: usize) -> Vec<BiquadCoefficients> {
+        let (frequency, gain_db, q) = (self.frequency, self.gain_db, self.q);
+
+        match self.band_type {
+            BandType::Peaking => vec![BiquadCoefficients::peaking(
+                frequency,
+                q,
+                gain_db,
+                sample_rate,
+            )],
+            BandType::LowShelf => vec![BiquadCoefficients::low_shelf(
+                frequency,
+                q,
+                gain_db,
+                sample_rate,
+            )],
+            BandType::HighShelf => vec![BiquadCoefficients::high_shelf(
+                frequency,
+                q,
+                gain_db,
+                sample_rate,
+            )],
+            BandType::LowPass(slope) => butterworth_qs(slope, q)
+                .into_iter()
+                .map(|q| BiquadCoefficients::lowpass(frequency, q, sample_rate))
+                .collect(),
+            BandType::HighPass(slope) => butterworth_qs(slope, q)
+                .into_iter()
+                .map(|q| BiquadCoefficients::highpass(frequency, q, sample_rate))
+                .collect(),
+        }
+    }
+}
+
+// Qs of the cascaded sections of an even-order Butterworth filter
+fn butterworth_qs(slope: Slope, q: f64) -> Vec<f64> {
+    let sections = slope.sections();
+    if sections == 1 {
+        return vec![q];
+    }
+
+    let order = 2 * sections;
+    (1..=sections)
+        .map(|k| 1.0 / (2.0 * ((2 * k - 1) as f64 * PI / (2 * order) as f64).cos()))
+        .collect()
+}
+
+// A band being smoothed towards its target settings
+#[derive(Clone, Debug)]
+struct Band {
+    target: EqBand,
+    current: EqBand,
+    sections: Vec<Biquad>,
+}
+
+impl Band {
+    fn new(band: EqBand, sample_rate: usize) -> Band {
+        Band {
+            target: band,
+            current: band,
+            sections: band
+                .coefficients(sample_rate)
+                .into_iter()
+                .map(Biquad::new)
+                .collect(),
+        }
+    }
+
+    fn update_sections(&mut self, sample_rate: usize) {
+        let coefficients = self.current.coefficients(sample_rate);
+        if coefficients.len() == self.sections.len() {
+            for (section, coefficients) in self.sections.iter_mut().zip(coefficients) {
+                section.coefficients = coefficients;
+            }
+        } else {
+            self.sections = coefficients.into_iter().map(Biquad::new).collect();
+        }
+    }
+
+    fn smooth(&mut self, coefficient: f64, sample_rate: usize) {
+        if self.current == self.target {
+            return;
+        }
+
+        let approach = |current: f64, target: f64| target + (current - target) * coefficient;
+        let (current, target) = (self.current, self.target);
+
+        // Frequency and Q are smoothed in the log domain, so sweeps sound even. They are clamped
+        // to the ranges the coefficients use first, as the log of values <= 0 is not finite.
+        let log_frequency =
+            |band: EqBand| BiquadCoefficients::clamp_frequency(band.frequency, sample_rate).ln();
+        let log_q = |band: EqBand| BiquadCoefficients::clamp_q(band.q).ln();
+        let target_frequency = log_frequency(target);
+        let target_q = log_q(target);
+        let frequency = approach(log_frequency(current), target_frequency);
+        let q = approach(log_q(current), target_q);
+        let gain_db = approach(current.gain_db, target.gain_db);
+
+        let settled = (frequency - target_frequency).abs() < 1e-4
+            && (q - target_q).abs() < 1e-4
+            && (gain_db - target.gain_db).abs() < 1e-3;
+
+        self.current = if settled {
+            target
+        } else {
+            EqBand::new(target.band_type, frequency.exp(), gain_db, q.exp())
+        };
+        self.update_sections(sample_rate);
+    }
+
+    fn tick(&mut self, input: f64) -> f64 {
+        self.sections
+            .iter_mut()
+            .fold(input, |sample, section| section.tick(sample))
+    }
+}
+
+/// A stateful parametric EQ with any number of bands, applied in series.
+///
+/// Changing the band type takes effect immediately. Other changes are smoothed over
+/// `smoothing_time`, 20ms by default.
+#[derive(Clone, Debug)]
+pub struct ParametricEq {
+    bands: Vec<Band>,
+    sample_rate: usize,
+    smoothing_coefficient: f64,
+}
+
+impl ParametricEq {
+    /// Creates a new EQ with no bands.
+    pub fn new(sample_rate: usize) -> ParametricEq {
+        let mut eq = ParametricEq {
+            bands: Vec::new(),
+            sample_rate,
+            smoothing_coefficient: 0.0,
+        };
+        eq.set_smoothing_time(0.02);
+        eq
+    }
+
+    /// Sets the time constant in seconds over which band changes are smoothed.
+    pub fn set_smoothing_time(&mut self, seconds: f64) {
+        self.smoothing_coefficient = if seconds > 0.0 {
+            (-1.0 / (seconds * self.sample_rate as f64)).exp()
+        } else {
+            0.0
+        };
+    }
+
+    /// Adds a band, returning its index.
+    pub fn add_band(&mut self, band: EqBand) -> usize {
+        self.bands.push(Band::new(band, self.sample_rate));
+        self.bands.len() - 1
+    }
+
+    pub fn remove_band(&mut self, index: usize) -> EqBand {
+        self.bands.remove(index).target
+    }
+
+    pub fn len(&self) -> usize {
+        self.bands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    /// Target settings of a band.
+    pub fn band(&self, index: usize) -> EqBand {
+        self.bands[index].target
+    }
+
+    /// Changes a band's settings, smoothly where possible.
+    pub fn set_band(&mut self, index: usize, band: EqBand) {
+        let current = &mut self.bands[index];
+        current.target = band;
+        if current.current.band_type != band.band_type {
+            current.current = band;
+            current.update_sections(self.sample_rate);
+        }
+    }
+
+    pub fn set_frequency(&mut self, index: usize, frequency: f64) {
+        let band = EqBand {
+            frequency,
+            ..self.band(index)
+        };
+        self.set_band(index, band);
+    }
+
+    pub fn set_gain(&mut self, index: usize, gain_db: f64) {
+        let band = EqBand {
+            gain_db,
+            ..self.band(index)
+        };
+        self.set_band(index, band);
+    }
+
+    pub fn set_q(&mut self, index: usize, q: f64) {
+        let band = EqBand {
+            q,
+            ..self.band(index)
+        };
+        self.set_band(index, band);
+    }
+
+    pub fn reset(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.current = band.target;
+            band.update_sections(self.sample_rate);
+            for section in band.sections.iter_mut() {
+                section.reset();
+            }
+        }
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let (coefficient, sample_rate) = (self.smoothing_coefficient, self.sample_rate);
+
+        self.bands.iter_mut().fold(input, |sample, band| {
+            band.smooth(coefficient, sample_rate);
+            band.tick(sample)
+        })
+    }
+
+    /// Feedforward (`b`) and feedback (`a`) coefficients of all bands combined, at their target
+    /// settings.
+    pub fn coefficients(&self) -> (Vec<f64>, Vec<f64>) {
+        let mut b = vec![1.0];
+        let mut a = vec![1.0];
+
+        for band in self.bands.iter() {
+            for section in band.target.coefficients(self.sample_rate) {
+                let (section_b, section_a) = section.polynomials();
+                b = multiply_polynomials(&b, &section_b);
+                a = multiply_polynomials(&a, &section_a);
+            }
+        }
+
+        (b, a)
+    }
+
+    /// Combined response of all bands at their target settings, at `frequency` in hertz.
+    pub fn response_at(&self, frequency: f64) -> Complex<f64> {
+        let frequency = frequency / self.sample_rate as f64;
+
+        self.bands
+            .iter()
+            .flat_map(|band| band.target.coefficients(self.sample_rate))
+            .fold(Complex::new(1.0, 0.0), |response, section| {
+                response * section.response_at(frequency)
+            })
+    }
+
+    /// Combined magnitude in dB of all bands at their target settings, at `frequency` in hertz.
+    pub fn magnitude_db_at(&self, frequency: f64) -> f64 {
+        20.0 * self.response_at(frequency).norm().log10()
+    }
+
+    /// Combined response of all bands at their target settings, at `points` frequencies.
+    pub fn frequency_response(&self, points: usize) -> FrequencyResponse {
+        let (b, a) = self.coefficients();
+        iir_response(&b, &a, points)
+    }
+}
+
+impl Processor for ParametricEq {
+    fn tick(&mut self, input: f64) -> f64 {
+        ParametricEq::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        ParametricEq::reset(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        self.bands
+            .iter()
+            .flat_map(|band| band.sections.iter())
+            .fold(0, |sum: usize, section| {
+                sum.saturating_add(section.tail_length())
+            })
+    }
+}
+
+fn multiply_polynomials(p: &[f64], q: &[f64]) -> Vec<f64> {
+    let mut product = vec![0.0; p.len() + q.len() - 1];
+    for (i, x) in p.iter().enumerate() {
+        for (j, y) in q.iter().enumerate() {
+            product[i + j] += x * y;
+        }
+    }
+    product
+}
+
+/// ISO band layouts for `GraphicEq`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphicEqBands {
+    /// 10 octave bands from 31.5Hz to 16kHz
+    Octave,
+    /// 31 third-octave bands from 20Hz to 20kHz
+    ThirdOctave,
+}
+
+impl GraphicEqBands {
+    /// ISO 266 centre frequencies in hertz.
+    pub fn frequencies(self) -> Vec<f64> {
+        match self {
+            GraphicEqBands::Octave => vec![
+                31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+            ],
+            GraphicEqBands::ThirdOctave => vec![
+                20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0,
+                400.0, 500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0,
+                5000.0, 6300.0, 8000.0, 10000.0, 12500.0, 16000.0, 20000.0,
+            ],
+        }
+    }
+
+    /// Q of a band spanning the band spacing.
+    pub fn q(self) -> f64 {
+        let bandwidth: f64 = match self {
+            GraphicEqBands::Octave => 1.0,
+            GraphicEqBands::ThirdOctave => 1.0 / 3.0,
+        };
+        2.0f64.powf(bandwidth).sqrt() / (2.0f64.powf(bandwidth) - 1.0)
+    }
+}
+
+/// A stateful graphic EQ: peaking bands at fixed ISO frequencies, all starting at 0dB.
+///
+/// ```
+/// use synthrs::eq::{GraphicEq, GraphicEqBands};
+///
+/// let mut eq = GraphicEq::new(44_100, GraphicEqBands::Octave);
+/// // Boost the 125Hz band
+/// eq.set_gain(2, 6.0);
+///
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+/// let filtered = samples.into_iter().map(|sample| eq.tick(sample));
+/// ```
+#[derive(Clone, Debug)]
+pub struct GraphicEq {
+    eq: ParametricEq,
+    frequencies: Vec<f64>,
+}
+
+impl GraphicEq {
+    pub fn new(sample_rate: usize, bands: GraphicEqBands) -> GraphicEq {
+        let frequencies = bands.frequencies();
+        let mut eq = ParametricEq::new(sample_rate);
+        for &frequency in frequencies.iter() {
+            eq.add_band(EqBand::new(BandType::Peaking, frequency, 0.0, bands.q()));
+        }
+
+        GraphicEq { eq, frequencies }
+    }
+
+    /// Centre frequencies of the bands in hertz.
+    pub fn frequencies(&self) -> &[f64] {
+        &self.frequencies
+    }
+
+    pub fn gain(&self, index: usize) -> f64 {
+        self.eq.band(index).gain_db
+    }
+
+    /// Sets the gain of a band in dB, smoothly.
+    pub fn set_gain(&mut self, index: usize, gain_db: f64) {
+        self.eq.set_gain(index, gain_db);
+    }
+
+    pub fn reset(&mut self) {
+        self.eq.reset();
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        self.eq.tick(input)
+    }
+
+    /// Combined magnitude in dB of all bands at `frequency` in hertz.
+    pub fn magnitude_db_at(&self, frequency: f64) -> f64 {
+        self.eq.magnitude_db_at(frequency)
+    }
+
+    /// Combined response of all bands at `points` frequencies.
+    pub fn frequency_response(&self, points: usize) -> FrequencyResponse {
+        self.eq.frequency_response(points)
+    }
+}
+
+impl Processor for GraphicEq {
+    fn tick(&mut self, input: f64) -> f64 {
+        GraphicEq::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        GraphicEq::reset(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        self.eq.tail_length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wave::sine_wave;
+
+    // Peak amplitude of the EQ's output for a sine, after it settles
+    fn sine_gain(eq: &mut ParametricEq, frequency: f64) -> f64 {
+        let sine = sine_wave(frequency);
+        let output: Vec<f64> = (0..44_100)
+            .map(|i| eq.tick(sine(i as f64 / 44_100.0)))
+            .collect();
+        output[22_050..].iter().fold(0.0, |max, x| x.abs().max(max))
+    }
+
+    #[test]
+    fn test_peaking_and_shelving_bands() {
+        let mut eq = ParametricEq::new(44_100);
+        eq.add_band(EqBand::new(BandType::Peaking, 1000.0, 6.0, 1.0));
+        assert!((eq.magnitude_db_at(1000.0) - 6.0).abs() < 1e-6);
+        assert!(eq.magnitude_db_at(20.0).abs() < 0.1);
+        assert!((sine_gain(&mut eq, 1000.0) - 10.0f64.powf(6.0 / 20.0)).abs() < 0.01);
+
+        let mut eq = ParametricEq::new(44_100);
+        eq.add_band(EqBand::new(BandType::LowShelf, 200.0, -6.0, 0.707));
+        eq.add_band(EqBand::new(BandType::HighShelf, 5000.0, 4.0, 0.707));
+        assert!((eq.magnitude_db_at(10.0) + 6.0).abs() < 0.1);
+        assert!((eq.magnitude_db_at(20_000.0) - 4.0).abs() < 0.1);
+        assert!(eq.magnitude_db_at(1000.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_pass_filter_slopes() {
+        let attenuation = |slope: Slope| {
+            let mut eq = ParametricEq::new(44_100);
+            eq.add_band(EqBand::new(BandType::HighPass(slope), 1000.0, 0.0, 0.707));
+            assert!((eq.magnitude_db_at(1000.0) + 3.01).abs() < 0.05);
+            assert!(eq.magnitude_db_at(10_000.0).abs() < 0.1);
+            eq.magnitude_db_at(250.0)
+        };
+
+        // Two octaves below the cutoff
+        assert!((attenuation(Slope::Db12) + 24.0).abs() < 1.0);
+        assert!((attenuation(Slope::Db24) + 48.0).abs() < 1.0);
+        assert!((attenuation(Slope::Db36) + 72.0).abs() < 1.0);
+        assert!((attenuation(Slope::Db48) + 96.0).abs() < 1.0);
+
+        let mut eq = ParametricEq::new(44_100);
+        eq.add_band(EqBand::new(
+            BandType::LowPass(Slope::Db24),
+            1000.0,
+            0.0,
+            0.707,
+        ));
+        // Slightly steeper than 48dB, as the bilinear transform compresses frequencies near Nyquist
+        assert!((eq.magnitude_db_at(4000.0) + 49.1).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_combined_response_matches_filtering() {
+        let mut eq = ParametricEq::new(44_100);
+        eq.add_band(EqBand::new(BandType::Peaking, 500.0, -8.0, 2.0));
+        eq.add_band(EqBand::new(
+            BandType::HighPass(Slope::Db12),
+            100.0,
+            0.0,
+            0.707,
+        ));
+        eq.add_band(EqBand::new(BandType::HighShelf, 3000.0, 3.0, 0.707));
+
+        let response = eq.frequency_response(1025);
+        for &bin in [3, 23, 93, 372].iter() {
+            // Frequencies sampled by the response
+            let frequency = bin as f64 * 22_050.0 / 1024.0;
+            let expected = eq.magnitude_db_at(frequency);
+            assert!((response.magnitude_db_at(frequency / 44_100.0) - expected).abs() < 1e-6);
+
+            let gain = 20.0 * sine_gain(&mut eq, frequency).log10();
+            assert!((gain - expected).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_parameter_changes_are_smoothed() {
+        let mut eq = ParametricEq::new(44_100);
+        eq.add_band(EqBand::new(BandType::Peaking, 1000.0, 0.0, 1.0));
+        let sine = sine_wave(1000.0);
+
+        let before: Vec<f64> = (0..4410)
+            .map(|i| eq.tick(sine(i as f64 / 44_100.0)))
+            .collect();
+        eq.set_gain(0, 12.0);
+        let after: Vec<f64> = (4410..44_100)
+            .map(|i| eq.tick(sine(i as f64 / 44_100.0)))
+            .collect();
+
+        // No jump in the waveform, and the gain ramps up over several periods
+        let step = (after[0] - before[before.len() - 1]).abs();
+        assert!(step < 0.2);
+        let peak = |samples: &[f64]| samples.iter().fold(0.0, |max: f64, x| x.abs().max(max));
+        assert!(peak(&after[0..44]) < 1.2);
+        assert!(peak(&after[441..882]) > 1.5);
+        assert!((peak(&after[30_000..]) - 10.0f64.powf(12.0 / 20.0)).abs() < 0.01);
+
+        // Out of range frequencies and Qs are clamped rather than smoothed into NaN
+        eq.set_frequency(0, 0.0);
+        eq.set_q(0, -1.0);
+        let output: Vec<f64> = (0..4410)
+            .map(|i| eq.tick(sine(i as f64 / 44_100.0)))
+            .collect();
+        assert!(output.iter().all(|sample| sample.is_finite()));
+    }
+
+    #[test]
+    fn test_graphic_eq() {
+        let octave = GraphicEq::new(44_100, GraphicEqBands::Octave);
+        assert_eq!(octave.frequencies().len(), 10);
+        assert!((GraphicEqBands::Octave.q() - 2.0f64.sqrt()).abs() < 1e-9);
+
+        let mut third = GraphicEq::new(44_100, GraphicEqBands::ThirdOctave);
+        assert_eq!(third.frequencies().len(), 31);
+        assert!(third.magnitude_db_at(1000.0).abs() < 1e-9);
+
+        let index = third
+            .frequencies()
+            .iter()
+            .position(|&f| f == 1000.0)
+            .unwrap();
+        third.set_gain(index, -9.0);
+        assert_eq!(third.gain(index), -9.0);
+        assert!((third.magnitude_db_at(1000.0) + 9.0).abs() < 1e-6);
+        assert!(third.magnitude_db_at(2000.0).abs() < 0.5);
+    }
+}
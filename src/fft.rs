@@ -0,0 +1,130 @@
+//! A small radix-2 fast Fourier transform, used by convolution and analysis routines.
+//!
+//! Buffer lengths must be a power of two. Use `fft_size` to find a suitable length and
+//! zero-pad your input to it.
+//!
+//! ```
+//! use num::complex::Complex;
+//! use synthrs::fft::{fft, ifft};
+//!
+//! let mut buf: Vec<Complex<f64>> = vec![1.0, 2.0, 3.0, 4.0]
+//!     .into_iter()
+//!     .map(|x| Complex::new(x, 0.0))
+//!     .collect();
+//!
+//! fft(&mut buf);
+//! ifft(&mut buf);
+//!
+//! assert!((buf[2].re - 3.0).abs() < 1e-9);
+//! ```
+
+use std::f64::consts::PI;
+
+use num::complex::Complex;
+
+/// Returns the smallest power of two that is greater than or equal to `length`.
+pub fn fft_size(length: usize) -> usize {
+    length.max(1).next_power_of_two()
+}
+
+/// Performs an in-place forward FFT. Panics if the length of `buf` is not a power of two.
+pub fn fft(buf: &mut [Complex<f64>]) {
+    transform(buf, false);
+}
+
+/// Performs an in-place inverse FFT, including the `1 / n` scaling.
+/// Panics if the length of `buf` is not a power of two.
+pub fn ifft(buf: &mut [Complex<f64>]) {
+    transform(buf, true);
+    let scale = 1.0 / buf.len() as f64;
+    for x in buf.iter_mut() {
+        *x *= scale;
+    }
+}
+
+/// Zero-pads real `samples` to `size` and returns the forward FFT.
+pub fn real_fft(samples: &[f64], size: usize) -> Vec<Complex<f64>> {
+    let mut buf: Vec<Complex<f64>> = samples
+        .iter()
+        .take(size)
+        .map(|&x| Complex::new(x, 0.0))
+        .collect();
+    buf.resize(size, Complex::new(0.0, 0.0));
+    fft(&mut buf);
+    buf
+}
+
+// Iterative Cooley-Tukey
+fn transform(buf: &mut [Complex<f64>], inverse: bool) {
+    let n = buf.len();
+    assert!(n.is_power_of_two(), "FFT length must be a power of two");
+    if n < 2 {
+        return;
+    }
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut length = 2;
+    while length <= n {
+        let angle = sign * 2.0 * PI / length as f64;
+        let step = Complex::new(angle.cos(), angle.sin());
+        for start in (0..n).step_by(length) {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..length / 2 {
+                let even = buf[start + k];
+                let odd = buf[start + k + length / 2] * w;
+                buf[start + k] = even + odd;
+                buf[start + k + length / 2] = even - odd;
+                w *= step;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fft_matches_dft() {
+        let input: Vec<f64> = vec![0.5, -1.0, 2.0, 0.25, 0.0, 3.0, -2.0, 1.0];
+        let output = real_fft(&input, 8);
+
+        for (k, bin) in output.iter().enumerate() {
+            let expected = input
+                .iter()
+                .enumerate()
+                .fold(Complex::new(0.0, 0.0), |acc, (n, &x)| {
+                    let angle = -2.0 * PI * k as f64 * n as f64 / 8.0;
+                    acc + Complex::new(angle.cos(), angle.sin()) * x
+                });
+            assert!((bin - expected).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_ifft_roundtrip() {
+        let input: Vec<f64> = (0..16).map(|i| (i as f64 * 0.7).sin()).collect();
+        let mut buf = real_fft(&input, 16);
+        ifft(&mut buf);
+
+        for (x, y) in input.iter().zip(buf.iter()) {
+            assert!((x - y.re).abs() < 1e-9);
+            assert!(y.im.abs() < 1e-9);
+        }
+    }
+}
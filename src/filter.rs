@@ -27,10 +27,14 @@
 //! #### Common stateless filter arguments:
 //!
 //! * `cutoff`: as a fraction of sample rate, can be obtained from
-//!             `cutoff_from_frequency(cutoff, sample_rate)`. (eg. for a lowpass filter
-//!             frequencies below `sample_rate` / `cutoff` are preserved)
+//!   `cutoff_from_frequency(cutoff, sample_rate)`. (eg. for a lowpass filter
+//!   frequencies below `sample_rate` / `cutoff` are preserved)
 //! * `band`: transition band as a fraction of the sample rate. This determines how
-//!         the cutoff "blends", or how harsh a cutoff this is.
+//!   the cutoff "blends", or how harsh a cutoff this is.
+//!
+//! `lowpass_filter` uses a Blackman window. Other windows are available through `Window` and
+//! `windowed_lowpass_filter`, and `kaiser_lowpass_filter` designs a filter from ripple and
+//! attenuation specifications.
 //!
 //! ### Stateful filters
 //!
@@ -63,29 +67,46 @@
 //!
 //! See: `examples/filters.rs`
 //!
-//! An all-poss filter is implemented as a generator in `crate::wave::allpass`.
+//! An all-pass filter is implemented as `AllPass`. `FractionalDelayLine` supports modulated
+//! delays, and is used by the effects in `crate::modulation`.
 
 use std::f64::consts::PI;
 
+use crate::processor::{decay_tail, Processor};
+
 /// Creates a low-pass filter. Frequencies below the cutoff are preserved when
 /// samples are convolved with this filter.
+///
+/// The filter has an odd number of taps and is symmetric about its centre tap, so it delays
+/// samples by exactly `group_delay(&filter)` samples.
 pub fn lowpass_filter(cutoff: f64, band: f64) -> Vec<f64> {
     let mut n = (4.0 / band).ceil() as usize;
-    if n % 2 == 1 {
+    if n.is_multiple_of(2) {
         n += 1;
     }
 
-    let sinc = |x: f64| -> f64 { (x * PI).sin() / (x * PI) };
+    windowed_lowpass_filter(cutoff, n, &Window::Blackman)
+}
 
-    let sinc_wave: Vec<f64> = (0..n)
-        .map(|i| sinc(2.0 * cutoff * (i as f64 - (n as f64 - 1.0) / 2.0)))
-        .collect();
+/// Creates a windowed-sinc low-pass filter with `size` taps, using any `Window`.
+/// The filter is normalized to unity gain at DC. Use an odd `size` for a linear-phase filter
+/// with an integer group delay.
+pub fn windowed_lowpass_filter(cutoff: f64, size: usize, window: &Window) -> Vec<f64> {
+    let sinc = |x: f64| -> f64 {
+        if x == 0.0 {
+            1.0
+        } else {
+            (x * PI).sin() / (x * PI)
+        }
+    };
 
-    let blackman_window = blackman_window(n);
+    let sinc_wave: Vec<f64> = (0..size)
+        .map(|i| sinc(2.0 * cutoff * (i as f64 - (size as f64 - 1.0) / 2.0)))
+        .collect();
 
     let filter: Vec<f64> = sinc_wave
         .iter()
-        .zip(blackman_window.iter())
+        .zip(window.symmetric(size).iter())
         .map(|tup| *tup.0 * *tup.1)
         .collect();
 
@@ -95,14 +116,188 @@ pub fn lowpass_filter(cutoff: f64, band: f64) -> Vec<f64> {
     filter.iter().map(|&el| el / sum).collect()
 }
 
+/// Creates a low-pass filter using a Kaiser window. The number of taps and the window shape
+/// are computed from the allowed `passband_ripple` and the required `stopband_attenuation`,
+/// both in dB, for a transition band of width `band`.
+///
+/// ```
+/// use synthrs::filter::{cutoff_from_frequency, kaiser_lowpass_filter};
+///
+/// // 0.1dB ripple, 80dB attenuation, 1kHz cutoff with a 200Hz transition band
+/// let lowpass = kaiser_lowpass_filter(
+///     cutoff_from_frequency(1000.0, 44_100),
+///     cutoff_from_frequency(200.0, 44_100),
+///     0.1,
+///     80.0,
+/// );
+/// ```
+pub fn kaiser_lowpass_filter(
+    cutoff: f64,
+    band: f64,
+    passband_ripple: f64,
+    stopband_attenuation: f64,
+) -> Vec<f64> {
+    let (size, beta) = kaiser_parameters(band, passband_ripple, stopband_attenuation);
+    windowed_lowpass_filter(cutoff, size, &Window::Kaiser(beta))
+}
+
+/// Estimates the number of taps and the Kaiser window beta required to meet
+/// `passband_ripple` and `stopband_attenuation` (in dB) for a transition band of width `band`
+/// (as a fraction of the sample rate). The number of taps is always odd, so that the filter is
+/// symmetric about its centre sample.
+///
+/// See: Oppenheim & Schafer, Discrete-Time Signal Processing, section 7.6
+pub fn kaiser_parameters(
+    band: f64,
+    passband_ripple: f64,
+    stopband_attenuation: f64,
+) -> (usize, f64) {
+    let passband_linear = 10.0f64.powf(passband_ripple / 20.0);
+    let passband_delta = (passband_linear - 1.0) / (passband_linear + 1.0);
+    let stopband_delta = 10.0f64.powf(-stopband_attenuation / 20.0);
+    let attenuation = -20.0 * passband_delta.min(stopband_delta).log10();
+
+    let beta = if attenuation > 50.0 {
+        0.1102 * (attenuation - 8.7)
+    } else if attenuation >= 21.0 {
+        0.5842 * (attenuation - 21.0).powf(0.4) + 0.07886 * (attenuation - 21.0)
+    } else {
+        0.0
+    };
+
+    let order = ((attenuation - 8.0) / (2.285 * 2.0 * PI * band))
+        .ceil()
+        .max(0.0) as usize;
+    let size = if order % 2 == 1 { order + 2 } else { order + 1 };
+
+    (size, beta)
+}
+
 /// Creates a Blackman window filter of a given size.
 pub fn blackman_window(size: usize) -> Vec<f64> {
-    (0..size)
-        .map(|i| {
-            0.42 - 0.5 * (2.0 * PI * i as f64 / (size as f64 - 1.0)).cos()
-                + 0.08 * (4.0 * PI * i as f64 / (size as f64 - 1.0)).cos()
-        })
-        .collect()
+    Window::Blackman.symmetric(size)
+}
+
+/// Window functions, for FIR filter design and spectral analysis.
+///
+/// https://en.wikipedia.org/wiki/Window_function
+///
+/// ```
+/// use synthrs::filter::Window;
+///
+/// // Symmetric windows are used for filter design
+/// let hann = Window::Hann.symmetric(65);
+///
+/// // Periodic windows are used for spectral analysis
+/// let kaiser = Window::Kaiser(8.6).periodic(1024);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    Nuttall,
+    FlatTop,
+    /// Kaiser window with the given beta. Larger betas trade a wider main lobe for lower
+    /// side lobes.
+    Kaiser(f64),
+    /// Tukey (tapered cosine) window with the given taper fraction in [0, 1]. 0 is rectangular,
+    /// 1 is Hann.
+    Tukey(f64),
+    /// Gaussian window with the given standard deviation, relative to half the window length.
+    /// The standard deviation must be positive, or creating the window panics.
+    Gaussian(f64),
+}
+
+impl Window {
+    /// Returns the window value at `position` in [0, 1] across the window.
+    fn value(&self, position: f64) -> f64 {
+        let cosine_sum = |coefficients: &[f64]| -> f64 {
+            coefficients.iter().enumerate().fold(0.0, |acc, (k, a)| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                acc + sign * a * (2.0 * PI * k as f64 * position).cos()
+            })
+        };
+
+        match *self {
+            Window::Rectangular => 1.0,
+            Window::Hann => cosine_sum(&[0.5, 0.5]),
+            Window::Hamming => cosine_sum(&[0.54, 0.46]),
+            Window::Blackman => cosine_sum(&[0.42, 0.5, 0.08]),
+            Window::BlackmanHarris => cosine_sum(&[0.358_75, 0.488_29, 0.141_28, 0.011_68]),
+            Window::Nuttall => cosine_sum(&[0.355_768, 0.487_396, 0.144_232, 0.012_604]),
+            Window::FlatTop => cosine_sum(&[
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_158,
+                0.083_578_947,
+                0.006_947_368,
+            ]),
+            Window::Kaiser(beta) => {
+                let x = 2.0 * position - 1.0;
+                bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(beta)
+            }
+            Window::Tukey(alpha) => {
+                let alpha = alpha.clamp(0.0, 1.0);
+                let edge = position.min(1.0 - position);
+                if edge >= alpha / 2.0 {
+                    1.0
+                } else {
+                    0.5 * (1.0 - (2.0 * PI * edge / alpha).cos())
+                }
+            }
+            Window::Gaussian(sigma) => {
+                assert!(sigma > 0.0, "Gaussian window sigma must be positive");
+                let x = (2.0 * position - 1.0) / sigma;
+                (-0.5 * x * x).exp()
+            }
+        }
+    }
+
+    /// Creates a symmetric window of `size` samples, for FIR filter design.
+    pub fn symmetric(&self, size: usize) -> Vec<f64> {
+        match size {
+            0 => Vec::new(),
+            1 => vec![1.0],
+            _ => (0..size)
+                .map(|i| self.value(i as f64 / (size as f64 - 1.0)))
+                .collect(),
+        }
+    }
+
+    /// Creates a periodic window of `size` samples, for spectral analysis.
+    pub fn periodic(&self, size: usize) -> Vec<f64> {
+        let mut window = self.symmetric(size + 1);
+        window.truncate(size);
+        window
+    }
+
+    /// Multiplies `samples` by a periodic window of the same length.
+    pub fn apply(&self, samples: &[f64]) -> Vec<f64> {
+        samples
+            .iter()
+            .zip(self.periodic(samples.len()).iter())
+            .map(|(sample, w)| sample * w)
+            .collect()
+    }
+}
+
+/// Modified Bessel function of the first kind, order zero, by power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    let mut k = 1.0;
+
+    while term > sum * 1e-12 {
+        term *= (half_x / k) * (half_x / k);
+        sum += term;
+        k += 1.0;
+    }
+
+    sum
 }
 
 /// Creates a high-pass filter. Frequencies above the cutoff are preserved when
@@ -111,16 +306,16 @@ pub fn highpass_filter(cutoff: f64, band: f64) -> Vec<f64> {
     spectral_invert(&lowpass_filter(cutoff, band))
 }
 
-/// Creates a low-pass filter. Frequencies between `low_frequency` and `high_frequency`
+/// Creates a band-pass filter. Frequencies between `low_frequency` and `high_frequency`
 /// are preserved when samples are convolved with this filter.
 pub fn bandpass_filter(low_frequency: f64, high_frequency: f64, band: f64) -> Vec<f64> {
     assert!(low_frequency <= high_frequency);
-    let lowpass = lowpass_filter(high_frequency, band);
-    let highpass = highpass_filter(low_frequency, band);
-    convolve(&highpass, &lowpass)
+    let high_lowpass = lowpass_filter(high_frequency, band);
+    let low_lowpass = lowpass_filter(low_frequency, band);
+    subtract(&high_lowpass, &low_lowpass)
 }
 
-/// Creates a low-pass filter. Frequencies outside of `low_frequency` and `high_frequency`
+/// Creates a band-reject filter. Frequencies outside of `low_frequency` and `high_frequency`
 /// are preserved when samples are convolved with this filter.
 pub fn bandreject_filter(low_frequency: f64, high_frequency: f64, band: f64) -> Vec<f64> {
     assert!(low_frequency <= high_frequency);
@@ -131,45 +326,117 @@ pub fn bandreject_filter(low_frequency: f64, high_frequency: f64, band: f64) ->
 
 /// Given a filter, inverts it. For example, inverting a low-pass filter will result in a
 /// high-pass filter with the same cutoff frequency.
+///
+/// The filter must have an odd number of taps and be symmetric, such as those created by
+/// `lowpass_filter`.
 pub fn spectral_invert(filter: &[f64]) -> Vec<f64> {
-    assert_eq!(filter.len() % 2, 0);
-    let mut count = 0;
+    assert_eq!(
+        filter.len() % 2,
+        1,
+        "spectral inversion needs an odd-length, symmetric filter"
+    );
+    let centre = group_delay(filter);
 
     filter
         .iter()
-        .map(|&el| {
-            let add = if count == filter.len() / 2 { 1.0 } else { 0.0 };
-            count += 1;
-            -el + add
-        })
+        .enumerate()
+        .map(|(i, &el)| if i == centre { 1.0 - el } else { -el })
         .collect()
 }
 
+/// Returns the delay, in samples, introduced by convolving with a symmetric (linear-phase)
+/// filter. This is exact for odd-length filters and rounded down for even-length filters.
+pub fn group_delay(filter: &[f64]) -> usize {
+    filter.len().saturating_sub(1) / 2
+}
+
+/// Output length and alignment of a convolution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConvolutionMode {
+    /// Returns `input.len() + filter.len() - 1` samples, including the delay introduced by the
+    /// filter and its tail
+    Full,
+    /// Returns `input.len()` samples, with the filter's `group_delay` removed so that the output
+    /// is aligned with the input
+    Same,
+}
+
+/// Convolves `input` with `filter`, compensating for the filter's delay so that the output is
+/// aligned with the input. See `convolve_with_mode`.
 pub fn convolve(filter: &[f64], input: &[f64]) -> Vec<f64> {
-    let mut output: Vec<f64> = Vec::new();
-    let h_len = (filter.len() / 2) as isize;
-
-    for i in -(filter.len() as isize / 2)..(input.len() as isize - 1) {
-        output.push(0.0);
-        for j in 0isize..filter.len() as isize {
-            let input_idx = i + j;
-            let output_idx = i + h_len;
-            if input_idx < 0 || input_idx >= input.len() as isize {
-                continue;
-            }
-            output[output_idx as usize] += input[input_idx as usize] * filter[j as usize]
-        }
+    convolve_with_mode(filter, input, ConvolutionMode::Same)
+}
+
+/// Convolves `input` with `filter`.
+///
+/// ```
+/// use synthrs::filter::{convolve_with_mode, ConvolutionMode};
+///
+/// let filter = vec![0.25, 0.5, 0.25];
+/// let input = vec![0.0, 1.0, 0.0, 0.0];
+///
+/// let full = convolve_with_mode(&filter, &input, ConvolutionMode::Full);
+/// assert_eq!(full, vec![0.0, 0.25, 0.5, 0.25, 0.0, 0.0]);
+///
+/// let same = convolve_with_mode(&filter, &input, ConvolutionMode::Same);
+/// assert_eq!(same, vec![0.25, 0.5, 0.25, 0.0]);
+/// ```
+pub fn convolve_with_mode(filter: &[f64], input: &[f64], mode: ConvolutionMode) -> Vec<f64> {
+    if filter.is_empty() || input.is_empty() {
+        return match mode {
+            ConvolutionMode::Full => Vec::new(),
+            ConvolutionMode::Same => vec![0.0; input.len()],
+        };
     }
 
-    output
+    let (offset, length) = match mode {
+        ConvolutionMode::Full => (0, input.len() + filter.len() - 1),
+        ConvolutionMode::Same => (group_delay(filter), input.len()),
+    };
+
+    (offset..offset + length)
+        .map(|i| {
+            // Only the taps that overlap the input contribute
+            let first = (i + 1).saturating_sub(input.len());
+            let last = i.min(filter.len() - 1);
+            (first..=last).fold(0.0, |acc, j| acc + filter[j] * input[i - j])
+        })
+        .collect()
 }
 
 /// Performs elementwise addition of two `Vec<f64>`s. Can be used to combine filters together
-/// (eg. combining a low-pass filter with a high-pass filter to create a band-pass filter)
+/// (eg. combining a low-pass filter with a high-pass filter to create a band-reject filter)
+///
+/// Inputs of different lengths are centre-aligned, so that symmetric filters of different
+/// lengths can be combined. The output is as long as the longer input.
 pub fn add(left: &[f64], right: &[f64]) -> Vec<f64> {
-    left.iter()
-        .zip(right.iter())
-        .map(|tup| *tup.0 + *tup.1)
+    combine_centred(left, right, |l, r| l + r)
+}
+
+/// Performs elementwise subtraction of two `Vec<f64>`s, centre-aligning inputs of different
+/// lengths. See `add`.
+pub fn subtract(left: &[f64], right: &[f64]) -> Vec<f64> {
+    combine_centred(left, right, |l, r| l - r)
+}
+
+fn combine_centred<F>(left: &[f64], right: &[f64], f: F) -> Vec<f64>
+where
+    F: Fn(f64, f64) -> f64,
+{
+    let length = left.len().max(right.len());
+    let left_offset = (length - left.len()) / 2;
+    let right_offset = (length - right.len()) / 2;
+
+    let padded = |samples: &[f64], offset: usize, i: usize| -> f64 {
+        if i >= offset && i - offset < samples.len() {
+            samples[i - offset]
+        } else {
+            0.0
+        }
+    };
+
+    (0..length)
+        .map(|i| f(padded(left, left_offset, i), padded(right, right_offset, i)))
         .collect()
 }
 
@@ -192,6 +459,201 @@ pub fn envelope(relative_t: f64, attack: f64, decay: f64) -> f64 {
     0.0
 }
 
+/// Stages of an `Adsr` envelope.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdsrStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// A stateful, linear attack/decay/sustain/release envelope, with output in [0.0, 1.0].
+///
+/// ```
+/// use synthrs::filter::Adsr;
+///
+/// // 10ms attack, 100ms decay to 0.7, and 200ms release
+/// let mut adsr = Adsr::new(0.01, 0.1, 0.7, 0.2, 44_100);
+/// adsr.note_on();
+/// let held: Vec<f64> = (0..44_100).map(|_| adsr.tick()).collect();
+/// adsr.note_off();
+/// let released: Vec<f64> = (0..44_100).map(|_| adsr.tick()).collect();
+///
+/// assert!((held[44_099] - 0.7).abs() < 1e-9);
+/// assert_eq!(released[44_099], 0.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Adsr {
+    stage: AdsrStage,
+    value: f64,
+    release_step: f64,
+    sample_rate: usize,
+    /// Attack time in seconds
+    pub attack: f64,
+    /// Decay time in seconds
+    pub decay: f64,
+    /// Sustain level from 0.0 to 1.0
+    pub sustain: f64,
+    /// Release time in seconds
+    pub release: f64,
+}
+
+impl Adsr {
+    pub fn new(attack: f64, decay: f64, sustain: f64, release: f64, sample_rate: usize) -> Adsr {
+        Adsr {
+            stage: AdsrStage::Idle,
+            value: 0.0,
+            release_step: 0.0,
+            sample_rate,
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+
+    pub fn stage(&self) -> AdsrStage {
+        self.stage
+    }
+
+    /// Current output, without advancing.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Whether the envelope is still producing output.
+    pub fn is_active(&self) -> bool {
+        self.stage != AdsrStage::Idle
+    }
+
+    /// Starts the attack from the current level.
+    pub fn note_on(&mut self) {
+        self.stage = AdsrStage::Attack;
+    }
+
+    /// Starts the release from the current level.
+    pub fn note_off(&mut self) {
+        if self.stage != AdsrStage::Idle {
+            self.stage = AdsrStage::Release;
+            self.release_step = self.value / self.samples(self.release);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stage = AdsrStage::Idle;
+        self.value = 0.0;
+    }
+
+    fn samples(&self, seconds: f64) -> f64 {
+        (seconds * self.sample_rate as f64).max(1.0)
+    }
+
+    /// Advances by one sample, returning the new output.
+    pub fn tick(&mut self) -> f64 {
+        match self.stage {
+            AdsrStage::Idle => {}
+            AdsrStage::Attack => {
+                self.value += 1.0 / self.samples(self.attack);
+                if self.value >= 1.0 {
+                    self.value = 1.0;
+                    self.stage = AdsrStage::Decay;
+                }
+            }
+            AdsrStage::Decay => {
+                self.value -= (1.0 - self.sustain) / self.samples(self.decay);
+                if self.value <= self.sustain {
+                    self.value = self.sustain;
+                    self.stage = AdsrStage::Sustain;
+                }
+            }
+            AdsrStage::Sustain => self.value = self.sustain,
+            AdsrStage::Release => {
+                self.value -= self.release_step;
+                if self.value <= 0.0 {
+                    self.value = 0.0;
+                    self.stage = AdsrStage::Idle;
+                }
+            }
+        }
+
+        self.value
+    }
+}
+
+/// Gain of a quadratic fade in over `fade_length` at the start of a note, and out over
+/// `fade_length` at its end, given the time `elapsed` since the start and `remaining` until the
+/// end. Any time unit can be used. Fading notes in and out avoids clicks when they start or stop
+/// abruptly.
+///
+/// ```
+/// use synthrs::filter::fade_gain;
+///
+/// assert_eq!(fade_gain(0.0, 1.0, 0.01), 0.0);
+/// assert_eq!(fade_gain(0.005, 1.0, 0.01), 0.25);
+/// assert_eq!(fade_gain(0.5, 0.5, 0.01), 1.0);
+/// ```
+pub fn fade_gain(elapsed: f64, remaining: f64, fade_length: f64) -> f64 {
+    if fade_length <= 0.0 {
+        return 1.0;
+    }
+
+    let ratio = (elapsed.min(remaining) / fade_length).clamp(0.0, 1.0);
+    ratio * ratio
+}
+
+/// Fades in the start and fades out the end of `samples` over `fade_length` samples.
+pub fn fade_edges(samples: &[f64], fade_length: usize) -> Vec<f64> {
+    let len = samples.len();
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| sample * fade_gain(i as f64, (len - 1 - i) as f64, fade_length as f64))
+        .collect()
+}
+
+/// Smooths over discontinuities in `samples`. Wherever consecutive samples jump by more than
+/// `threshold`, the jump is spread out over the following `fade_length` samples with a raised
+/// cosine, so that it no longer clicks.
+///
+/// ```
+/// use synthrs::filter::declick;
+///
+/// let samples = vec![0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+/// let declicked = declick(&samples, 0.5, 4);
+///
+/// assert_eq!(declicked[2], 0.0);
+/// assert_eq!(declicked[6], 1.0);
+/// ```
+pub fn declick(samples: &[f64], threshold: f64, fade_length: usize) -> Vec<f64> {
+    // (start index, size of the step being faded out)
+    let mut corrections: Vec<(usize, f64)> = Vec::new();
+
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, &sample)| {
+            if i > 0 && fade_length > 0 {
+                let step = samples[i - 1] - sample;
+                if step.abs() > threshold {
+                    corrections.push((i, step));
+                }
+            }
+            corrections.retain(|&(start, _)| i - start < fade_length);
+
+            sample
+                + corrections
+                    .iter()
+                    .map(|&(start, step)| {
+                        let progress = (i - start) as f64 / fade_length as f64;
+                        step * 0.5 * (1.0 + (PI * progress).cos())
+                    })
+                    .sum::<f64>()
+        })
+        .collect()
+}
+
 /// A stateful delay line. Samples are delayed for `delay_length` seconds.
 ///
 /// https://en.wikipedia.org/wiki/Analog_delay_line
@@ -244,6 +706,349 @@ impl DelayLine {
     }
 }
 
+impl Processor for DelayLine {
+    /// Reads the delayed sample, then writes `input`.
+    fn tick(&mut self, input: f64) -> f64 {
+        let output = self.read();
+        self.write(input);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buf.iter_mut().for_each(|x| *x = 0.0);
+        self.index = 0;
+    }
+
+    fn tail_length(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+/// Interpolation used by `FractionalDelayLine` to read between samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Linear interpolation between the two nearest samples. Cheap, but attenuates high
+    /// frequencies for fractional delays.
+    Linear,
+    /// First-order (Thiran) all-pass interpolation. Flat magnitude response, best for slowly
+    /// changing delays read from a single tap, such as in feedback loops.
+    AllPass,
+    /// Third-order Lagrange interpolation over the four nearest samples.
+    Cubic,
+}
+
+/// A stateful delay line with a fractional, modulatable delay time.
+///
+/// Unlike `DelayLine`, the delay can be changed while the delay line is running, up to the
+/// maximum delay given when it was created. Samples are read before they are written, so the
+/// minimum delay is one sample (two samples for cubic interpolation).
+///
+/// ```
+/// use synthrs::filter::{FractionalDelayLine, Interpolation};
+///
+/// // Up to 10ms of delay at 44_100Hz
+/// let mut delay_line = FractionalDelayLine::new(0.01, 44_100, Interpolation::Cubic);
+/// delay_line.set_delay(0.002_5);
+///
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+/// let delayed: Vec<f64> = samples.into_iter().map(|sample| delay_line.tick(sample)).collect();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FractionalDelayLine {
+    buf: Vec<f64>,
+    index: usize,
+    delay_samples: f64,
+    allpass_state: f64,
+    pub interpolation: Interpolation,
+    pub sample_rate: usize,
+}
+
+impl FractionalDelayLine {
+    /// Creates a new fractional delay line holding up to `max_delay_length` seconds.
+    /// The delay is initially set to `max_delay_length`.
+    pub fn new(
+        max_delay_length: f64,
+        sample_rate: usize,
+        interpolation: Interpolation,
+    ) -> FractionalDelayLine {
+        let max_delay_samples = (max_delay_length * sample_rate as f64).max(0.0);
+
+        let mut delay_line = FractionalDelayLine {
+            // Room for the interpolation points on either side of the maximum delay
+            buf: vec![0.0; max_delay_samples.ceil() as usize + 4],
+            index: 0,
+            delay_samples: 0.0,
+            allpass_state: 0.0,
+            interpolation,
+            sample_rate,
+        };
+        delay_line.set_delay_samples(max_delay_samples);
+        delay_line
+    }
+
+    /// Largest delay that can be set, in samples.
+    pub fn max_delay_samples(&self) -> f64 {
+        (self.buf.len() - 4) as f64
+    }
+
+    /// Current delay in samples.
+    pub fn delay_samples(&self) -> f64 {
+        self.delay_samples
+    }
+
+    /// Sets the delay in seconds, clamped to the supported range.
+    pub fn set_delay(&mut self, delay_length: f64) {
+        self.set_delay_samples(delay_length * self.sample_rate as f64);
+    }
+
+    /// Sets the delay in samples, clamped to the supported range.
+    pub fn set_delay_samples(&mut self, delay_samples: f64) {
+        let min = match self.interpolation {
+            Interpolation::Linear => 1.0,
+            Interpolation::AllPass => 1.5,
+            Interpolation::Cubic => 2.0,
+        };
+        self.delay_samples = delay_samples
+            .max(min)
+            .min(self.max_delay_samples().max(min));
+    }
+
+    /// Reads the sample written `delay` samples ago, where 1 is the most recently written sample.
+    fn at(&self, delay: usize) -> f64 {
+        let len = self.buf.len();
+        self.buf[(self.index + len - delay % len) % len]
+    }
+
+    /// Reads a sample at the current delay.
+    pub fn read(&mut self) -> f64 {
+        match self.interpolation {
+            Interpolation::AllPass => {
+                // Keep the fractional part in [0.5, 1.5) so the all-pass coefficient stays
+                // well away from the unstable edge
+                let whole = (self.delay_samples - 0.5).floor();
+                let fraction = self.delay_samples - whole;
+                let eta = (1.0 - fraction) / (1.0 + fraction);
+                let whole = whole as usize;
+
+                let output = eta * self.at(whole) + self.at(whole + 1) - eta * self.allpass_state;
+                self.allpass_state = output;
+                output
+            }
+            _ => self.read_at(self.delay_samples),
+        }
+    }
+
+    /// Reads a sample at an arbitrary delay in samples, without changing the current delay.
+    /// This allows several taps to be read from the same delay line. All-pass interpolation
+    /// needs state from the previous read, so linear interpolation is used for it here instead.
+    pub fn read_at(&self, delay_samples: f64) -> f64 {
+        let max = self.max_delay_samples();
+        match self.interpolation {
+            Interpolation::Linear | Interpolation::AllPass => {
+                let delay_samples = delay_samples.max(1.0).min(max.max(1.0));
+                let whole = delay_samples.floor();
+                let fraction = delay_samples - whole;
+                let whole = whole as usize;
+
+                (1.0 - fraction) * self.at(whole) + fraction * self.at(whole + 1)
+            }
+            Interpolation::Cubic => {
+                let delay_samples = delay_samples.max(2.0).min(max.max(2.0));
+                let whole = delay_samples.floor();
+                let f = delay_samples - whole;
+                let whole = whole as usize;
+
+                let h0 = -f * (f - 1.0) * (f - 2.0) / 6.0;
+                let h1 = (f + 1.0) * (f - 1.0) * (f - 2.0) / 2.0;
+                let h2 = -(f + 1.0) * f * (f - 2.0) / 2.0;
+                let h3 = (f + 1.0) * f * (f - 1.0) / 6.0;
+
+                h0 * self.at(whole - 1)
+                    + h1 * self.at(whole)
+                    + h2 * self.at(whole + 1)
+                    + h3 * self.at(whole + 2)
+            }
+        }
+    }
+
+    pub fn write(&mut self, value: f64) {
+        self.buf[self.index] = value;
+        self.index = (self.index + 1) % self.buf.len();
+    }
+
+    /// Reads the delayed sample, then writes `input`.
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let output = self.read();
+        self.write(input);
+        output
+    }
+}
+
+impl Processor for FractionalDelayLine {
+    fn tick(&mut self, input: f64) -> f64 {
+        FractionalDelayLine::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.buf.iter_mut().for_each(|x| *x = 0.0);
+        self.index = 0;
+        self.allpass_state = 0.0;
+    }
+
+    fn tail_length(&self) -> usize {
+        self.delay_samples.ceil() as usize + 2
+    }
+}
+
+/// A stateful first-order all-pass filter. All frequencies are passed with unity gain, while
+/// the phase is shifted by 90 degrees at the `frequency` given.
+///
+/// https://en.wikipedia.org/wiki/All-pass_filter#Digital_implementation
+///
+/// ```
+/// use synthrs::filter::FirstOrderAllPass;
+///
+/// let mut allpass = FirstOrderAllPass::new(1000.0, 44_100);
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+///
+/// let filtered = samples.into_iter().map(|sample| allpass.tick(sample));
+/// ```
+#[derive(Clone, Debug)]
+pub struct FirstOrderAllPass {
+    previous_input: f64,
+    previous_output: f64,
+    pub coefficient: f64,
+}
+
+impl FirstOrderAllPass {
+    /// Creates a new first-order all-pass filter with its 90 degree phase shift at `frequency`.
+    pub fn new(frequency: f64, sample_rate: usize) -> FirstOrderAllPass {
+        let mut allpass = FirstOrderAllPass {
+            previous_input: 0.0,
+            previous_output: 0.0,
+            coefficient: 0.0,
+        };
+        allpass.set_frequency(frequency, sample_rate);
+        allpass
+    }
+
+    /// The coefficient which puts the 90 degree phase shift point at `frequency`.
+    pub fn coefficient_for(frequency: f64, sample_rate: usize) -> f64 {
+        let t = (PI * frequency / sample_rate as f64).tan();
+        (t - 1.0) / (t + 1.0)
+    }
+
+    /// Moves the 90 degree phase shift point to `frequency`.
+    pub fn set_frequency(&mut self, frequency: f64, sample_rate: usize) {
+        self.coefficient = FirstOrderAllPass::coefficient_for(frequency, sample_rate);
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let output = self.coefficient * input + self.previous_input
+            - self.coefficient * self.previous_output;
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+impl Processor for FirstOrderAllPass {
+    fn tick(&mut self, input: f64) -> f64 {
+        FirstOrderAllPass::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.previous_input = 0.0;
+        self.previous_output = 0.0;
+    }
+
+    fn tail_length(&self) -> usize {
+        decay_tail(1.0, self.coefficient)
+    }
+}
+
+/// Left and right gains for constant-power panning. `pan` ranges from -1.0 (hard left) through
+/// 0.0 (centre, both at -3dB) to 1.0 (hard right).
+pub fn pan_gains(pan: f64) -> (f64, f64) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * PI / 4.0;
+    (angle.cos(), angle.sin())
+}
+
+/// Pans mono samples into a stereo pair of left and right channels with constant power.
+///
+/// ```
+/// use synthrs::filter::pan;
+///
+/// let (left, right) = pan(&[1.0, 0.5], 1.0);
+/// assert!(left.iter().all(|&sample| sample.abs() < 1e-12));
+/// assert_eq!(right, vec![1.0, 0.5]);
+/// ```
+pub fn pan(samples: &[f64], pan: f64) -> (Vec<f64>, Vec<f64>) {
+    let (left_gain, right_gain) = pan_gains(pan);
+    samples
+        .iter()
+        .map(|&sample| (sample * left_gain, sample * right_gain))
+        .unzip()
+}
+
+/// A stateful DC blocker: a first-order high-pass filter with a very low cutoff, which removes
+/// any constant offset from a signal.
+///
+/// https://ccrma.stanford.edu/~jos/filters/DC_Blocker.html
+///
+/// ```
+/// use synthrs::filter::DcBlocker;
+///
+/// let mut dc_blocker = DcBlocker::new(20.0, 44_100);
+/// let samples: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
+///
+/// let filtered = samples.into_iter().map(|sample| dc_blocker.tick(sample));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DcBlocker {
+    previous_input: f64,
+    previous_output: f64,
+    /// Pole radius, just below 1.0
+    pub coefficient: f64,
+}
+
+impl DcBlocker {
+    /// Creates a new DC blocker with its cutoff at roughly `frequency`. 5Hz to 20Hz works.
+    pub fn new(frequency: f64, sample_rate: usize) -> DcBlocker {
+        DcBlocker {
+            previous_input: 0.0,
+            previous_output: 0.0,
+            coefficient: (-2.0 * PI * frequency / sample_rate as f64).exp(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.previous_input = 0.0;
+        self.previous_output = 0.0;
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let output = input - self.previous_input + self.coefficient * self.previous_output;
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+impl Processor for DcBlocker {
+    fn tick(&mut self, input: f64) -> f64 {
+        DcBlocker::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        DcBlocker::reset(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        decay_tail(1.0, self.coefficient)
+    }
+}
+
 /// A stateful all-pass filter.
 ///
 /// https://en.wikipedia.org/wiki/All-pass_filter
@@ -281,6 +1086,20 @@ impl AllPass {
     }
 }
 
+impl Processor for AllPass {
+    fn tick(&mut self, input: f64) -> f64 {
+        AllPass::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+    }
+
+    fn tail_length(&self) -> usize {
+        decay_tail(self.delay_line.buf.len() as f64, self.feedback)
+    }
+}
+
 /// A stateful comb filter.
 ///
 /// https://en.wikipedia.org/wiki/Comb_filter
@@ -335,6 +1154,30 @@ impl Comb {
     }
 }
 
+impl Processor for Comb {
+    fn tick(&mut self, input: f64) -> f64 {
+        Comb::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+        self.filter_state = 0.0;
+    }
+
+    fn tail_length(&self) -> usize {
+        // Loop gain at DC, where the dampening filter passes the most
+        let dampening_gain = if self.dampening.abs() < 1.0 {
+            self.dampening_inverse / (1.0 - self.dampening.abs())
+        } else {
+            1.0
+        };
+        decay_tail(
+            self.delay_line.buf.len() as f64,
+            self.feedback * dampening_gain,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -347,12 +1190,169 @@ mod tests {
         assert_eq!(convolve(&filter, &input), output);
     }
 
+    #[test]
+    fn test_convolve_with_mode() {
+        let filter = vec![1.0, 2.0, 3.0];
+        let input = vec![1.0, 0.0, -1.0, 2.0];
+        assert_eq!(
+            convolve_with_mode(&filter, &input, ConvolutionMode::Full),
+            vec![1.0, 2.0, 2.0, 0.0, 1.0, 6.0]
+        );
+        assert_eq!(
+            convolve_with_mode(&filter, &input, ConvolutionMode::Same),
+            vec![2.0, 2.0, 0.0, 1.0]
+        );
+        assert_eq!(
+            convolve_with_mode(&filter, &[], ConvolutionMode::Full),
+            Vec::<f64>::new()
+        );
+
+        // Same-mode output of a linear-phase filter is not delayed
+        let lowpass = lowpass_filter(0.1, 0.05);
+        let mut impulse = vec![0.0; 200];
+        impulse[100] = 1.0;
+        let filtered = convolve(&lowpass, &impulse);
+        let peak = filtered.iter().enumerate().fold(
+            (0, 0.0),
+            |acc, (i, &x)| if x > acc.1 { (i, x) } else { acc },
+        );
+        assert_eq!(peak.0, 100);
+    }
+
     #[test]
     fn test_add() {
         let a = vec![1.0, -1.0, -8.0];
         let b = vec![-1.0, 5.0, 3.0];
         let expected = vec![0.0, 4.0, -5.0];
         assert_eq!(add(&a, &b), expected);
+
+        let c = vec![1.0, 2.0, 3.0, 2.0, 1.0];
+        assert_eq!(add(&a, &c), vec![1.0, 3.0, 2.0, -6.0, 1.0]);
+        assert_eq!(subtract(&c, &a), vec![1.0, 1.0, 4.0, 10.0, 1.0]);
+    }
+
+    #[test]
+    fn test_linear_phase_filters() {
+        use crate::analysis::fir_response;
+
+        let lowpass = lowpass_filter(0.1, 0.01);
+        assert_eq!(lowpass.len() % 2, 1);
+        assert_eq!(group_delay(&lowpass), 200);
+        for i in 0..lowpass.len() / 2 {
+            assert!((lowpass[i] - lowpass[lowpass.len() - 1 - i]).abs() < 1e-12);
+        }
+
+        let highpass = fir_response(&highpass_filter(0.1, 0.01), 1001);
+        assert!(highpass.magnitude_db_at(0.05) < -70.0);
+        assert!(highpass.magnitude_db_at(0.2).abs() < 0.01);
+        assert!((highpass.group_delay_at(0.2) - 200.0).abs() < 1e-6);
+
+        let bandpass = fir_response(&bandpass_filter(0.1, 0.3, 0.01), 1001);
+        assert!(bandpass.magnitude_db_at(0.05) < -70.0);
+        assert!(bandpass.magnitude_db_at(0.2).abs() < 0.01);
+        assert!(bandpass.magnitude_db_at(0.35) < -70.0);
+
+        let bandreject = fir_response(&bandreject_filter(0.1, 0.3, 0.01), 1001);
+        assert!(bandreject.magnitude_db_at(0.05).abs() < 0.01);
+        assert!(bandreject.magnitude_db_at(0.2) < -70.0);
+        assert!(bandreject.magnitude_db_at(0.35).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_windows() {
+        let windows = [
+            Window::Rectangular,
+            Window::Hann,
+            Window::Hamming,
+            Window::Blackman,
+            Window::BlackmanHarris,
+            Window::Nuttall,
+            Window::FlatTop,
+            Window::Kaiser(6.0),
+            Window::Tukey(0.5),
+            Window::Gaussian(0.4),
+        ];
+
+        for window in windows.iter() {
+            let coefficients = window.symmetric(33);
+            assert_eq!(coefficients.len(), 33);
+            assert!((coefficients[16] - 1.0).abs() < 1e-6, "{:?}", window);
+            for i in 0..16 {
+                assert!((coefficients[i] - coefficients[32 - i]).abs() < 1e-9);
+            }
+            assert_eq!(window.periodic(32)[..], coefficients[..32]);
+        }
+
+        assert!(Window::Hann.symmetric(33)[0].abs() < 1e-12);
+        assert_eq!(Window::Kaiser(0.0).symmetric(8), vec![1.0; 8]);
+        assert_eq!(Window::Tukey(0.0).symmetric(8), vec![1.0; 8]);
+        assert_eq!(Window::Hann.symmetric(1), vec![1.0]);
+    }
+
+    #[test]
+    fn test_kaiser_lowpass_filter() {
+        let (size, beta) = kaiser_parameters(0.05, 0.1, 60.0);
+        assert_eq!(size % 2, 1);
+        assert!((beta - 5.653).abs() < 0.01);
+        assert!(size > 70 && size < 80);
+
+        // Measure attenuation directly with a DFT of the kernel
+        let filter = kaiser_lowpass_filter(0.1, 0.05, 0.1, 60.0);
+        let magnitude = |frequency: f64| -> f64 {
+            let (re, im) = filter
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, h)| {
+                    let angle = -2.0 * PI * frequency * n as f64;
+                    (re + h * angle.cos(), im + h * angle.sin())
+                });
+            (re * re + im * im).sqrt()
+        };
+
+        for i in 0..=50 {
+            let passband = 0.075 * i as f64 / 50.0;
+            assert!((20.0 * magnitude(passband).log10()).abs() < 0.1);
+
+            let stopband = 0.125 + 0.375 * i as f64 / 50.0;
+            assert!(20.0 * magnitude(stopband).log10() < -60.0);
+        }
+    }
+
+    #[test]
+    fn test_dc_blocker() {
+        let mut dc_blocker = DcBlocker::new(10.0, 44_100);
+        let sine = crate::wave::sine_wave(440.0);
+        let output: Vec<f64> = (0..44_100)
+            .map(|i| dc_blocker.tick(0.5 + sine(i as f64 / 44_100.0)))
+            .collect();
+
+        let tail = &output[22_050..];
+        let mean = tail.iter().sum::<f64>() / tail.len() as f64;
+        let peak = tail.iter().fold(0.0f64, |max, x| x.abs().max(max));
+        assert!(mean.abs() < 1e-3);
+        assert!((peak - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_declick() {
+        let max_step = |samples: &[f64]| {
+            samples
+                .windows(2)
+                .fold(0.0f64, |max, w| (w[1] - w[0]).abs().max(max))
+        };
+
+        // A square wave switching abruptly
+        let samples: Vec<f64> = (0..400)
+            .map(|i| if (i / 100) % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        let declicked = declick(&samples, 0.1, 32);
+        assert!(max_step(&declicked) < 0.06);
+        assert_eq!(&declicked[50..100], &samples[50..100]);
+
+        let faded = fade_edges(&samples, 10);
+        assert_eq!(faded[0], 0.0);
+        assert_eq!(faded[399], 0.0);
+        assert_eq!(&faded[10..390], &samples[10..390]);
     }
 
     #[test]
@@ -366,6 +1366,56 @@ mod tests {
         assert_eq!(envelope(-0.5, 1.0, 1.0), 0.0);
     }
 
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_fractional_delay_line() {
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::AllPass,
+            Interpolation::Cubic,
+        ]
+        .iter()
+        {
+            // Integer delays are exact
+            let mut delay_line = FractionalDelayLine::new(10.0, 1, *interpolation);
+            delay_line.set_delay_samples(3.0);
+            let output: Vec<f64> = (1..=8).map(|i| delay_line.tick(i as f64)).collect();
+            for (i, x) in output.iter().enumerate().skip(3) {
+                assert!((x - (i as f64 - 2.0)).abs() < 1e-12, "{:?}", interpolation);
+            }
+
+            // Fractional delays of a low-frequency sine are close to the ideal delay
+            let mut delay_line = FractionalDelayLine::new(0.01, 44_100, *interpolation);
+            delay_line.set_delay_samples(10.3);
+            for i in 0..1000 {
+                let t = i as f64 / 44_100.0;
+                let output = delay_line.tick((2.0 * PI * 200.0 * t).sin());
+                if i > 100 {
+                    let expected = (2.0 * PI * 200.0 * (t - 10.3 / 44_100.0)).sin();
+                    assert!((output - expected).abs() < 1e-3, "{:?}", interpolation);
+                }
+            }
+        }
+
+        let mut delay_line = FractionalDelayLine::new(0.01, 44_100, Interpolation::Cubic);
+        delay_line.set_delay(1.0);
+        assert_eq!(delay_line.delay_samples(), 441.0);
+        delay_line.set_delay(0.0);
+        assert_eq!(delay_line.delay_samples(), 2.0);
+    }
+
+    #[test]
+    fn test_first_order_allpass() {
+        use crate::analysis::probe_response;
+
+        let mut allpass = FirstOrderAllPass::new(4410.0, 44_100);
+        let response = probe_response(|sample| allpass.tick(sample), 4096, 101);
+        for magnitude in response.magnitude_db.iter() {
+            assert!(magnitude.abs() < 1e-6);
+        }
+        assert!((response.phase[20] + PI / 2.0).abs() < 1e-6);
+    }
+
     #[test]
     #[allow(clippy::float_cmp)]
     fn test_delay_line() {
@@ -0,0 +1,357 @@
+//! ITU-T G.711 μ-law and A-law companding, as used in telephony and `.au` files.
+//!
+//! Each 8-bit code holds a sign, a 3-bit segment and a 4-bit step within the segment, giving
+//! roughly 14 bits (μ-law) or 13 bits (A-law) of dynamic range. Encoding and decoding are table
+//! lookups, with tables built at compile time from the G.191 reference algorithms.
+//!
+//! ```
+//! use synthrs::g711::{alaw_to_linear, linear_to_alaw, linear_to_ulaw, ulaw_to_linear};
+//!
+//! assert_eq!(linear_to_ulaw(0), 0xff);
+//! assert_eq!(ulaw_to_linear(linear_to_ulaw(1000)), 988);
+//! assert_eq!(linear_to_alaw(0), 0xd5);
+//! assert_eq!(alaw_to_linear(linear_to_alaw(1000)), 1008);
+//! ```
+//!
+//! To write 8kHz G.711 WAV files, see `crate::writer::write_wav_g711`.
+
+const SIGN_BIT: u8 = 0x80;
+const QUANT_MASK: u8 = 0x0f;
+const SEG_SHIFT: u8 = 4;
+const SEG_MASK: u8 = 0x70;
+
+/// Added to μ-law magnitudes so that segment ends fall on powers of two
+const ULAW_BIAS: i32 = 0x84;
+/// Largest biased 14-bit magnitude, the end of the last segment
+const ULAW_CLIP: i32 = 0x1fff;
+
+/// Ends of the μ-law segments for biased 14-bit magnitudes
+const ULAW_SEGMENT_ENDS: [i32; 8] = [0x3f, 0x7f, 0xff, 0x1ff, 0x3ff, 0x7ff, 0xfff, 0x1fff];
+/// Ends of the A-law segments for 13-bit magnitudes
+const ALAW_SEGMENT_ENDS: [i32; 8] = [0x1f, 0x3f, 0x7f, 0xff, 0x1ff, 0x3ff, 0x7ff, 0xfff];
+
+/// μ-law codes indexed by the top 14 bits of a 16-bit sample
+static ULAW_ENCODE: [u8; 1 << 14] = build_ulaw_encode_table();
+/// A-law codes indexed by the top 13 bits of a 16-bit sample
+static ALAW_ENCODE: [u8; 1 << 13] = build_alaw_encode_table();
+static ULAW_DECODE: [i16; 256] = build_ulaw_decode_table();
+static ALAW_DECODE: [i16; 256] = build_alaw_decode_table();
+
+/// The two G.711 companding laws.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Law {
+    /// μ-law, used in North America and Japan
+    MuLaw,
+    /// A-law, used in Europe and elsewhere
+    ALaw,
+}
+
+impl Law {
+    pub fn encode(self, sample: i16) -> u8 {
+        match self {
+            Law::MuLaw => linear_to_ulaw(sample),
+            Law::ALaw => linear_to_alaw(sample),
+        }
+    }
+
+    pub fn decode(self, code: u8) -> i16 {
+        match self {
+            Law::MuLaw => ulaw_to_linear(code),
+            Law::ALaw => alaw_to_linear(code),
+        }
+    }
+}
+
+/// Encodes a signed 16-bit sample as μ-law. Only the top 14 bits are used.
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    ULAW_ENCODE[(sample >> 2) as u16 as usize & 0x3fff]
+}
+
+/// Decodes a μ-law code to a signed 16-bit sample.
+pub fn ulaw_to_linear(code: u8) -> i16 {
+    ULAW_DECODE[code as usize]
+}
+
+/// Encodes a signed 16-bit sample as A-law. Only the top 13 bits are used.
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    ALAW_ENCODE[(sample >> 3) as u16 as usize & 0x1fff]
+}
+
+/// Decodes an A-law code to a signed 16-bit sample.
+pub fn alaw_to_linear(code: u8) -> i16 {
+    ALAW_DECODE[code as usize]
+}
+
+const fn segment(value: i32, ends: &[i32; 8]) -> usize {
+    let mut segment = 0;
+    while segment < 8 && value > ends[segment] {
+        segment += 1;
+    }
+    segment
+}
+
+const fn encode_ulaw(sample: i16) -> u8 {
+    // Negative samples use the one's complement, so that -1 encodes as negative zero
+    let magnitude = if sample < 0 { !sample } else { sample };
+    let mut value = ((magnitude as i32) >> 2) + (ULAW_BIAS >> 2);
+    if value > ULAW_CLIP {
+        value = ULAW_CLIP;
+    }
+    let segment = segment(value, &ULAW_SEGMENT_ENDS);
+
+    let code = ((segment as u8) << SEG_SHIFT) | ((value >> (segment + 1)) as u8 & QUANT_MASK);
+    let mask = if sample < 0 { 0x7f } else { 0xff };
+    code ^ mask
+}
+
+const fn decode_ulaw(code: u8) -> i16 {
+    let code = !code;
+    let mut value = (((code & QUANT_MASK) as i32) << 3) + ULAW_BIAS;
+    value <<= (code & SEG_MASK) >> SEG_SHIFT;
+
+    if code & SIGN_BIT != 0 {
+        (ULAW_BIAS - value) as i16
+    } else {
+        (value - ULAW_BIAS) as i16
+    }
+}
+
+const fn encode_alaw(sample: i16) -> u8 {
+    // Negative samples use the one's complement
+    let (value, mask) = if sample >= 0 {
+        ((sample >> 3) as i32, 0xd5)
+    } else {
+        ((!sample >> 3) as i32, 0x55)
+    };
+
+    let segment = segment(value, &ALAW_SEGMENT_ENDS);
+    if segment >= 8 {
+        return 0x7f ^ mask;
+    }
+
+    let step = if segment < 2 {
+        value >> 1
+    } else {
+        value >> segment
+    };
+    let code = ((segment as u8) << SEG_SHIFT) | (step as u8 & QUANT_MASK);
+    code ^ mask
+}
+
+const fn decode_alaw(code: u8) -> i16 {
+    let code = code ^ 0x55;
+    let value = ((code & QUANT_MASK) as i32) << 4;
+    let value = match (code & SEG_MASK) >> SEG_SHIFT {
+        0 => value + 8,
+        1 => value + 0x108,
+        segment => (value + 0x108) << (segment - 1),
+    };
+
+    if code & SIGN_BIT != 0 {
+        value as i16
+    } else {
+        -value as i16
+    }
+}
+
+const fn build_ulaw_encode_table() -> [u8; 1 << 14] {
+    let mut table = [0u8; 1 << 14];
+    let mut index = 0;
+    while index < table.len() {
+        table[index] = encode_ulaw((index << 2) as u16 as i16);
+        index += 1;
+    }
+    table
+}
+
+const fn build_alaw_encode_table() -> [u8; 1 << 13] {
+    let mut table = [0u8; 1 << 13];
+    let mut index = 0;
+    while index < table.len() {
+        table[index] = encode_alaw((index << 3) as u16 as i16);
+        index += 1;
+    }
+    table
+}
+
+const fn build_ulaw_decode_table() -> [i16; 256] {
+    let mut table = [0i16; 256];
+    let mut code = 0;
+    while code < 256 {
+        table[code] = decode_ulaw(code as u8);
+        code += 1;
+    }
+    table
+}
+
+const fn build_alaw_decode_table() -> [i16; 256] {
+    let mut table = [0i16; 256];
+    let mut code = 0;
+    while code < 256 {
+        table[code] = decode_alaw(code as u8);
+        code += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ulaw() {
+        assert_eq!(linear_to_ulaw(0), 0xff);
+        assert_eq!(linear_to_ulaw(-1), 0x7f);
+        assert_eq!(linear_to_ulaw(1000), 0xce);
+        assert_eq!(linear_to_ulaw(i16::MAX), 0x80);
+        assert_eq!(linear_to_ulaw(i16::MIN), 0x00);
+        assert_eq!(ulaw_to_linear(0xff), 0);
+        assert_eq!(ulaw_to_linear(0x80), 32_124);
+        assert_eq!(ulaw_to_linear(0x00), -32_124);
+
+        // Decoded values are reproduced exactly
+        for code in 0..=255u8 {
+            let sample = ulaw_to_linear(code);
+            // 0x7f and 0xff both decode to zero
+            let expected = if code == 0x7f { 0xff } else { code };
+            assert_eq!(linear_to_ulaw(sample), expected);
+        }
+    }
+
+    #[test]
+    fn test_alaw() {
+        assert_eq!(linear_to_alaw(0), 0xd5);
+        assert_eq!(linear_to_alaw(-1), 0x55);
+        assert_eq!(linear_to_alaw(1000), 0xfa);
+        assert_eq!(linear_to_alaw(i16::MAX), 0xaa);
+        assert_eq!(linear_to_alaw(i16::MIN), 0x2a);
+        assert_eq!(alaw_to_linear(0xd5), 8);
+        assert_eq!(alaw_to_linear(0x55), -8);
+        assert_eq!(alaw_to_linear(0xaa), 32_256);
+
+        for code in 0..=255u8 {
+            assert_eq!(linear_to_alaw(alaw_to_linear(code)), code);
+        }
+    }
+
+    // Decode tables and segment boundary encodings from the ITU-T G.191 reference
+    // implementation, which encodes negative samples as their one's complement
+    #[rustfmt::skip]
+    const ULAW_TO_LINEAR: [i16; 256] = [
+        -32124, -31100, -30076, -29052, -28028, -27004, -25980, -24956,
+        -23932, -22908, -21884, -20860, -19836, -18812, -17788, -16764,
+        -15996, -15484, -14972, -14460, -13948, -13436, -12924, -12412,
+        -11900, -11388, -10876, -10364, -9852, -9340, -8828, -8316,
+        -7932, -7676, -7420, -7164, -6908, -6652, -6396, -6140,
+        -5884, -5628, -5372, -5116, -4860, -4604, -4348, -4092,
+        -3900, -3772, -3644, -3516, -3388, -3260, -3132, -3004,
+        -2876, -2748, -2620, -2492, -2364, -2236, -2108, -1980,
+        -1884, -1820, -1756, -1692, -1628, -1564, -1500, -1436,
+        -1372, -1308, -1244, -1180, -1116, -1052, -988, -924,
+        -876, -844, -812, -780, -748, -716, -684, -652,
+        -620, -588, -556, -524, -492, -460, -428, -396,
+        -372, -356, -340, -324, -308, -292, -276, -260,
+        -244, -228, -212, -196, -180, -164, -148, -132,
+        -120, -112, -104, -96, -88, -80, -72, -64,
+        -56, -48, -40, -32, -24, -16, -8, 0,
+        32124, 31100, 30076, 29052, 28028, 27004, 25980, 24956,
+        23932, 22908, 21884, 20860, 19836, 18812, 17788, 16764,
+        15996, 15484, 14972, 14460, 13948, 13436, 12924, 12412,
+        11900, 11388, 10876, 10364, 9852, 9340, 8828, 8316,
+        7932, 7676, 7420, 7164, 6908, 6652, 6396, 6140,
+        5884, 5628, 5372, 5116, 4860, 4604, 4348, 4092,
+        3900, 3772, 3644, 3516, 3388, 3260, 3132, 3004,
+        2876, 2748, 2620, 2492, 2364, 2236, 2108, 1980,
+        1884, 1820, 1756, 1692, 1628, 1564, 1500, 1436,
+        1372, 1308, 1244, 1180, 1116, 1052, 988, 924,
+        876, 844, 812, 780, 748, 716, 684, 652,
+        620, 588, 556, 524, 492, 460, 428, 396,
+        372, 356, 340, 324, 308, 292, 276, 260,
+        244, 228, 212, 196, 180, 164, 148, 132,
+        120, 112, 104, 96, 88, 80, 72, 64,
+        56, 48, 40, 32, 24, 16, 8, 0,
+    ];
+
+    #[rustfmt::skip]
+    const ALAW_TO_LINEAR: [i16; 256] = [
+        -5504, -5248, -6016, -5760, -4480, -4224, -4992, -4736,
+        -7552, -7296, -8064, -7808, -6528, -6272, -7040, -6784,
+        -2752, -2624, -3008, -2880, -2240, -2112, -2496, -2368,
+        -3776, -3648, -4032, -3904, -3264, -3136, -3520, -3392,
+        -22016, -20992, -24064, -23040, -17920, -16896, -19968, -18944,
+        -30208, -29184, -32256, -31232, -26112, -25088, -28160, -27136,
+        -11008, -10496, -12032, -11520, -8960, -8448, -9984, -9472,
+        -15104, -14592, -16128, -15616, -13056, -12544, -14080, -13568,
+        -344, -328, -376, -360, -280, -264, -312, -296,
+        -472, -456, -504, -488, -408, -392, -440, -424,
+        -88, -72, -120, -104, -24, -8, -56, -40,
+        -216, -200, -248, -232, -152, -136, -184, -168,
+        -1376, -1312, -1504, -1440, -1120, -1056, -1248, -1184,
+        -1888, -1824, -2016, -1952, -1632, -1568, -1760, -1696,
+        -688, -656, -752, -720, -560, -528, -624, -592,
+        -944, -912, -1008, -976, -816, -784, -880, -848,
+        5504, 5248, 6016, 5760, 4480, 4224, 4992, 4736,
+        7552, 7296, 8064, 7808, 6528, 6272, 7040, 6784,
+        2752, 2624, 3008, 2880, 2240, 2112, 2496, 2368,
+        3776, 3648, 4032, 3904, 3264, 3136, 3520, 3392,
+        22016, 20992, 24064, 23040, 17920, 16896, 19968, 18944,
+        30208, 29184, 32256, 31232, 26112, 25088, 28160, 27136,
+        11008, 10496, 12032, 11520, 8960, 8448, 9984, 9472,
+        15104, 14592, 16128, 15616, 13056, 12544, 14080, 13568,
+        344, 328, 376, 360, 280, 264, 312, 296,
+        472, 456, 504, 488, 408, 392, 440, 424,
+        88, 72, 120, 104, 24, 8, 56, 40,
+        216, 200, 248, 232, 152, 136, 184, 168,
+        1376, 1312, 1504, 1440, 1120, 1056, 1248, 1184,
+        1888, 1824, 2016, 1952, 1632, 1568, 1760, 1696,
+        688, 656, 752, 720, 560, 528, 624, 592,
+        944, 912, 1008, 976, 816, 784, 880, 848,
+    ];
+
+    #[test]
+    fn test_known_answers() {
+        for code in 0..=255u8 {
+            assert_eq!(
+                ulaw_to_linear(code),
+                ULAW_TO_LINEAR[code as usize],
+                "{:#04x}",
+                code
+            );
+            assert_eq!(
+                alaw_to_linear(code),
+                ALAW_TO_LINEAR[code as usize],
+                "{:#04x}",
+                code
+            );
+        }
+
+        // The last sample of each segment and the first sample of the next
+        #[rustfmt::skip]
+        let ulaw_boundaries = [
+            (123, 0xf0), (124, 0xef), (-124, 0x70), (-125, 0x6f),
+            (379, 0xe0), (380, 0xdf), (-380, 0x60), (-381, 0x5f),
+            (891, 0xd0), (892, 0xcf), (-892, 0x50), (-893, 0x4f),
+            (1915, 0xc0), (1916, 0xbf), (-1916, 0x40), (-1917, 0x3f),
+            (3963, 0xb0), (3964, 0xaf), (-3964, 0x30), (-3965, 0x2f),
+            (8059, 0xa0), (8060, 0x9f), (-8060, 0x20), (-8061, 0x1f),
+            (16251, 0x90), (16252, 0x8f), (-16252, 0x10), (-16253, 0x0f),
+        ];
+        for &(sample, code) in &ulaw_boundaries {
+            assert_eq!(linear_to_ulaw(sample), code, "{}", sample);
+        }
+
+        #[rustfmt::skip]
+        let alaw_boundaries = [
+            (255, 0xda), (256, 0xc5), (-256, 0x5a), (-257, 0x45),
+            (511, 0xca), (512, 0xf5), (-512, 0x4a), (-513, 0x75),
+            (1023, 0xfa), (1024, 0xe5), (-1024, 0x7a), (-1025, 0x65),
+            (2047, 0xea), (2048, 0x95), (-2048, 0x6a), (-2049, 0x15),
+            (4095, 0x9a), (4096, 0x85), (-4096, 0x1a), (-4097, 0x05),
+            (8191, 0x8a), (8192, 0xb5), (-8192, 0x0a), (-8193, 0x35),
+            (16383, 0xba), (16384, 0xa5), (-16384, 0x3a), (-16385, 0x25),
+        ];
+        for &(sample, code) in &alaw_boundaries {
+            assert_eq!(linear_to_alaw(sample), code, "{}", sample);
+        }
+    }
+}
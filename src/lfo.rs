@@ -0,0 +1,200 @@
+//! Low-frequency oscillators for modulating parameters over time.
+//!
+//! An `Lfo` runs at the sample rate, one `tick` per sample, and outputs values in [-1.0, 1.0].
+//! It is either free-running at a rate in hertz, or synced to a tempo using
+//! `crate::delay::NoteValue`.
+//!
+//! ```
+//! use synthrs::delay::NoteValue;
+//! use synthrs::lfo::{Lfo, LfoShape};
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sawtooth_wave;
+//!
+//! let samples = make_samples(0.5, 44_100, sawtooth_wave(220.0));
+//!
+//! // Tremolo, once every eighth note at 120BPM
+//! let mut lfo = Lfo::new(LfoShape::Triangle, 1.0, 44_100);
+//! lfo.set_tempo(120.0, NoteValue::Eighth);
+//!
+//! let tremolo: Vec<f64> = samples
+//!     .into_iter()
+//!     .map(|sample| sample * (0.75 + 0.25 * lfo.tick()))
+//!     .collect();
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::delay::NoteValue;
+
+/// LFO waveforms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    /// Rising sawtooth
+    Saw,
+    Square,
+    /// A new random value each cycle, held for the whole cycle
+    SampleAndHold,
+    /// Random values each cycle, smoothly interpolated between
+    SmoothRandom,
+}
+
+fn random_bipolar() -> f64 {
+    rand::random::<f64>() * 2.0 - 1.0
+}
+
+/// A stateful low-frequency oscillator.
+#[derive(Clone, Debug)]
+pub struct Lfo {
+    phase: f64,
+    held: f64,
+    next: f64,
+    sample_rate: usize,
+    pub shape: LfoShape,
+    /// Rate in hertz
+    pub rate: f64,
+    /// Offset of the waveform in cycles, from 0.0 to 1.0. Ignored by the random shapes, which
+    /// pick new values at the start of each cycle.
+    pub phase_offset: f64,
+}
+
+impl Lfo {
+    pub fn new(shape: LfoShape, rate: f64, sample_rate: usize) -> Lfo {
+        Lfo {
+            phase: 0.0,
+            held: random_bipolar(),
+            next: random_bipolar(),
+            sample_rate,
+            shape,
+            rate,
+            phase_offset: 0.0,
+        }
+    }
+
+    /// Sets the rate to one cycle per note value at `bpm` beats per minute.
+    pub fn set_tempo(&mut self, bpm: f64, note: NoteValue) {
+        self.rate = 1.0 / note.seconds(bpm);
+    }
+
+    /// Moves the LFO to where it would be `time` seconds after starting, such as the current
+    /// position in a song, so that tempo-synced LFOs stay in time.
+    pub fn sync(&mut self, time: f64) {
+        self.phase = (time * self.rate).rem_euclid(1.0);
+    }
+
+    /// Restarts the LFO at the beginning of its cycle.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// Current output in [-1.0, 1.0], without advancing.
+    pub fn value(&self) -> f64 {
+        let phase = (self.phase + self.phase_offset).rem_euclid(1.0);
+
+        match self.shape {
+            LfoShape::Sine => (2.0 * PI * phase).sin(),
+            LfoShape::Triangle => {
+                if phase < 0.25 {
+                    4.0 * phase
+                } else if phase < 0.75 {
+                    2.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 4.0
+                }
+            }
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::SampleAndHold => self.held,
+            // Interpolated over the unoffset phase, so the curve stays continuous when
+            // `next` is picked
+            LfoShape::SmoothRandom => {
+                let t = (1.0 - (PI * self.phase).cos()) / 2.0;
+                self.held + (self.next - self.held) * t
+            }
+        }
+    }
+
+    /// Returns the current output, then advances by one sample.
+    pub fn tick(&mut self) -> f64 {
+        let output = self.value();
+
+        self.phase += self.rate / self.sample_rate as f64;
+        if self.phase >= 1.0 {
+            self.phase = self.phase.fract();
+            self.held = match self.shape {
+                LfoShape::SmoothRandom => self.next,
+                _ => random_bipolar(),
+            };
+            self.next = random_bipolar();
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycle(shape: LfoShape) -> Vec<f64> {
+        let mut lfo = Lfo::new(shape, 1.0, 8);
+        (0..8).map(|_| lfo.tick()).collect()
+    }
+
+    #[test]
+    fn test_shapes() {
+        let sine = cycle(LfoShape::Sine);
+        assert!(sine[0].abs() < 1e-12 && (sine[2] - 1.0).abs() < 1e-12);
+        assert_eq!(
+            cycle(LfoShape::Triangle),
+            vec![0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5]
+        );
+        assert_eq!(
+            cycle(LfoShape::Saw),
+            vec![-1.0, -0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75]
+        );
+        assert_eq!(
+            cycle(LfoShape::Square),
+            vec![1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn test_random_shapes() {
+        let mut lfo = Lfo::new(LfoShape::SampleAndHold, 1.0, 8);
+        let values: Vec<f64> = (0..32).map(|_| lfo.tick()).collect();
+        for held in values.chunks(8) {
+            assert!(held
+                .iter()
+                .all(|&x| x == held[0] && (-1.0..=1.0).contains(&x)));
+        }
+
+        // Continuous across cycles
+        let mut lfo = Lfo::new(LfoShape::SmoothRandom, 1.0, 1000);
+        let values: Vec<f64> = (0..5000).map(|_| lfo.tick()).collect();
+        assert!(values.windows(2).all(|w| (w[1] - w[0]).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_phase_offset_and_sync() {
+        let mut lfo = Lfo::new(LfoShape::Saw, 2.0, 100);
+        lfo.phase_offset = 0.25;
+        assert!((lfo.tick() + 0.5).abs() < 1e-12);
+
+        lfo.set_tempo(120.0, NoteValue::Quarter);
+        assert!((lfo.rate - 2.0).abs() < 1e-12);
+        lfo.phase_offset = 0.0;
+        lfo.sync(1.25);
+        assert!(lfo.value().abs() < 1e-12);
+
+        lfo.reset();
+        assert!((lfo.value() + 1.0).abs() < 1e-12);
+    }
+}
@@ -1,9 +1,23 @@
 #![allow(dead_code)]
 
+pub mod analysis;
+pub mod automation;
+pub mod delay;
+pub mod distortion;
+pub mod dynamics;
+pub mod eq;
 pub mod errors;
+pub mod fft;
 pub mod filter;
+pub mod g711;
+pub mod lfo;
+pub mod metadata;
 pub mod midi;
+pub mod modulation;
 pub mod music;
+pub mod processor;
+pub mod reverb;
+pub mod riff;
 pub mod sample;
 pub mod synthesizer;
 pub mod wave;
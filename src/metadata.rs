@@ -0,0 +1,587 @@
+//! Metadata stored in WAV files: `LIST/INFO` tags, Broadcast WAV `bext` descriptions, `cue `
+//! markers and `smpl` sampler loops.
+//!
+//! Metadata is read into `crate::writer::Wave::metadata` by `read_wav`, and written with
+//! `crate::writer::write_wav_data_with_metadata` or `WavWriter::new_with_metadata`.
+//!
+//! ```
+//! use synthrs::metadata::{CuePoint, SampleLoop, SamplerInfo, WavMetadata};
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sine_wave;
+//! use synthrs::writer::{read_wav, write_wav_data_with_metadata, PcmData, SampleFormat, WavSpec};
+//!
+//! let samples = make_samples(1.0, 44_100, sine_wave(440.0));
+//!
+//! let mut metadata = WavMetadata::default();
+//! metadata.info.title = Some("A4 sine".to_string());
+//! metadata.info.software = Some("synthrs".to_string());
+//! metadata.cue_points.push(CuePoint::new(1, 22_050, "Middle"));
+//!
+//! // A4, looping the whole second
+//! let mut sampler = SamplerInfo::new(44_100, 69);
+//! sampler.loops.push(SampleLoop::forward(0, 0, 44_099));
+//! metadata.sampler = Some(sampler);
+//!
+//! let mut output = Vec::new();
+//! write_wav_data_with_metadata(
+//!     &mut output,
+//!     &WavSpec::new(1, 44_100),
+//!     &PcmData::from_f64(&samples, SampleFormat::I16),
+//!     &metadata,
+//! ).unwrap();
+//!
+//! let wave = read_wav(&mut &output[..]).unwrap();
+//! assert_eq!(wave.metadata, metadata);
+//! ```
+
+use std::io::{Error, ErrorKind, Read, Result};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::riff::RiffChunk;
+
+/// Text tags from a `LIST` chunk of type `INFO`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InfoTags {
+    /// `INAM`
+    pub title: Option<String>,
+    /// `IART`
+    pub artist: Option<String>,
+    /// `ISFT`
+    pub software: Option<String>,
+    /// `ICMT`
+    pub comment: Option<String>,
+    /// Any other tags, such as `ICRD` (creation date) or `IGNR` (genre)
+    pub other: Vec<([u8; 4], String)>,
+}
+
+impl InfoTags {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.artist.is_none()
+            && self.software.is_none()
+            && self.comment.is_none()
+            && self.other.is_empty()
+    }
+
+    fn tags(&self) -> Vec<([u8; 4], &str)> {
+        let named = [
+            (*b"INAM", &self.title),
+            (*b"IART", &self.artist),
+            (*b"ISFT", &self.software),
+            (*b"ICMT", &self.comment),
+        ];
+        named
+            .iter()
+            .filter_map(|(id, value)| value.as_ref().map(|value| (*id, value.as_str())))
+            .chain(self.other.iter().map(|(id, value)| (*id, value.as_str())))
+            .collect()
+    }
+
+    fn set(&mut self, id: [u8; 4], value: String) {
+        match &id {
+            b"INAM" => self.title = Some(value),
+            b"IART" => self.artist = Some(value),
+            b"ISFT" => self.software = Some(value),
+            b"ICMT" => self.comment = Some(value),
+            _ => self.other.push((id, value)),
+        }
+    }
+}
+
+/// A Broadcast WAV Format `bext` chunk (EBU Tech 3285).
+///
+/// Loudness fields of version 2 chunks are not kept, and are written as zero.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bext {
+    /// Up to 256 characters
+    pub description: String,
+    /// Up to 32 characters
+    pub originator: String,
+    /// Up to 32 characters
+    pub originator_reference: String,
+    /// `yyyy-mm-dd`
+    pub origination_date: String,
+    /// `hh:mm:ss`
+    pub origination_time: String,
+    /// Position of the first sample in samples since midnight
+    pub time_reference: u64,
+    pub version: u16,
+    /// SMPTE UMID, up to 64 bytes. Padded with zeros when written, and read back without
+    /// trailing zeros, so a 32-byte basic UMID or no UMID at all round-trips.
+    pub umid: Vec<u8>,
+    pub coding_history: String,
+}
+
+/// A marker from a `cue ` chunk, with its label from a `LIST/adtl` chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CuePoint {
+    pub id: u32,
+    /// Position in frames from the start of the data
+    pub position: u32,
+    pub label: Option<String>,
+}
+
+impl CuePoint {
+    pub fn new(id: u32, position: u32, label: &str) -> CuePoint {
+        CuePoint {
+            id,
+            position,
+            label: Some(label.to_string()),
+        }
+    }
+}
+
+/// Direction a `SampleLoop` plays in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopType {
+    Forward,
+    /// Alternating forward and backward
+    PingPong,
+    Backward,
+    Other(u32),
+}
+
+impl LoopType {
+    fn from_u32(value: u32) -> LoopType {
+        match value {
+            0 => LoopType::Forward,
+            1 => LoopType::PingPong,
+            2 => LoopType::Backward,
+            other => LoopType::Other(other),
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        match self {
+            LoopType::Forward => 0,
+            LoopType::PingPong => 1,
+            LoopType::Backward => 2,
+            LoopType::Other(other) => other,
+        }
+    }
+}
+
+/// A loop from a `smpl` chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleLoop {
+    /// Usually matches a `CuePoint` id
+    pub id: u32,
+    pub loop_type: LoopType,
+    /// First frame of the loop
+    pub start: u32,
+    /// Last frame of the loop, inclusive
+    pub end: u32,
+    /// Fraction of a frame to adjust the end by, out of 2^32
+    pub fraction: u32,
+    /// 0 loops forever
+    pub play_count: u32,
+}
+
+impl SampleLoop {
+    /// A forward loop that repeats forever.
+    pub fn forward(id: u32, start: u32, end: u32) -> SampleLoop {
+        SampleLoop {
+            id,
+            loop_type: LoopType::Forward,
+            start,
+            end,
+            fraction: 0,
+            play_count: 0,
+        }
+    }
+}
+
+/// Sampler settings from a `smpl` chunk.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SamplerInfo {
+    /// MMA manufacturer code, 0 if none
+    pub manufacturer: u32,
+    pub product: u32,
+    /// Length of a sample in nanoseconds
+    pub sample_period: u32,
+    /// MIDI note played back at the original pitch
+    pub midi_unity_note: u32,
+    /// Fraction of a semitone above the unity note, out of 2^32
+    pub midi_pitch_fraction: u32,
+    pub smpte_format: u32,
+    pub smpte_offset: u32,
+    pub loops: Vec<SampleLoop>,
+    /// Manufacturer specific data
+    pub sampler_data: Vec<u8>,
+}
+
+impl SamplerInfo {
+    pub fn new(sample_rate: usize, midi_unity_note: u32) -> SamplerInfo {
+        SamplerInfo {
+            sample_period: (1e9 / sample_rate as f64).round() as u32,
+            midi_unity_note,
+            ..SamplerInfo::default()
+        }
+    }
+}
+
+/// All metadata understood in a WAV file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WavMetadata {
+    pub info: InfoTags,
+    pub bext: Option<Bext>,
+    pub cue_points: Vec<CuePoint>,
+    pub sampler: Option<SamplerInfo>,
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Text up to the first NUL.
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn trim_trailing_zeros(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |last| last + 1);
+    &bytes[..end]
+}
+
+/// Text in a fixed-size field, truncated or padded with NULs.
+fn write_fixed_string(bytes: &mut Vec<u8>, value: &[u8], length: usize) {
+    let value = &value[..value.len().min(length)];
+    bytes.extend_from_slice(value);
+    bytes.resize(bytes.len() + length - value.len(), 0);
+}
+
+/// NUL-terminated text, as stored in `INFO` and `labl` chunks.
+fn terminated_string(value: &str) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+/// Splits the data of a `LIST` chunk, after its type, into subchunks.
+fn list_subchunks(mut bytes: &[u8]) -> Result<Vec<RiffChunk>> {
+    let mut chunks = Vec::new();
+    while bytes.len() >= 8 {
+        let mut id = [0u8; 4];
+        bytes.read_exact(&mut id)?;
+        let size = bytes.read_u32::<LittleEndian>()? as usize;
+        if size > bytes.len() {
+            return Err(invalid_data("LIST subchunk is truncated"));
+        }
+        chunks.push(RiffChunk::new(id, bytes[..size].to_vec()));
+        bytes = &bytes[(size + size % 2).min(bytes.len())..];
+    }
+    Ok(chunks)
+}
+
+fn list_chunk(list_type: &[u8; 4], subchunks: &[RiffChunk]) -> RiffChunk {
+    let mut data = list_type.to_vec();
+    for subchunk in subchunks {
+        data.extend(subchunk.to_bytes());
+    }
+    RiffChunk::new(*b"LIST", data)
+}
+
+impl WavMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.info.is_empty()
+            && self.bext.is_none()
+            && self.cue_points.is_empty()
+            && self.sampler.is_none()
+    }
+
+    /// Reads a chunk into the metadata, returning `false` if it is not a metadata chunk.
+    ///
+    /// If the chunk is malformed an error is returned and the metadata is left unchanged.
+    pub fn read_chunk(&mut self, chunk: &RiffChunk) -> Result<bool> {
+        let mut metadata = self.clone();
+        let is_metadata = metadata.parse_chunk(chunk)?;
+        *self = metadata;
+        Ok(is_metadata)
+    }
+
+    fn parse_chunk(&mut self, chunk: &RiffChunk) -> Result<bool> {
+        match &chunk.id {
+            b"LIST" if chunk.data.starts_with(b"INFO") => {
+                for tag in list_subchunks(&chunk.data[4..])? {
+                    self.info.set(tag.id, read_string(&tag.data));
+                }
+            }
+            b"LIST" if chunk.data.starts_with(b"adtl") => {
+                for label in list_subchunks(&chunk.data[4..])? {
+                    if &label.id != b"labl" || label.data.len() < 4 {
+                        continue;
+                    }
+                    let id = (&label.data[..4]).read_u32::<LittleEndian>()?;
+                    let text = read_string(&label.data[4..]);
+                    match self.cue_points.iter_mut().find(|cue| cue.id == id) {
+                        Some(cue) => cue.label = Some(text),
+                        None => self.cue_points.push(CuePoint {
+                            id,
+                            position: 0,
+                            label: Some(text),
+                        }),
+                    }
+                }
+            }
+            b"cue " => {
+                let mut reader = &chunk.data[..];
+                let count = reader.read_u32::<LittleEndian>()?;
+                for _ in 0..count {
+                    let id = reader.read_u32::<LittleEndian>()?;
+                    let _position = reader.read_u32::<LittleEndian>()?; // Playlist position
+                    let mut _data_chunk_id = [0u8; 4];
+                    reader.read_exact(&mut _data_chunk_id)?;
+                    let _chunk_start = reader.read_u32::<LittleEndian>()?;
+                    let _block_start = reader.read_u32::<LittleEndian>()?;
+                    let sample_offset = reader.read_u32::<LittleEndian>()?;
+
+                    // Labels may have been read first
+                    match self.cue_points.iter_mut().find(|cue| cue.id == id) {
+                        Some(cue) => cue.position = sample_offset,
+                        None => self.cue_points.push(CuePoint {
+                            id,
+                            position: sample_offset,
+                            label: None,
+                        }),
+                    }
+                }
+            }
+            b"bext" => {
+                if chunk.data.len() < 602 {
+                    return Err(invalid_data("bext chunk is too short"));
+                }
+                let data = &chunk.data;
+                self.bext = Some(Bext {
+                    description: read_string(&data[0..256]),
+                    originator: read_string(&data[256..288]),
+                    originator_reference: read_string(&data[288..320]),
+                    origination_date: read_string(&data[320..330]),
+                    origination_time: read_string(&data[330..338]),
+                    time_reference: (&data[338..346]).read_u64::<LittleEndian>()?,
+                    version: (&data[346..348]).read_u16::<LittleEndian>()?,
+                    umid: trim_trailing_zeros(&data[348..412]).to_vec(),
+                    coding_history: read_string(&data[602..]),
+                });
+            }
+            b"smpl" => {
+                let mut reader = &chunk.data[..];
+                let mut sampler = SamplerInfo {
+                    manufacturer: reader.read_u32::<LittleEndian>()?,
+                    product: reader.read_u32::<LittleEndian>()?,
+                    sample_period: reader.read_u32::<LittleEndian>()?,
+                    midi_unity_note: reader.read_u32::<LittleEndian>()?,
+                    midi_pitch_fraction: reader.read_u32::<LittleEndian>()?,
+                    smpte_format: reader.read_u32::<LittleEndian>()?,
+                    smpte_offset: reader.read_u32::<LittleEndian>()?,
+                    ..SamplerInfo::default()
+                };
+                let loop_count = reader.read_u32::<LittleEndian>()?;
+                let sampler_data_size = reader.read_u32::<LittleEndian>()? as usize;
+                for _ in 0..loop_count {
+                    sampler.loops.push(SampleLoop {
+                        id: reader.read_u32::<LittleEndian>()?,
+                        loop_type: LoopType::from_u32(reader.read_u32::<LittleEndian>()?),
+                        start: reader.read_u32::<LittleEndian>()?,
+                        end: reader.read_u32::<LittleEndian>()?,
+                        fraction: reader.read_u32::<LittleEndian>()?,
+                        play_count: reader.read_u32::<LittleEndian>()?,
+                    });
+                }
+                sampler.sampler_data = reader[..sampler_data_size.min(reader.len())].to_vec();
+                self.sampler = Some(sampler);
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// The metadata as chunks, in the order `bext`, `LIST/INFO`, `cue `, `LIST/adtl`, `smpl`.
+    pub fn to_chunks(&self) -> Vec<RiffChunk> {
+        let mut chunks = Vec::new();
+
+        if let Some(ref bext) = self.bext {
+            let mut data = Vec::with_capacity(602 + bext.coding_history.len());
+            write_fixed_string(&mut data, bext.description.as_bytes(), 256);
+            write_fixed_string(&mut data, bext.originator.as_bytes(), 32);
+            write_fixed_string(&mut data, bext.originator_reference.as_bytes(), 32);
+            write_fixed_string(&mut data, bext.origination_date.as_bytes(), 10);
+            write_fixed_string(&mut data, bext.origination_time.as_bytes(), 8);
+            data.extend_from_slice(&bext.time_reference.to_le_bytes());
+            data.extend_from_slice(&bext.version.to_le_bytes());
+            write_fixed_string(&mut data, &bext.umid, 64);
+            // Loudness values and reserved space
+            data.resize(602, 0);
+            data.extend_from_slice(bext.coding_history.as_bytes());
+            chunks.push(RiffChunk::new(*b"bext", data));
+        }
+
+        if !self.info.is_empty() {
+            let tags: Vec<RiffChunk> = self
+                .info
+                .tags()
+                .into_iter()
+                .map(|(id, value)| RiffChunk::new(id, terminated_string(value)))
+                .collect();
+            chunks.push(list_chunk(b"INFO", &tags));
+        }
+
+        if !self.cue_points.is_empty() {
+            let mut data = (self.cue_points.len() as u32).to_le_bytes().to_vec();
+            for cue in &self.cue_points {
+                data.extend_from_slice(&cue.id.to_le_bytes());
+                data.extend_from_slice(&cue.position.to_le_bytes());
+                data.extend_from_slice(b"data");
+                data.extend_from_slice(&[0; 8]); // Chunk and block start
+                data.extend_from_slice(&cue.position.to_le_bytes());
+            }
+            chunks.push(RiffChunk::new(*b"cue ", data));
+
+            let labels: Vec<RiffChunk> = self
+                .cue_points
+                .iter()
+                .filter_map(|cue| {
+                    cue.label.as_ref().map(|label| {
+                        let mut data = cue.id.to_le_bytes().to_vec();
+                        data.extend(terminated_string(label));
+                        RiffChunk::new(*b"labl", data)
+                    })
+                })
+                .collect();
+            if !labels.is_empty() {
+                chunks.push(list_chunk(b"adtl", &labels));
+            }
+        }
+
+        if let Some(ref sampler) = self.sampler {
+            let mut data = Vec::with_capacity(36 + 24 * sampler.loops.len());
+            for value in &[
+                sampler.manufacturer,
+                sampler.product,
+                sampler.sample_period,
+                sampler.midi_unity_note,
+                sampler.midi_pitch_fraction,
+                sampler.smpte_format,
+                sampler.smpte_offset,
+                sampler.loops.len() as u32,
+                sampler.sampler_data.len() as u32,
+            ] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            for sample_loop in &sampler.loops {
+                for value in &[
+                    sample_loop.id,
+                    sample_loop.loop_type.to_u32(),
+                    sample_loop.start,
+                    sample_loop.end,
+                    sample_loop.fraction,
+                    sample_loop.play_count,
+                ] {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&sampler.sampler_data);
+            chunks.push(RiffChunk::new(*b"smpl", data));
+        }
+
+        chunks
+    }
+
+    /// The metadata chunks as stored in a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_chunks()
+            .iter()
+            .flat_map(|chunk| chunk.to_bytes())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let metadata = WavMetadata {
+            info: InfoTags {
+                title: Some("Title".to_string()),
+                artist: Some("Artist".to_string()),
+                software: None,
+                comment: Some("Odd".to_string()),
+                other: vec![(*b"ICRD", "2024-01-01".to_string())],
+            },
+            bext: Some(Bext {
+                description: "Description".to_string(),
+                originator: "synthrs".to_string(),
+                originator_reference: "ref".to_string(),
+                origination_date: "2024-01-01".to_string(),
+                origination_time: "12:00:00".to_string(),
+                time_reference: 0x1_0000_0001,
+                version: 1,
+                umid: vec![7; 32],
+                coding_history: "A=PCM,F=48000,W=24,M=stereo\r\n".to_string(),
+            }),
+            cue_points: vec![
+                CuePoint::new(1, 100, "Attack"),
+                CuePoint {
+                    id: 2,
+                    position: 200,
+                    label: None,
+                },
+            ],
+            sampler: Some(SamplerInfo {
+                loops: vec![
+                    SampleLoop::forward(1, 100, 199),
+                    SampleLoop {
+                        id: 2,
+                        loop_type: LoopType::PingPong,
+                        start: 0,
+                        end: 50,
+                        fraction: 0x8000_0000,
+                        play_count: 3,
+                    },
+                ],
+                sampler_data: vec![1, 2, 3],
+                ..SamplerInfo::new(48_000, 60)
+            }),
+        };
+
+        let chunks = metadata.to_chunks();
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|chunk| chunk.to_bytes().len() % 2 == 0));
+
+        let mut read = WavMetadata::default();
+        for chunk in &chunks {
+            assert!(read.read_chunk(chunk).unwrap());
+        }
+        assert_eq!(read, metadata);
+        assert!(!read.read_chunk(&RiffChunk::new(*b"JUNK", vec![])).unwrap());
+
+        // Empty fields
+        let empty = WavMetadata {
+            bext: Some(Bext::default()),
+            ..WavMetadata::default()
+        };
+        let mut read_empty = WavMetadata::default();
+        for chunk in &empty.to_chunks() {
+            assert!(read_empty.read_chunk(chunk).unwrap());
+        }
+        assert_eq!(read_empty, empty);
+
+        // The last cue point is missing
+        let mut cue = chunks
+            .iter()
+            .find(|chunk| &chunk.id == b"cue ")
+            .unwrap()
+            .clone();
+        cue.data[0] += 1;
+        assert!(read.read_chunk(&cue).is_err());
+        assert_eq!(read, metadata);
+        assert!(WavMetadata::default().is_empty());
+    }
+}
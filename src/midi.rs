@@ -386,7 +386,7 @@ where
         let mut value = (octet & 0b0111_1111) as usize;
         while octet >= 0b1000_0000 {
             octet = self.reader.read_u8()?;
-            value = (value << 7) as usize + (octet & 0b0111_1111) as usize;
+            value = (value << 7) + (octet & 0b0111_1111) as usize;
         }
 
         Ok(value)
@@ -510,7 +510,7 @@ where
     for track in &song.tracks {
         for event in &track.events {
             if let Some(MetaEventType::TempoSetting) = event.meta_event_type {
-                song.bpm = (60_000_000.0 / event.value1 as f64) as f64;
+                song.bpm = 60_000_000.0 / event.value1 as f64;
                 break;
             }
         }
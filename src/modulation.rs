@@ -0,0 +1,740 @@
+//! Modulation effects: chorus, flanger, vibrato and phaser, and a modulation matrix.
+//!
+//! These are stateful effects built on `crate::filter::FractionalDelayLine` and
+//! `crate::filter::FirstOrderAllPass`, modulated by a sine `crate::lfo::Lfo`. Like
+//! `crate::filter::Comb`, they transform one sample at a time with `tick`.
+//!
+//! ```
+//! use synthrs::modulation::{Chorus, Flanger, Phaser, Vibrato};
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sawtooth_wave;
+//!
+//! let samples = make_samples(0.5, 44_100, sawtooth_wave(220.0));
+//!
+//! // 3 voices, 20ms delay, swept by 3ms at 0.8Hz
+//! let mut chorus = Chorus::new(44_100, 3, 0.02, 0.003, 0.8);
+//! // 2ms delay, swept by 1.5ms at 0.25Hz, with 0.7 feedback
+//! let mut flanger = Flanger::new(44_100, 0.002, 0.001_5, 0.25, 0.7);
+//! // Swept by 2ms at 5Hz
+//! let mut vibrato = Vibrato::new(44_100, 0.002, 5.0);
+//! // 4 stages swept between 200Hz and 2kHz at 0.5Hz, with 0.5 feedback
+//! let mut phaser = Phaser::new(44_100, 4, 200.0, 2000.0, 0.5, 0.5);
+//!
+//! let processed: Vec<f64> = samples
+//!     .into_iter()
+//!     .map(|sample| phaser.tick(vibrato.tick(flanger.tick(chorus.tick(sample)))))
+//!     .collect();
+//! ```
+
+use crate::filter::{Adsr, FirstOrderAllPass, FractionalDelayLine, Interpolation};
+use crate::lfo::{Lfo, LfoShape};
+use crate::midi::{EventType, MidiEvent};
+use crate::processor::{decay_tail, Processor};
+
+// Sine LFO starting at its lowest point, `phase` cycles in
+fn sine_lfo(phase: f64, rate: f64, sample_rate: usize) -> Lfo {
+    let mut lfo = Lfo::new(LfoShape::Sine, rate, sample_rate);
+    lfo.phase_offset = 0.75 + phase;
+    lfo
+}
+
+// Ticks `lfo` at `rate`, which may have been changed since the last tick, with unipolar output
+// in [0, 1]
+fn tick_unipolar(lfo: &mut Lfo, rate: f64) -> f64 {
+    lfo.rate = rate;
+    0.5 + 0.5 * lfo.tick()
+}
+
+/// A stateful multi-voice chorus. Each voice reads from a shared delay line with its own
+/// modulated delay, spread evenly in LFO phase across voices.
+#[derive(Clone, Debug)]
+pub struct Chorus {
+    delay_line: FractionalDelayLine,
+    lfos: Vec<Lfo>,
+    sample_rate: usize,
+    /// Centre delay in seconds
+    pub delay: f64,
+    /// Delay modulation depth in seconds
+    pub depth: f64,
+    /// LFO rate in hertz
+    pub rate: f64,
+    /// Wet (chorused) signal multiplier, 0.5 by default
+    pub wet: f64,
+    /// Dry (original) signal multiplier, 0.5 by default
+    pub dry: f64,
+}
+
+impl Chorus {
+    /// Creates a new chorus with `voices` voices. Each voice's delay is swept between
+    /// `delay - depth / 2` and `delay + depth / 2` seconds at `rate` hertz.
+    pub fn new(sample_rate: usize, voices: usize, delay: f64, depth: f64, rate: f64) -> Chorus {
+        let voices = voices.max(1);
+
+        Chorus {
+            delay_line: FractionalDelayLine::new(
+                (delay + depth) * 2.0,
+                sample_rate,
+                Interpolation::Cubic,
+            ),
+            lfos: (0..voices)
+                .map(|i| sine_lfo(i as f64 / voices as f64, rate, sample_rate))
+                .collect(),
+            sample_rate,
+            delay,
+            depth,
+            rate,
+            wet: 0.5,
+            dry: 0.5,
+        }
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let voices = self.lfos.len() as f64;
+        let mut wet = 0.0;
+
+        for lfo in self.lfos.iter_mut() {
+            let modulation = tick_unipolar(lfo, self.rate) - 0.5;
+            let delay = (self.delay + self.depth * modulation) * self.sample_rate as f64;
+            wet += self.delay_line.read_at(delay);
+        }
+
+        self.delay_line.write(input);
+
+        self.dry * input + self.wet * wet / voices
+    }
+}
+
+impl Processor for Chorus {
+    fn tick(&mut self, input: f64) -> f64 {
+        Chorus::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+        self.lfos.iter_mut().for_each(|lfo| lfo.reset());
+    }
+
+    fn tail_length(&self) -> usize {
+        ((self.delay + self.depth / 2.0) * self.sample_rate as f64).ceil() as usize + 2
+    }
+}
+
+/// A stateful flanger: a short, swept delay mixed with the original signal, with feedback.
+#[derive(Clone, Debug)]
+pub struct Flanger {
+    delay_line: FractionalDelayLine,
+    lfo: Lfo,
+    sample_rate: usize,
+    /// Minimum delay in seconds
+    pub delay: f64,
+    /// Delay modulation depth in seconds
+    pub depth: f64,
+    /// LFO rate in hertz
+    pub rate: f64,
+    /// Feedback multiplier in (-1, 1). Negative values give a hollower sound.
+    pub feedback: f64,
+    /// Wet (flanged) signal multiplier, 0.5 by default
+    pub wet: f64,
+    /// Dry (original) signal multiplier, 0.5 by default
+    pub dry: f64,
+}
+
+impl Flanger {
+    /// Creates a new flanger. The delay is swept between `delay` and `delay + depth` seconds at
+    /// `rate` hertz.
+    pub fn new(sample_rate: usize, delay: f64, depth: f64, rate: f64, feedback: f64) -> Flanger {
+        Flanger {
+            delay_line: FractionalDelayLine::new(
+                (delay + depth) * 2.0,
+                sample_rate,
+                Interpolation::Cubic,
+            ),
+            lfo: sine_lfo(0.0, rate, sample_rate),
+            sample_rate,
+            delay,
+            depth,
+            rate,
+            feedback,
+            wet: 0.5,
+            dry: 0.5,
+        }
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let modulation = tick_unipolar(&mut self.lfo, self.rate);
+        self.delay_line
+            .set_delay(self.delay + self.depth * modulation);
+
+        let delayed = self.delay_line.read();
+        self.delay_line.write(input + self.feedback * delayed);
+
+        self.dry * input + self.wet * delayed
+    }
+}
+
+impl Processor for Flanger {
+    fn tick(&mut self, input: f64) -> f64 {
+        Flanger::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+        self.lfo.reset();
+    }
+
+    fn tail_length(&self) -> usize {
+        decay_tail(
+            ((self.delay + self.depth) * self.sample_rate as f64).ceil() + 2.0,
+            self.feedback,
+        )
+    }
+}
+
+/// A stateful vibrato: a fully-wet, swept delay which modulates the pitch of the input.
+#[derive(Clone, Debug)]
+pub struct Vibrato {
+    delay_line: FractionalDelayLine,
+    lfo: Lfo,
+    sample_rate: usize,
+    /// Delay modulation depth in seconds
+    pub depth: f64,
+    /// LFO rate in hertz
+    pub rate: f64,
+}
+
+impl Vibrato {
+    /// Creates a new vibrato. The delay is swept over `depth` seconds at `rate` hertz.
+    pub fn new(sample_rate: usize, depth: f64, rate: f64) -> Vibrato {
+        Vibrato {
+            delay_line: FractionalDelayLine::new(depth * 2.0, sample_rate, Interpolation::Cubic),
+            lfo: sine_lfo(0.0, rate, sample_rate),
+            sample_rate,
+            depth,
+            rate,
+        }
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let modulation = tick_unipolar(&mut self.lfo, self.rate);
+        // Offset by the minimum readable delay so that a depth of 0 is a plain delay
+        let delay = 2.0 + self.depth * modulation * self.sample_rate as f64;
+        self.delay_line.set_delay_samples(delay);
+        self.delay_line.tick(input)
+    }
+}
+
+impl Processor for Vibrato {
+    fn tick(&mut self, input: f64) -> f64 {
+        Vibrato::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+        self.lfo.reset();
+    }
+
+    fn tail_length(&self) -> usize {
+        (self.depth * self.sample_rate as f64).ceil() as usize + 4
+    }
+}
+
+/// A stateful multi-stage phaser. A chain of first-order all-pass filters with swept
+/// frequencies is mixed with the original signal, creating moving notches.
+#[derive(Clone, Debug)]
+pub struct Phaser {
+    stages: Vec<FirstOrderAllPass>,
+    lfo: Lfo,
+    sample_rate: usize,
+    last_output: f64,
+    /// Lowest all-pass frequency in hertz
+    pub min_frequency: f64,
+    /// Highest all-pass frequency in hertz
+    pub max_frequency: f64,
+    /// LFO rate in hertz
+    pub rate: f64,
+    /// Feedback multiplier in (-1, 1), which deepens the notches
+    pub feedback: f64,
+    /// Wet (phased) signal multiplier, 0.5 by default
+    pub wet: f64,
+    /// Dry (original) signal multiplier, 0.5 by default
+    pub dry: f64,
+}
+
+impl Phaser {
+    /// Creates a new phaser with `stages` all-pass stages (use an even number: each pair creates
+    /// one notch), swept exponentially between `min_frequency` and `max_frequency` at `rate`
+    /// hertz. Panics unless `0 < min_frequency <= max_frequency < sample_rate / 2`.
+    pub fn new(
+        sample_rate: usize,
+        stages: usize,
+        min_frequency: f64,
+        max_frequency: f64,
+        rate: f64,
+        feedback: f64,
+    ) -> Phaser {
+        assert!(0.0 < min_frequency && min_frequency <= max_frequency);
+        assert!(max_frequency < sample_rate as f64 / 2.0);
+
+        Phaser {
+            stages: (0..stages)
+                .map(|_| FirstOrderAllPass::new(min_frequency, sample_rate))
+                .collect(),
+            lfo: sine_lfo(0.0, rate, sample_rate),
+            sample_rate,
+            last_output: 0.0,
+            min_frequency,
+            max_frequency,
+            rate,
+            feedback,
+            wet: 0.5,
+            dry: 0.5,
+        }
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let modulation = tick_unipolar(&mut self.lfo, self.rate);
+        let frequency =
+            self.min_frequency * (self.max_frequency / self.min_frequency).powf(modulation);
+        // Every stage shares the same frequency
+        let coefficient = FirstOrderAllPass::coefficient_for(frequency, self.sample_rate);
+
+        let mut output = input + self.feedback * self.last_output;
+        for stage in self.stages.iter_mut() {
+            stage.coefficient = coefficient;
+            output = stage.tick(output);
+        }
+        self.last_output = output;
+
+        self.dry * input + self.wet * output
+    }
+}
+
+impl Processor for Phaser {
+    fn tick(&mut self, input: f64) -> f64 {
+        Phaser::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        self.stages.iter_mut().for_each(|stage| stage.reset());
+        self.lfo.reset();
+        self.last_output = 0.0;
+    }
+
+    fn tail_length(&self) -> usize {
+        // Stages ring longest at whichever end of the sweep has the largest coefficient
+        let stage_tail = [self.min_frequency, self.max_frequency]
+            .iter()
+            .map(|&frequency| FirstOrderAllPass::new(frequency, self.sample_rate).tail_length())
+            .max()
+            .unwrap_or(0);
+        let stages_tail = stage_tail.saturating_mul(self.stages.len());
+        decay_tail(stages_tail as f64, self.feedback)
+    }
+}
+
+/// Sources of modulation for a `ModMatrix`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModSource {
+    /// Output of an LFO, by index, in [-1.0, 1.0]
+    Lfo(usize),
+    /// Output of an envelope, by index, in [0.0, 1.0]
+    Envelope(usize),
+    /// Velocity of the last note, in [0.0, 1.0]
+    Velocity,
+    /// Value of a MIDI continuous controller, in [0.0, 1.0]
+    ControlChange(u8),
+    /// Channel or polyphonic aftertouch, in [0.0, 1.0]
+    Aftertouch,
+}
+
+impl ModSource {
+    fn is_bipolar(self) -> bool {
+        matches!(self, ModSource::Lfo(_))
+    }
+}
+
+/// Range a source is mapped to before scaling by a route's depth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Polarity {
+    /// [0.0, 1.0]
+    Unipolar,
+    /// [-1.0, 1.0]
+    Bipolar,
+}
+
+/// A connection from a modulation source to a named destination parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub destination: String,
+    /// Amount of modulation, in the destination parameter's units. Negative depths invert.
+    pub depth: f64,
+    pub polarity: Polarity,
+}
+
+/// A modulation matrix, routing LFOs, envelopes, velocity, MIDI CCs and aftertouch to named
+/// destination parameters.
+///
+/// Call `tick` once per sample to advance the LFOs and envelopes, then apply the modulation to
+/// each parameter with `modulate`.
+///
+/// ```
+/// use synthrs::filter::Adsr;
+/// use synthrs::lfo::{Lfo, LfoShape};
+/// use synthrs::modulation::{ModMatrix, ModSource, Polarity};
+/// use synthrs::wave::sine_wave;
+///
+/// let mut matrix = ModMatrix::new();
+/// let vibrato = matrix.add_lfo(Lfo::new(LfoShape::Sine, 5.0, 44_100));
+/// let envelope = matrix.add_envelope(Adsr::new(0.01, 0.2, 0.5, 0.3, 44_100));
+///
+/// // ±4Hz of vibrato, and the envelope and velocity controlling the level
+/// matrix.route(ModSource::Lfo(vibrato), "frequency", 4.0, Polarity::Bipolar);
+/// matrix.route(ModSource::Envelope(envelope), "level", 1.0, Polarity::Unipolar);
+/// matrix.route(ModSource::Velocity, "level", -0.5, Polarity::Unipolar);
+///
+/// matrix.note_on(100);
+/// let samples: Vec<f64> = (0..44_100)
+///     .map(|i| {
+///         matrix.tick();
+///         let frequency = matrix.modulate("frequency", 440.0);
+///         let level = matrix.modulate("level", 0.5);
+///         level * sine_wave(frequency)(i as f64 / 44_100.0)
+///     })
+///     .collect();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ModMatrix {
+    routes: Vec<ModRoute>,
+    velocity: f64,
+    controllers: Vec<f64>,
+    aftertouch: f64,
+    pub lfos: Vec<Lfo>,
+    pub envelopes: Vec<Adsr>,
+}
+
+impl Default for ModMatrix {
+    fn default() -> ModMatrix {
+        ModMatrix::new()
+    }
+}
+
+impl ModMatrix {
+    /// Creates an empty modulation matrix.
+    pub fn new() -> ModMatrix {
+        ModMatrix {
+            routes: Vec::new(),
+            velocity: 0.0,
+            controllers: vec![0.0; 128],
+            aftertouch: 0.0,
+            lfos: Vec::new(),
+            envelopes: Vec::new(),
+        }
+    }
+
+    /// Adds an LFO, returning its index for `ModSource::Lfo`.
+    pub fn add_lfo(&mut self, lfo: Lfo) -> usize {
+        self.lfos.push(lfo);
+        self.lfos.len() - 1
+    }
+
+    /// Adds an envelope, returning its index for `ModSource::Envelope`.
+    pub fn add_envelope(&mut self, envelope: Adsr) -> usize {
+        self.envelopes.push(envelope);
+        self.envelopes.len() - 1
+    }
+
+    /// Routes `source` to `destination`.
+    pub fn route(&mut self, source: ModSource, destination: &str, depth: f64, polarity: Polarity) {
+        self.routes.push(ModRoute {
+            source,
+            destination: destination.to_string(),
+            depth,
+            polarity,
+        });
+    }
+
+    pub fn routes(&self) -> &[ModRoute] {
+        &self.routes
+    }
+
+    /// Mutable access to routes, such as for changing depths.
+    pub fn routes_mut(&mut self) -> &mut Vec<ModRoute> {
+        &mut self.routes
+    }
+
+    /// Removes all routes to `destination`.
+    pub fn clear_destination(&mut self, destination: &str) {
+        self.routes.retain(|route| route.destination != destination);
+    }
+
+    /// Sets the velocity and triggers all envelopes.
+    pub fn note_on(&mut self, velocity: u8) {
+        self.velocity = f64::from(velocity.min(127)) / 127.0;
+        self.envelopes
+            .iter_mut()
+            .for_each(|envelope| envelope.note_on());
+    }
+
+    /// Releases all envelopes.
+    pub fn note_off(&mut self) {
+        self.envelopes
+            .iter_mut()
+            .for_each(|envelope| envelope.note_off());
+    }
+
+    pub fn set_control_change(&mut self, controller: u8, value: u8) {
+        if let Some(slot) = self.controllers.get_mut(controller as usize) {
+            *slot = f64::from(value.min(127)) / 127.0;
+        }
+    }
+
+    pub fn set_aftertouch(&mut self, value: u8) {
+        self.aftertouch = f64::from(value.min(127)) / 127.0;
+    }
+
+    /// Updates velocity, envelopes, CCs and aftertouch from a MIDI event.
+    pub fn handle_event(&mut self, event: &MidiEvent) {
+        let value1 = event.value1.min(127) as u8;
+        let value2 = event.value2.unwrap_or(0).min(127) as u8;
+
+        if event.is_note_terminating() {
+            self.note_off();
+            return;
+        }
+
+        match event.event_type {
+            EventType::NoteOn => self.note_on(value2),
+            EventType::ControlChange => self.set_control_change(value1, value2),
+            EventType::ChannelPressure => self.set_aftertouch(value1),
+            EventType::PolyponicKeyPressure => self.set_aftertouch(value2),
+            _ => {}
+        }
+    }
+
+    /// Advances all LFOs and envelopes by one sample.
+    pub fn tick(&mut self) {
+        self.lfos.iter_mut().for_each(|lfo| {
+            lfo.tick();
+        });
+        self.envelopes.iter_mut().for_each(|envelope| {
+            envelope.tick();
+        });
+    }
+
+    /// Current value of a source, in its natural range.
+    pub fn source_value(&self, source: ModSource) -> f64 {
+        match source {
+            ModSource::Lfo(index) => self.lfos.get(index).map_or(0.0, |lfo| lfo.value()),
+            ModSource::Envelope(index) => self
+                .envelopes
+                .get(index)
+                .map_or(0.0, |envelope| envelope.value()),
+            ModSource::Velocity => self.velocity,
+            ModSource::ControlChange(controller) => self.controllers[controller as usize % 128],
+            ModSource::Aftertouch => self.aftertouch,
+        }
+    }
+
+    /// Sum of all modulation routed to `destination`.
+    pub fn value(&self, destination: &str) -> f64 {
+        self.routes
+            .iter()
+            .filter(|route| route.destination == destination)
+            .map(|route| {
+                let value = self.source_value(route.source);
+                let value = match (route.source.is_bipolar(), route.polarity) {
+                    (true, Polarity::Unipolar) => (value + 1.0) / 2.0,
+                    (false, Polarity::Bipolar) => value * 2.0 - 1.0,
+                    _ => value,
+                };
+                value * route.depth
+            })
+            .sum()
+    }
+
+    /// Applies the modulation routed to `destination` to a base parameter value.
+    pub fn modulate(&self, destination: &str, base: f64) -> f64 {
+        base + self.value(destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::probe_response;
+    use crate::lfo::LfoShape;
+    use crate::synthesizer::make_samples;
+    use crate::wave::sine_wave;
+
+    fn zero_crossing_intervals(samples: &[f64]) -> Vec<usize> {
+        let crossings: Vec<usize> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+            .map(|(i, _)| i)
+            .collect();
+        crossings.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    #[test]
+    fn test_chorus_without_depth_is_a_delay() {
+        let mut chorus = Chorus::new(1000, 3, 0.01, 0.0, 1.0);
+        chorus.wet = 1.0;
+        chorus.dry = 0.0;
+
+        let output: Vec<f64> = (0..20)
+            .map(|i| chorus.tick(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+        assert_eq!(output.iter().position(|x| x.abs() > 1e-9), Some(10));
+        assert!((output[10] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_chorus_voices_are_spread() {
+        let mut chorus = Chorus::new(44_100, 2, 0.02, 0.01, 1.0);
+        chorus.wet = 1.0;
+        chorus.dry = 0.0;
+
+        // Two voices half an LFO cycle apart smear an impulse over two different delays
+        let output: Vec<f64> = (0..2000)
+            .map(|i| chorus.tick(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+        let first = output.iter().position(|x| x.abs() > 1e-3).unwrap();
+        let last = output.iter().rposition(|x| x.abs() > 1e-3).unwrap();
+        assert!(last - first > 300);
+    }
+
+    #[test]
+    fn test_vibrato_modulates_pitch() {
+        let samples = make_samples(1.0, 44_100, sine_wave(441.0));
+
+        let mut vibrato = Vibrato::new(44_100, 0.0, 5.0);
+        let unmodulated: Vec<f64> = samples.iter().map(|&s| vibrato.tick(s)).collect();
+        let intervals = zero_crossing_intervals(&unmodulated);
+        assert!(intervals.iter().all(|&i| i == 100 || i == 99 || i == 101));
+
+        let mut vibrato = Vibrato::new(44_100, 0.002, 5.0);
+        let modulated: Vec<f64> = samples.iter().map(|&s| vibrato.tick(s)).collect();
+        let intervals = zero_crossing_intervals(&modulated);
+        let shortest = *intervals.iter().min().unwrap();
+        let longest = *intervals.iter().max().unwrap();
+        assert!(shortest < 98 && longest > 102);
+        assert!(modulated.iter().all(|x| x.abs() <= 1.001));
+    }
+
+    #[test]
+    fn test_flanger_feedback_creates_resonances() {
+        let mut flanger = Flanger::new(44_100, 0.001, 0.0, 0.0, 0.8);
+        flanger.wet = 1.0;
+        flanger.dry = 0.0;
+
+        let response = probe_response(|sample| flanger.tick(sample), 8192, 1001);
+        let peak = response
+            .magnitude_db
+            .iter()
+            .fold(f64::MIN, |acc, &x| acc.max(x));
+        // 1 / (1 - 0.8)
+        assert!((peak - 20.0 * 5.0f64.log10()).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_phaser_creates_notches() {
+        let mut phaser = Phaser::new(44_100, 4, 1000.0, 1000.0, 0.0, 0.0);
+        let response = probe_response(|sample| phaser.tick(sample), 8192, 1001);
+
+        let deepest = response
+            .magnitude_db
+            .iter()
+            .fold(f64::MAX, |acc, &x| acc.min(x));
+        assert!(deepest < -40.0);
+        assert!(response.magnitude_db[0].abs() < 1e-6);
+
+        // Fully wet, the phaser only shifts phase
+        let mut phaser = Phaser::new(44_100, 4, 1000.0, 1000.0, 0.0, 0.0);
+        phaser.wet = 1.0;
+        phaser.dry = 0.0;
+        let response = probe_response(|sample| phaser.tick(sample), 8192, 101);
+        assert!(response.magnitude_db.iter().all(|x| x.abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_mod_matrix() {
+        let mut matrix = ModMatrix::new();
+        let lfo = matrix.add_lfo(Lfo::new(LfoShape::Square, 1.0, 4));
+        let envelope = matrix.add_envelope(Adsr::new(0.0, 0.0, 0.5, 0.0, 4));
+
+        matrix.route(ModSource::Lfo(lfo), "cutoff", 100.0, Polarity::Bipolar);
+        matrix.route(ModSource::Lfo(lfo), "level", 0.5, Polarity::Unipolar);
+        matrix.route(ModSource::Velocity, "level", 1.0, Polarity::Bipolar);
+        matrix.route(
+            ModSource::ControlChange(1),
+            "cutoff",
+            -200.0,
+            Polarity::Unipolar,
+        );
+        matrix.route(
+            ModSource::Envelope(envelope),
+            "gain",
+            1.0,
+            Polarity::Unipolar,
+        );
+
+        assert_eq!(matrix.modulate("cutoff", 1000.0), 1100.0);
+        assert_eq!(matrix.value("level"), 0.5 - 1.0);
+        assert_eq!(matrix.value("unrouted"), 0.0);
+
+        matrix.note_on(127);
+        matrix.set_control_change(1, 127);
+        assert_eq!(matrix.value("cutoff"), 100.0 - 200.0);
+        assert_eq!(matrix.value("level"), 0.5 + 1.0);
+
+        // Square LFO flips halfway through its cycle, and the envelope reaches sustain
+        for _ in 0..3 {
+            matrix.tick();
+        }
+        assert_eq!(matrix.value("cutoff"), -100.0 - 200.0);
+        assert_eq!(matrix.value("level"), 1.0);
+        assert_eq!(matrix.value("gain"), 0.5);
+
+        matrix.clear_destination("cutoff");
+        assert_eq!(matrix.value("cutoff"), 0.0);
+    }
+
+    #[test]
+    fn test_mod_matrix_midi_events() {
+        let event = |event_type: EventType, value1: usize, value2: Option<usize>| MidiEvent {
+            event_type,
+            system_event_type: None,
+            meta_event_type: None,
+            time: 0,
+            channel: 0,
+            value1,
+            value2,
+        };
+
+        let mut matrix = ModMatrix::new();
+        let envelope = matrix.add_envelope(Adsr::new(0.0, 0.0, 1.0, 0.0, 100));
+        matrix.route(ModSource::Aftertouch, "pressure", 1.0, Polarity::Unipolar);
+        matrix.route(
+            ModSource::ControlChange(74),
+            "brightness",
+            1.0,
+            Polarity::Unipolar,
+        );
+
+        matrix.handle_event(&event(EventType::NoteOn, 60, Some(127)));
+        assert_eq!(matrix.source_value(ModSource::Velocity), 1.0);
+        matrix.tick();
+        assert_eq!(matrix.source_value(ModSource::Envelope(envelope)), 1.0);
+
+        matrix.handle_event(&event(EventType::ChannelPressure, 127, None));
+        matrix.handle_event(&event(EventType::ControlChange, 74, Some(127)));
+        assert_eq!(matrix.value("pressure"), 1.0);
+        assert_eq!(matrix.value("brightness"), 1.0);
+
+        matrix.handle_event(&event(EventType::NoteOn, 60, Some(0)));
+        matrix.tick();
+        assert_eq!(matrix.source_value(ModSource::Envelope(envelope)), 0.0);
+    }
+}
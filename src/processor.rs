@@ -0,0 +1,581 @@
+//! A common interface for stateful effects, and combinators for chaining them.
+//!
+//! Every stateful filter and effect in this crate implements `Processor`, so they can be
+//! composed without hand-writing closures. Stereo effects such as `crate::reverb::Freeverb`
+//! process mono samples through the trait.
+//!
+//! ```
+//! use synthrs::filter::{AllPass, Comb};
+//! use synthrs::processor::{Chain, Processor};
+//! use synthrs::reverb::Freeverb;
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sine_wave;
+//!
+//! let mut samples = make_samples(0.5, 44_100, sine_wave(440.0));
+//!
+//! let mut chain = Chain::new()
+//!     .with(Comb::new(0.2, 44_100, 0.5, 0.5, 0.5))
+//!     .with(AllPass::new(0.1, 44_100, 0.5).wet_dry(0.3, 1.0))
+//!     .with(Freeverb::new(44_100).bypassable());
+//!
+//! chain.process_block(&mut samples);
+//! ```
+
+use std::collections::VecDeque;
+
+/// A stateful processor which transforms one sample at a time.
+pub trait Processor {
+    /// Processes one sample.
+    fn tick(&mut self, input: f64) -> f64;
+
+    /// Processes a block of samples in place.
+    fn process_block(&mut self, block: &mut [f64]) {
+        for sample in block.iter_mut() {
+            *sample = self.tick(*sample);
+        }
+    }
+
+    /// Clears all internal state, such as delay lines and filter memory, as if newly created.
+    /// Parameters are kept.
+    fn reset(&mut self);
+
+    /// Number of samples the output is delayed by, such as by lookahead or block processing.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Number of samples the output may keep ringing for after the input falls silent,
+    /// including latency. This is `usize::MAX` if the output never decays, such as with
+    /// feedback of 1.0 or more.
+    fn tail_length(&self) -> usize {
+        0
+    }
+
+    /// Runs `self`, then `next`.
+    fn then<P: Processor>(self, next: P) -> Serial<Self, P>
+    where
+        Self: Sized,
+    {
+        Serial::new(self, next)
+    }
+
+    /// Runs `self` and `other` on the same input, summing their outputs.
+    fn parallel<P: Processor>(self, other: P) -> Parallel<Self, P>
+    where
+        Self: Sized,
+    {
+        Parallel::new(self, other)
+    }
+
+    /// Mixes the processed (wet) and original (dry) signals.
+    fn wet_dry(self, wet: f64, dry: f64) -> WetDry<Self>
+    where
+        Self: Sized,
+    {
+        WetDry::new(self, wet, dry)
+    }
+
+    /// Wraps the processor so it can be switched off.
+    fn bypassable(self) -> Bypass<Self>
+    where
+        Self: Sized,
+    {
+        Bypass::new(self)
+    }
+}
+
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    fn tick(&mut self, input: f64) -> f64 {
+        (**self).tick(input)
+    }
+
+    fn process_block(&mut self, block: &mut [f64]) {
+        (**self).process_block(block)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+
+    fn latency(&self) -> usize {
+        (**self).latency()
+    }
+
+    fn tail_length(&self) -> usize {
+        (**self).tail_length()
+    }
+}
+
+/// Number of samples for a feedback loop of `loop_length` samples and loop gain `gain` to decay
+/// by 60dB, or `usize::MAX` if it never decays.
+///
+/// ```
+/// use synthrs::processor::decay_tail;
+///
+/// assert_eq!(decay_tail(100.0, 0.0), 100);
+/// assert_eq!(decay_tail(100.0, 0.1), 400);
+/// assert_eq!(decay_tail(100.0, 1.0), usize::MAX);
+/// ```
+pub fn decay_tail(loop_length: f64, gain: f64) -> usize {
+    let gain = gain.abs();
+    if gain >= 1.0 {
+        return usize::MAX;
+    }
+
+    let repeats = if gain > 0.0 {
+        (0.001f64.ln() / gain.ln()).ceil()
+    } else {
+        0.0
+    };
+    (loop_length * (repeats + 1.0)).ceil() as usize
+}
+
+// Delays a signal to line it up with a processor's latency
+#[derive(Clone, Debug)]
+struct CompensationDelay {
+    buf: VecDeque<f64>,
+}
+
+impl CompensationDelay {
+    fn new() -> CompensationDelay {
+        CompensationDelay {
+            buf: VecDeque::new(),
+        }
+    }
+
+    fn tick(&mut self, input: f64, length: usize) -> f64 {
+        self.buf.resize(length, 0.0);
+        if length == 0 {
+            return input;
+        }
+        self.buf.push_back(input);
+        self.buf.pop_front().unwrap_or(0.0)
+    }
+
+    fn reset(&mut self) {
+        self.buf.iter_mut().for_each(|x| *x = 0.0);
+    }
+}
+
+/// Wraps a closure as a stateless `Processor`.
+///
+/// ```
+/// use synthrs::processor::{FnProcessor, Processor};
+///
+/// let mut gain = FnProcessor(|sample: f64| sample * 0.5);
+/// assert_eq!(gain.tick(1.0), 0.5);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FnProcessor<F>(pub F);
+
+impl<F: FnMut(f64) -> f64> Processor for FnProcessor<F> {
+    fn tick(&mut self, input: f64) -> f64 {
+        (self.0)(input)
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// A series of processors of any type, run one after another.
+#[derive(Default)]
+pub struct Chain {
+    processors: Vec<Box<dyn Processor>>,
+}
+
+impl Chain {
+    pub fn new() -> Chain {
+        Chain {
+            processors: Vec::new(),
+        }
+    }
+
+    /// Appends a processor to the end of the chain.
+    pub fn push<P: Processor + 'static>(&mut self, processor: P) {
+        self.processors.push(Box::new(processor));
+    }
+
+    /// Appends a processor to the end of the chain, builder-style.
+    pub fn with<P: Processor + 'static>(mut self, processor: P) -> Chain {
+        self.push(processor);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+}
+
+impl Processor for Chain {
+    fn tick(&mut self, input: f64) -> f64 {
+        self.processors
+            .iter_mut()
+            .fold(input, |sample, processor| processor.tick(sample))
+    }
+
+    fn process_block(&mut self, block: &mut [f64]) {
+        for processor in self.processors.iter_mut() {
+            processor.process_block(block);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.processors.iter_mut().for_each(|p| p.reset());
+    }
+
+    fn latency(&self) -> usize {
+        self.processors.iter().map(|p| p.latency()).sum()
+    }
+
+    fn tail_length(&self) -> usize {
+        self.processors
+            .iter()
+            .fold(0, |sum: usize, p| sum.saturating_add(p.tail_length()))
+    }
+}
+
+/// Two processors run one after another. See `Processor::then`.
+#[derive(Clone, Debug)]
+pub struct Serial<A, B> {
+    pub first: A,
+    pub second: B,
+}
+
+impl<A: Processor, B: Processor> Serial<A, B> {
+    pub fn new(first: A, second: B) -> Serial<A, B> {
+        Serial { first, second }
+    }
+}
+
+impl<A: Processor, B: Processor> Processor for Serial<A, B> {
+    fn tick(&mut self, input: f64) -> f64 {
+        self.second.tick(self.first.tick(input))
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.first.latency() + self.second.latency()
+    }
+
+    fn tail_length(&self) -> usize {
+        self.first
+            .tail_length()
+            .saturating_add(self.second.tail_length())
+    }
+}
+
+/// Two processors run on the same input, with their outputs summed. The processor with less
+/// latency is delayed to line up with the other. See `Processor::parallel`.
+#[derive(Clone, Debug)]
+pub struct Parallel<A, B> {
+    compensation: (CompensationDelay, CompensationDelay),
+    pub first: A,
+    pub second: B,
+}
+
+impl<A: Processor, B: Processor> Parallel<A, B> {
+    pub fn new(first: A, second: B) -> Parallel<A, B> {
+        Parallel {
+            compensation: (CompensationDelay::new(), CompensationDelay::new()),
+            first,
+            second,
+        }
+    }
+
+    // Extra delay for each processor
+    fn compensation_lengths(&self) -> (usize, usize) {
+        let latency = self.latency();
+        (
+            latency - self.first.latency(),
+            latency - self.second.latency(),
+        )
+    }
+}
+
+impl<A: Processor, B: Processor> Processor for Parallel<A, B> {
+    fn tick(&mut self, input: f64) -> f64 {
+        let (first_delay, second_delay) = self.compensation_lengths();
+        let first = self.first.tick(input);
+        let second = self.second.tick(input);
+
+        self.compensation.0.tick(first, first_delay)
+            + self.compensation.1.tick(second, second_delay)
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+        self.compensation.0.reset();
+        self.compensation.1.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.first.latency().max(self.second.latency())
+    }
+
+    fn tail_length(&self) -> usize {
+        let (first_delay, second_delay) = self.compensation_lengths();
+        self.first
+            .tail_length()
+            .saturating_add(first_delay)
+            .max(self.second.tail_length().saturating_add(second_delay))
+    }
+}
+
+/// Mixes a processor's output with its input. The dry signal is delayed to line up with the
+/// processor's latency. See `Processor::wet_dry`.
+#[derive(Clone, Debug)]
+pub struct WetDry<P> {
+    dry_delay: CompensationDelay,
+    pub processor: P,
+    /// Wet (processed) signal multiplier
+    pub wet: f64,
+    /// Dry (original) signal multiplier
+    pub dry: f64,
+}
+
+impl<P: Processor> WetDry<P> {
+    pub fn new(processor: P, wet: f64, dry: f64) -> WetDry<P> {
+        WetDry {
+            dry_delay: CompensationDelay::new(),
+            processor,
+            wet,
+            dry,
+        }
+    }
+}
+
+impl<P: Processor> Processor for WetDry<P> {
+    fn tick(&mut self, input: f64) -> f64 {
+        let latency = self.processor.latency();
+        let wet = self.processor.tick(input);
+        self.wet * wet + self.dry * self.dry_delay.tick(input, latency)
+    }
+
+    fn reset(&mut self) {
+        self.processor.reset();
+        self.dry_delay.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.processor.latency()
+    }
+
+    fn tail_length(&self) -> usize {
+        self.processor.tail_length()
+    }
+}
+
+/// A processor which can be switched off, passing its input through unchanged. The input is
+/// still delayed by the processor's latency when bypassed, so that switching doesn't shift the
+/// signal in time. See `Processor::bypassable`.
+#[derive(Clone, Debug)]
+pub struct Bypass<P> {
+    delay: CompensationDelay,
+    bypassed: bool,
+    pub processor: P,
+}
+
+impl<P: Processor> Bypass<P> {
+    pub fn new(processor: P) -> Bypass<P> {
+        Bypass {
+            delay: CompensationDelay::new(),
+            bypassed: false,
+            processor,
+        }
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Switches the processor off or on. The processor isn't run while bypassed, and is reset
+    /// when switched back on.
+    pub fn set_bypassed(&mut self, bypassed: bool) {
+        if self.bypassed && !bypassed {
+            self.processor.reset();
+        }
+        self.bypassed = bypassed;
+    }
+}
+
+impl<P: Processor> Processor for Bypass<P> {
+    fn tick(&mut self, input: f64) -> f64 {
+        let delayed = self.delay.tick(input, self.processor.latency());
+        if self.bypassed {
+            delayed
+        } else {
+            self.processor.tick(input)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.processor.reset();
+        self.delay.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.processor.latency()
+    }
+
+    fn tail_length(&self) -> usize {
+        if self.bypassed {
+            self.processor.latency()
+        } else {
+            self.processor.tail_length()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delay::{Echo, MultiTapDelay, Tap};
+    use crate::distortion::{Bitcrusher, Distortion, Oversampling, Waveshaper};
+    use crate::dynamics::{Compressor, Expander, Limiter, NoiseGate};
+    use crate::eq::{
+        BandType, Biquad, BiquadCoefficients, EqBand, GraphicEq, GraphicEqBands, ParametricEq,
+    };
+    use crate::filter::{
+        AllPass, Comb, DcBlocker, DelayLine, FirstOrderAllPass, FractionalDelayLine, Interpolation,
+    };
+    use crate::modulation::{Chorus, Flanger, Phaser, Vibrato};
+    use crate::reverb::{ConvolutionReverb, Freeverb, PartitionedConvolver};
+
+    fn impulse(i: usize) -> f64 {
+        if i == 0 {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    // Checks that resetting a used processor makes it behave like a fresh one
+    fn assert_resets<P: Processor + Clone>(processor: P) {
+        let mut fresh = processor.clone();
+        let mut used = processor;
+        for i in 0..2000 {
+            used.tick((i as f64 * 0.1).sin());
+        }
+        used.reset();
+
+        for i in 0..2000 {
+            let input = (i as f64 * 0.37).sin() * 0.8;
+            assert!((fresh.tick(input) - used.tick(input)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_all_processors_reset() {
+        let sample_rate = 8000;
+        let mut compressor = Compressor::new(sample_rate, -20.0, 4.0, 6.0, 0.001, 0.05);
+        compressor.set_lookahead(0.002);
+        let mut multi_tap = MultiTapDelay::new(sample_rate, 0.05, 0.5);
+        multi_tap.taps.push(Tap::new(0.01, 0.7, 0.0));
+        let mut eq = ParametricEq::new(sample_rate);
+        eq.add_band(EqBand::new(BandType::Peaking, 1000.0, 6.0, 1.0));
+
+        assert_resets(DelayLine::new(0.01, sample_rate));
+        assert_resets(FractionalDelayLine::new(
+            0.01,
+            sample_rate,
+            Interpolation::AllPass,
+        ));
+        assert_resets(FirstOrderAllPass::new(500.0, sample_rate));
+        assert_resets(DcBlocker::new(10.0, sample_rate));
+        assert_resets(AllPass::new(0.01, sample_rate, 0.5));
+        assert_resets(Comb::new(0.01, sample_rate, 0.5, 0.5, 0.5));
+        assert_resets(Biquad::new(BiquadCoefficients::lowpass(
+            500.0,
+            0.707,
+            sample_rate,
+        )));
+        assert_resets(eq);
+        assert_resets(GraphicEq::new(sample_rate, GraphicEqBands::Octave));
+        assert_resets(Chorus::new(sample_rate, 3, 0.01, 0.002, 1.0));
+        assert_resets(Flanger::new(sample_rate, 0.002, 0.001, 0.5, 0.5));
+        assert_resets(Vibrato::new(sample_rate, 0.002, 5.0));
+        assert_resets(Phaser::new(sample_rate, 4, 200.0, 2000.0, 0.5, 0.5));
+        assert_resets(PartitionedConvolver::new(&[1.0, 0.5, 0.25], 4));
+        assert_resets(ConvolutionReverb::new(
+            &[1.0, 0.5, 0.25],
+            sample_rate,
+            0.0,
+            0.5,
+            1.0,
+        ));
+        assert_resets(Freeverb::new(sample_rate));
+        assert_resets(compressor);
+        assert_resets(Limiter::new(sample_rate, -6.0, 0.002, 0.05));
+        assert_resets(Expander::new(sample_rate, -30.0, 2.0, 0.0, 0.001, 0.05));
+        assert_resets(NoiseGate::new(sample_rate, -30.0, 0.001, 0.01, 0.05));
+        assert_resets(Distortion::new(Waveshaper::Tanh, 4.0, Oversampling::X4));
+        assert_resets(Bitcrusher::new(sample_rate, 4.0, 2000.0));
+        assert_resets(Echo::new(sample_rate, 0.05, 0.5));
+        assert_resets(multi_tap);
+    }
+
+    #[test]
+    fn test_tail_length() {
+        let sample_rate = 1000;
+        let mut comb = Comb::new(0.01, sample_rate, 1.0, 0.0, 0.5);
+        let tail = comb.tail_length();
+        let output: Vec<f64> = (0..tail + 100).map(|i| comb.tick(impulse(i))).collect();
+        assert!(output[tail..].iter().all(|x| x.abs() < 1e-3));
+        assert!(output[..tail].iter().any(|x| x.abs() >= 1e-3));
+
+        let frozen = Comb::new(0.01, sample_rate, 1.0, 0.0, 1.0);
+        assert_eq!(frozen.tail_length(), usize::MAX);
+    }
+
+    #[test]
+    fn test_chain() {
+        let mut chain = Chain::new()
+            .with(DelayLine::new(0.004, 1000))
+            .with(FnProcessor(|sample: f64| sample * 0.5))
+            .with(Limiter::new(1000, 0.0, 0.003, 0.01));
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.latency(), 3);
+
+        let mut block: Vec<f64> = (0..20).map(impulse).collect();
+        chain.process_block(&mut block);
+        assert_eq!(block.iter().position(|&x| x != 0.0), Some(8));
+        assert_eq!(block[8], 0.5);
+
+        chain.reset();
+        let ticked: Vec<f64> = (0..20).map(|i| chain.tick(impulse(i))).collect();
+        assert_eq!(ticked, block);
+    }
+
+    #[test]
+    fn test_combinators() {
+        let gain = |gain: f64| FnProcessor(move |sample: f64| sample * gain);
+
+        let mut serial = gain(0.5).then(gain(3.0));
+        assert_eq!(serial.tick(1.0), 1.5);
+
+        // The branch without lookahead is delayed to line up with the limiter
+        let mut parallel = gain(1.0).parallel(Limiter::new(1000, 0.0, 0.005, 0.01));
+        assert_eq!(parallel.latency(), 5);
+        let output: Vec<f64> = (0..10).map(|i| parallel.tick(impulse(i))).collect();
+        assert_eq!(output.iter().position(|&x| x != 0.0), Some(5));
+        assert_eq!(output[5], 2.0);
+
+        let mut wet_dry = Limiter::new(1000, -6.0, 0.002, 0.01).wet_dry(0.0, 1.0);
+        let output: Vec<f64> = (0..10).map(|i| wet_dry.tick(impulse(i))).collect();
+        assert_eq!(output[2], 1.0);
+
+        let mut bypass = gain(0.5).bypassable();
+        assert_eq!(bypass.tick(1.0), 0.5);
+        bypass.set_bypassed(true);
+        assert!(bypass.is_bypassed());
+        assert_eq!(bypass.tick(1.0), 1.0);
+    }
+}
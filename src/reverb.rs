@@ -0,0 +1,737 @@
+//! Reverb effects.
+//!
+//! `ConvolutionReverb` convolves samples with a recorded impulse response, such as one loaded
+//! from a WAV file. Long impulse responses are handled with uniformly-partitioned FFT
+//! convolution, so multi-second halls can be rendered in reasonable time.
+//!
+//! ```
+//! use synthrs::reverb::ConvolutionReverb;
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sine_wave;
+//!
+//! // A synthetic, exponentially-decaying impulse response
+//! let impulse_response: Vec<f64> = (0..4410)
+//!     .map(|i| (-(i as f64) / 800.0).exp() * if i % 7 == 0 { 1.0 } else { -0.3 })
+//!     .collect();
+//!
+//! // 20ms pre-delay, 30% wet, 100% dry
+//! let mut reverb = ConvolutionReverb::new(&impulse_response, 44_100, 0.02, 0.3, 1.0);
+//!
+//! let samples = make_samples(0.1, 44_100, sine_wave(440.0));
+//! let reverberated = reverb.process(&samples);
+//! ```
+//!
+//! `Freeverb` is an algorithmic reverb built from `crate::filter::Comb` and
+//! `crate::filter::AllPass`, which needs no impulse response.
+//!
+//! ```
+//! use synthrs::reverb::Freeverb;
+//! use synthrs::synthesizer::make_samples;
+//! use synthrs::wave::sine_wave;
+//!
+//! let mut freeverb = Freeverb::new(44_100);
+//! freeverb.set_room_size(0.8);
+//! freeverb.set_wet(0.3);
+//! freeverb.set_dry(1.0);
+//!
+//! let samples = make_samples(0.1, 44_100, sine_wave(440.0));
+//! let stereo: Vec<(f64, f64)> = samples
+//!     .into_iter()
+//!     .map(|sample| freeverb.tick((sample, sample)))
+//!     .collect();
+//! ```
+
+use std::io::Result;
+
+use num::complex::Complex;
+
+use crate::fft::{fft, ifft, real_fft};
+use crate::filter::{AllPass, Comb};
+use crate::processor::Processor;
+use crate::sample::{resample, samples_from_wave};
+use crate::writer::read_wav_file;
+
+/// Block size used for partitioning impulse responses. This is also the latency of
+/// `ConvolutionReverb::tick` in samples.
+pub const CONVOLUTION_BLOCK_SIZE: usize = 512;
+
+/// A stateful FIR filter for long kernels, using uniformly-partitioned overlap-save FFT
+/// convolution. Output is delayed by `block_size` samples.
+///
+/// ```
+/// use synthrs::reverb::PartitionedConvolver;
+///
+/// let mut convolver = PartitionedConvolver::new(&[1.0, 0.5, 0.25], 4);
+/// let samples: Vec<f64> = vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+///
+/// let filtered: Vec<f64> = samples.into_iter().map(|s| convolver.tick(s)).collect();
+/// assert_eq!(filtered.len(), 8);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PartitionedConvolver {
+    block_size: usize,
+    partitions: Vec<Vec<Complex<f64>>>,
+    spectra: Vec<Vec<Complex<f64>>>,
+    spectra_index: usize,
+    input: Vec<f64>,
+    output: Vec<f64>,
+    position: usize,
+    accumulator: Vec<Complex<f64>>,
+}
+
+impl PartitionedConvolver {
+    /// Creates a new convolver for `kernel`. `block_size` must be a power of two.
+    pub fn new(kernel: &[f64], block_size: usize) -> PartitionedConvolver {
+        assert!(block_size.is_power_of_two());
+        let fft_length = 2 * block_size;
+
+        let mut partitions: Vec<Vec<Complex<f64>>> = kernel
+            .chunks(block_size)
+            .map(|partition| real_fft(partition, fft_length))
+            .collect();
+        if partitions.is_empty() {
+            partitions.push(vec![Complex::new(0.0, 0.0); fft_length]);
+        }
+
+        PartitionedConvolver {
+            block_size,
+            spectra: vec![vec![Complex::new(0.0, 0.0); fft_length]; partitions.len()],
+            partitions,
+            spectra_index: 0,
+            input: vec![0.0; fft_length],
+            output: vec![0.0; block_size],
+            position: 0,
+            accumulator: vec![Complex::new(0.0, 0.0); fft_length],
+        }
+    }
+
+    /// Latency of the convolver in samples.
+    pub fn latency(&self) -> usize {
+        self.block_size
+    }
+
+    /// Clears all internal buffers.
+    pub fn reset(&mut self) {
+        for spectrum in self.spectra.iter_mut() {
+            spectrum
+                .iter_mut()
+                .for_each(|x| *x = Complex::new(0.0, 0.0));
+        }
+        self.input.iter_mut().for_each(|x| *x = 0.0);
+        self.output.iter_mut().for_each(|x| *x = 0.0);
+        self.position = 0;
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        self.input[self.block_size + self.position] = input;
+        let output = self.output[self.position];
+
+        self.position += 1;
+        if self.position == self.block_size {
+            self.process_block();
+            self.position = 0;
+        }
+
+        output
+    }
+
+    fn process_block(&mut self) {
+        let count = self.partitions.len();
+
+        // Frequency-domain delay line: newest spectrum is at `spectra_index`
+        self.spectra_index = (self.spectra_index + count - 1) % count;
+        {
+            let spectrum = &mut self.spectra[self.spectra_index];
+            for (bin, &sample) in spectrum.iter_mut().zip(self.input.iter()) {
+                *bin = Complex::new(sample, 0.0);
+            }
+            fft(spectrum);
+        }
+
+        self.accumulator
+            .iter_mut()
+            .for_each(|x| *x = Complex::new(0.0, 0.0));
+        for (k, partition) in self.partitions.iter().enumerate() {
+            let spectrum = &self.spectra[(self.spectra_index + k) % count];
+            for ((acc, x), h) in self
+                .accumulator
+                .iter_mut()
+                .zip(spectrum.iter())
+                .zip(partition.iter())
+            {
+                *acc += x * h;
+            }
+        }
+        ifft(&mut self.accumulator);
+
+        // Overlap-save: the second half holds the valid output
+        for (out, bin) in self
+            .output
+            .iter_mut()
+            .zip(self.accumulator[self.block_size..].iter())
+        {
+            *out = bin.re;
+        }
+
+        self.input.copy_within(self.block_size.., 0);
+    }
+}
+
+impl Processor for PartitionedConvolver {
+    fn tick(&mut self, input: f64) -> f64 {
+        PartitionedConvolver::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        PartitionedConvolver::reset(self)
+    }
+
+    fn latency(&self) -> usize {
+        PartitionedConvolver::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        (self.partitions.len() + 1) * self.block_size
+    }
+}
+
+/// A stateful convolution reverb.
+///
+/// `tick` output (both wet and dry) is delayed by `latency()` samples. `process` compensates for
+/// this and also renders the reverb tail.
+#[derive(Clone, Debug)]
+pub struct ConvolutionReverb {
+    convolver: PartitionedConvolver,
+    dry_buffer: Vec<f64>,
+    dry_index: usize,
+    tail_length: usize,
+    /// Wet (reverberated) signal multiplier
+    pub wet: f64,
+    /// Dry (original) signal multiplier
+    pub dry: f64,
+}
+
+impl ConvolutionReverb {
+    /// Creates a new convolution reverb from an impulse response recorded at `sample_rate`.
+    /// The wet signal is delayed by `pre_delay` seconds.
+    pub fn new(
+        impulse_response: &[f64],
+        sample_rate: usize,
+        pre_delay: f64,
+        wet: f64,
+        dry: f64,
+    ) -> ConvolutionReverb {
+        let pre_delay_samples = (pre_delay.max(0.0) * sample_rate as f64).round() as usize;
+        let mut kernel = vec![0.0; pre_delay_samples];
+        kernel.extend_from_slice(impulse_response);
+
+        ConvolutionReverb {
+            convolver: PartitionedConvolver::new(&kernel, CONVOLUTION_BLOCK_SIZE),
+            dry_buffer: vec![0.0; CONVOLUTION_BLOCK_SIZE],
+            dry_index: 0,
+            tail_length: kernel.len().saturating_sub(1),
+            wet,
+            dry,
+        }
+    }
+
+    /// Loads an impulse response from a WAV file, resampling it to `sample_rate` if needed.
+    /// Only the first channel is used. The impulse response is trimmed of silence below -60dB
+    /// and normalised to unit energy.
+    ///
+    /// ```
+    /// use synthrs::reverb::ConvolutionReverb;
+    ///
+    /// let reverb =
+    ///     ConvolutionReverb::from_wave_file("./tests/assets/sine.wav", 22_050, 0.0, 0.3, 1.0)
+    ///         .unwrap();
+    /// ```
+    pub fn from_wave_file(
+        filename: &str,
+        sample_rate: usize,
+        pre_delay: f64,
+        wet: f64,
+        dry: f64,
+    ) -> Result<ConvolutionReverb> {
+        let wave = read_wav_file(filename)?;
+        let channels = wave.num_channels.max(1) as usize;
+        let file_sample_rate = wave.sample_rate as usize;
+        let (samples, _) = samples_from_wave(wave);
+
+        let first_channel: Vec<f64> = samples.into_iter().step_by(channels).collect();
+        let resampled = resample(&first_channel, file_sample_rate, sample_rate);
+        let impulse_response =
+            normalize_impulse_response(&trim_impulse_response(&resampled, -60.0));
+
+        Ok(ConvolutionReverb::new(
+            &impulse_response,
+            sample_rate,
+            pre_delay,
+            wet,
+            dry,
+        ))
+    }
+
+    /// Latency of `tick` in samples.
+    pub fn latency(&self) -> usize {
+        self.convolver.latency()
+    }
+
+    /// Number of samples the reverb keeps ringing for after the input stops.
+    pub fn tail_length(&self) -> usize {
+        self.tail_length
+    }
+
+    /// Clears the reverb tail.
+    pub fn reset(&mut self) {
+        self.convolver.reset();
+        self.dry_buffer.iter_mut().for_each(|x| *x = 0.0);
+        self.dry_index = 0;
+    }
+
+    pub fn tick(&mut self, input: f64) -> f64 {
+        let delayed_dry = self.dry_buffer[self.dry_index];
+        self.dry_buffer[self.dry_index] = input;
+        self.dry_index = (self.dry_index + 1) % self.dry_buffer.len();
+
+        self.dry * delayed_dry + self.wet * self.convolver.tick(input)
+    }
+
+    /// Processes a whole buffer of samples, returning `input.len() + tail_length()` samples
+    /// aligned with the input.
+    pub fn process(&mut self, input: &[f64]) -> Vec<f64> {
+        let latency = self.latency();
+        let padding = self.tail_length + latency;
+
+        input
+            .iter()
+            .cloned()
+            .chain(std::iter::repeat_n(0.0, padding))
+            .map(|sample| self.tick(sample))
+            .skip(latency)
+            .collect()
+    }
+}
+
+impl Processor for ConvolutionReverb {
+    fn tick(&mut self, input: f64) -> f64 {
+        ConvolutionReverb::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        ConvolutionReverb::reset(self)
+    }
+
+    fn latency(&self) -> usize {
+        ConvolutionReverb::latency(self)
+    }
+
+    fn tail_length(&self) -> usize {
+        ConvolutionReverb::tail_length(self) + self.latency()
+    }
+}
+
+/// Removes leading and trailing samples quieter than `threshold_db` relative to the peak
+/// of the impulse response.
+pub fn trim_impulse_response(impulse_response: &[f64], threshold_db: f64) -> Vec<f64> {
+    let peak = impulse_response
+        .iter()
+        .fold(0.0f64, |acc, sample| acc.max(sample.abs()));
+    let threshold = peak * 10.0f64.powf(threshold_db / 20.0);
+
+    let start = impulse_response
+        .iter()
+        .position(|sample| sample.abs() >= threshold && peak > 0.0);
+    let end = impulse_response
+        .iter()
+        .rposition(|sample| sample.abs() >= threshold && peak > 0.0);
+
+    match (start, end) {
+        (Some(start), Some(end)) => impulse_response[start..=end].to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+/// Scales an impulse response to unit energy, so that the reverb neither boosts nor attenuates
+/// broadband signals on average.
+pub fn normalize_impulse_response(impulse_response: &[f64]) -> Vec<f64> {
+    let energy = impulse_response
+        .iter()
+        .fold(0.0, |acc, sample| acc + sample * sample);
+
+    if energy == 0.0 {
+        return impulse_response.to_vec();
+    }
+
+    let scale = 1.0 / energy.sqrt();
+    impulse_response
+        .iter()
+        .map(|sample| sample * scale)
+        .collect()
+}
+
+// Freeverb tunings, in samples at 44_100Hz
+// Taken from: https://github.com/irh/freeverb-rs/blob/master/freeverb/src/freeverb.rs
+const FREEVERB_TUNING_SAMPLE_RATE: f64 = 44_100.0;
+const FREEVERB_FIXED_GAIN: f64 = 0.015;
+const FREEVERB_SCALE_WET: f64 = 3.0;
+const FREEVERB_SCALE_DAMPENING: f64 = 0.4;
+const FREEVERB_SCALE_ROOM: f64 = 0.28;
+const FREEVERB_OFFSET_ROOM: f64 = 0.7;
+const FREEVERB_STEREO_SPREAD: usize = 23;
+const FREEVERB_COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const FREEVERB_ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+
+/// A stateful stereo Freeverb algorithmic reverb: eight parallel lowpass-feedback comb filters
+/// feeding four series all-pass filters per channel. The right channel's delays are offset by a
+/// small stereo spread to decorrelate the channels.
+///
+/// All parameters are in the range [0, 1].
+///
+/// Taken from: https://github.com/irh/freeverb-rs/blob/master/freeverb/src/freeverb.rs
+#[derive(Clone, Debug)]
+pub struct Freeverb {
+    combs: Vec<(Comb, Comb)>,
+    allpasses: Vec<(AllPass, AllPass)>,
+    wet_gains: (f64, f64),
+    wet: f64,
+    width: f64,
+    dry: f64,
+    input_gain: f64,
+    dampening: f64,
+    room_size: f64,
+    frozen: bool,
+}
+
+impl Freeverb {
+    /// Creates a new Freeverb, scaling the reference delay tunings to `sample_rate`.
+    pub fn new(sample_rate: usize) -> Freeverb {
+        let seconds = |tuning: usize| tuning as f64 / FREEVERB_TUNING_SAMPLE_RATE;
+
+        let combs = FREEVERB_COMB_TUNINGS
+            .iter()
+            .map(|&tuning| {
+                (
+                    Comb::new(seconds(tuning), sample_rate, 0.0, 0.0, 0.0),
+                    Comb::new(
+                        seconds(tuning + FREEVERB_STEREO_SPREAD),
+                        sample_rate,
+                        0.0,
+                        0.0,
+                        0.0,
+                    ),
+                )
+            })
+            .collect();
+
+        let allpasses = FREEVERB_ALLPASS_TUNINGS
+            .iter()
+            .map(|&tuning| {
+                (
+                    AllPass::new(seconds(tuning), sample_rate, 0.5),
+                    AllPass::new(seconds(tuning + FREEVERB_STEREO_SPREAD), sample_rate, 0.5),
+                )
+            })
+            .collect();
+
+        let mut freeverb = Freeverb {
+            combs,
+            allpasses,
+            wet_gains: (0.0, 0.0),
+            wet: 0.0,
+            width: 0.0,
+            dry: 0.0,
+            input_gain: 0.0,
+            dampening: 0.0,
+            room_size: 0.0,
+            frozen: false,
+        };
+
+        freeverb.set_wet(1.0);
+        freeverb.set_width(0.5);
+        freeverb.set_dampening(0.5);
+        freeverb.set_room_size(0.5);
+        freeverb.set_frozen(false);
+
+        freeverb
+    }
+
+    /// Processes a stereo `(left, right)` sample.
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let input_mixed = (input.0 + input.1) * FREEVERB_FIXED_GAIN * self.input_gain;
+
+        let mut out = (0.0, 0.0);
+
+        for combs in self.combs.iter_mut() {
+            out.0 += combs.0.tick(input_mixed);
+            out.1 += combs.1.tick(input_mixed);
+        }
+
+        for allpasses in self.allpasses.iter_mut() {
+            out.0 = allpasses.0.tick(out.0);
+            out.1 = allpasses.1.tick(out.1);
+        }
+
+        (
+            out.0 * self.wet_gains.0 + out.1 * self.wet_gains.1 + input.0 * self.dry,
+            out.1 * self.wet_gains.0 + out.0 * self.wet_gains.1 + input.1 * self.dry,
+        )
+    }
+
+    /// Processes a mono sample, returning the average of both output channels.
+    pub fn tick_mono(&mut self, input: f64) -> f64 {
+        let (left, right) = self.tick((input, input));
+        (left + right) / 2.0
+    }
+
+    /// High-frequency dampening in the comb filter feedback paths.
+    pub fn set_dampening(&mut self, value: f64) {
+        self.dampening = value * FREEVERB_SCALE_DAMPENING;
+        self.update_combs();
+    }
+
+    /// Freezes the reverb tail, sustaining it indefinitely and muting further input.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+        self.input_gain = if frozen { 0.0 } else { 1.0 };
+        self.update_combs();
+    }
+
+    /// Wet (reverberated) signal level.
+    pub fn set_wet(&mut self, value: f64) {
+        self.wet = value * FREEVERB_SCALE_WET;
+        self.update_wet_gains();
+    }
+
+    /// Stereo width of the wet signal. 0 is mono, 1 is fully decorrelated.
+    pub fn set_width(&mut self, value: f64) {
+        self.width = value;
+        self.update_wet_gains();
+    }
+
+    /// Dry (original) signal level.
+    pub fn set_dry(&mut self, value: f64) {
+        self.dry = value;
+    }
+
+    /// Room size, which controls the comb filter feedback and therefore the decay time.
+    pub fn set_room_size(&mut self, value: f64) {
+        self.room_size = value * FREEVERB_SCALE_ROOM + FREEVERB_OFFSET_ROOM;
+        self.update_combs();
+    }
+
+    fn update_wet_gains(&mut self) {
+        self.wet_gains = (
+            self.wet * (self.width / 2.0 + 0.5),
+            self.wet * ((1.0 - self.width) / 2.0),
+        )
+    }
+
+    fn update_combs(&mut self) {
+        let (feedback, dampening) = if self.frozen {
+            (1.0, 0.0)
+        } else {
+            (self.room_size, self.dampening)
+        };
+
+        for combs in self.combs.iter_mut() {
+            for comb in [&mut combs.0, &mut combs.1].iter_mut() {
+                comb.feedback = feedback;
+                comb.dampening = dampening;
+                comb.dampening_inverse = 1.0 - dampening;
+            }
+        }
+    }
+}
+
+/// Processes mono samples with `tick_mono`.
+impl Processor for Freeverb {
+    fn tick(&mut self, input: f64) -> f64 {
+        self.tick_mono(input)
+    }
+
+    fn reset(&mut self) {
+        for (left, right) in self.combs.iter_mut() {
+            left.reset();
+            right.reset();
+        }
+        for (left, right) in self.allpasses.iter_mut() {
+            left.reset();
+            right.reset();
+        }
+    }
+
+    fn tail_length(&self) -> usize {
+        let combs = self
+            .combs
+            .iter()
+            .map(|(left, right)| left.tail_length().max(right.tail_length()))
+            .max()
+            .unwrap_or(0);
+        self.allpasses.iter().fold(combs, |sum, (left, right)| {
+            sum.saturating_add(left.tail_length().max(right.tail_length()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direct_convolution(kernel: &[f64], input: &[f64]) -> Vec<f64> {
+        let mut output = vec![0.0; input.len() + kernel.len() - 1];
+        for (i, x) in input.iter().enumerate() {
+            for (j, h) in kernel.iter().enumerate() {
+                output[i + j] += x * h;
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_partitioned_convolution_matches_direct_convolution() {
+        let kernel: Vec<f64> = (0..1500)
+            .map(|i| ((i * 37 % 101) as f64 - 50.0) / 50.0)
+            .collect();
+        let input: Vec<f64> = (0..3000).map(|i| (i as f64 * 0.31).sin()).collect();
+
+        let mut reverb = ConvolutionReverb::new(&kernel, 44_100, 0.0, 1.0, 0.0);
+        let output = reverb.process(&input);
+        let expected = direct_convolution(&kernel, &input);
+
+        assert_eq!(output.len(), expected.len());
+        for (x, y) in output.iter().zip(expected.iter()) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_convolution_reverb_mix_and_pre_delay() {
+        let impulse_response = vec![0.5, 0.25];
+        let mut reverb = ConvolutionReverb::new(&impulse_response, 10, 0.3, 0.5, 1.0);
+        assert_eq!(reverb.tail_length(), 4);
+
+        let output = reverb.process(&[1.0, 0.0]);
+        let expected = [1.0, 0.0, 0.0, 0.25, 0.125, 0.0];
+        assert_eq!(output.len(), expected.len());
+        for (x, y) in output.iter().zip(expected.iter()) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_tick_latency() {
+        let mut reverb = ConvolutionReverb::new(&[1.0], 44_100, 0.0, 1.0, 1.0);
+        let latency = reverb.latency();
+
+        let output: Vec<f64> = (0..latency + 1)
+            .map(|i| reverb.tick(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+        assert!(output[..latency].iter().all(|x| x.abs() < 1e-9));
+        assert!((output[latency] - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trim_and_normalize_impulse_response() {
+        let impulse_response = vec![0.0, 0.0001, 0.0, 2.0, -1.0, 0.5, 0.0001, 0.0];
+        let trimmed = trim_impulse_response(&impulse_response, -60.0);
+        assert_eq!(trimmed, vec![2.0, -1.0, 0.5]);
+
+        let normalized = normalize_impulse_response(&trimmed);
+        let energy: f64 = normalized.iter().map(|x| x * x).sum();
+        assert!((energy - 1.0).abs() < 1e-9);
+
+        assert!(trim_impulse_response(&[0.0, 0.0], -60.0).is_empty());
+    }
+
+    #[test]
+    fn test_load_impulse_response_from_wave_file() {
+        let reverb =
+            ConvolutionReverb::from_wave_file("./tests/assets/sine.wav", 22_050, 0.0, 0.3, 1.0)
+                .unwrap();
+        assert!(reverb.tail_length() > 0);
+        assert!(reverb.tail_length() < 44_100);
+    }
+
+    fn freeverb_impulse_response(freeverb: &mut Freeverb, length: usize) -> Vec<(f64, f64)> {
+        (0..length)
+            .map(|i| freeverb.tick(if i == 0 { (1.0, 1.0) } else { (0.0, 0.0) }))
+            .collect()
+    }
+
+    fn energy(samples: &[(f64, f64)]) -> f64 {
+        samples.iter().fold(0.0, |acc, (l, r)| acc + l * l + r * r)
+    }
+
+    #[test]
+    fn test_freeverb_impulse_response_decays() {
+        let mut freeverb = Freeverb::new(44_100);
+        let response = freeverb_impulse_response(&mut freeverb, 44_100 * 2);
+
+        let windows: Vec<f64> = response.chunks(11_025).map(energy).collect();
+        assert!(windows[0] > 0.0);
+        for pair in windows.windows(2) {
+            assert!(pair[1] < pair[0]);
+        }
+        assert!(windows[7] < windows[0] * 0.01);
+    }
+
+    #[test]
+    fn test_freeverb_room_size_lengthens_decay() {
+        let mut small = Freeverb::new(44_100);
+        small.set_room_size(0.1);
+        let mut large = Freeverb::new(44_100);
+        large.set_room_size(0.9);
+
+        let small_tail = energy(&freeverb_impulse_response(&mut small, 44_100)[22_050..]);
+        let large_tail = energy(&freeverb_impulse_response(&mut large, 44_100)[22_050..]);
+        assert!(large_tail > small_tail * 10.0);
+    }
+
+    #[test]
+    fn test_freeverb_freeze_sustains_tail() {
+        let mut freeverb = Freeverb::new(44_100);
+        freeverb.set_dampening(0.0);
+        let _ = freeverb_impulse_response(&mut freeverb, 4410);
+        freeverb.set_frozen(true);
+
+        let frozen: Vec<(f64, f64)> = (0..44_100).map(|_| freeverb.tick((1.0, 1.0))).collect();
+        let first = energy(&frozen[..11_025]);
+        let last = energy(&frozen[33_075..]);
+        assert!(first > 0.0);
+        assert!((last / first - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_freeverb_stereo_width_and_dry() {
+        let mut wide = Freeverb::new(44_100);
+        let response = freeverb_impulse_response(&mut wide, 4410);
+        assert!(response.iter().any(|(l, r)| (l - r).abs() > 1e-6));
+
+        let mut narrow = Freeverb::new(44_100);
+        narrow.set_width(0.0);
+        let response = freeverb_impulse_response(&mut narrow, 4410);
+        assert!(response.iter().all(|(l, r)| (l - r).abs() < 1e-12));
+
+        let mut dry = Freeverb::new(44_100);
+        dry.set_wet(0.0);
+        dry.set_dry(1.0);
+        assert_eq!(dry.tick((0.5, -0.25)), (0.5, -0.25));
+    }
+
+    #[test]
+    fn test_freeverb_scales_tunings_to_sample_rate() {
+        let first_output = |sample_rate: usize| {
+            let mut freeverb = Freeverb::new(sample_rate);
+            freeverb_impulse_response(&mut freeverb, sample_rate)
+                .iter()
+                .position(|(l, _)| l.abs() > 1e-12)
+                .unwrap()
+        };
+
+        let at_44k = first_output(44_100);
+        let at_88k = first_output(88_200);
+        assert!((at_88k as isize - 2 * at_44k as isize).abs() <= 2);
+    }
+}
@@ -0,0 +1,566 @@
+//! Reads the chunks of RIFF files, such as WAV.
+//!
+//! A RIFF file is a `RIFF` header, holding the total size and a form type such as `WAVE`,
+//! followed by chunks. Each chunk is a four character ID, a little-endian size, then that many
+//! bytes of data and a pad byte if the size is odd.
+//!
+//! RF64 and BW64 files, for audio over 4GiB, replace the `RIFF` ID and store sizes that do not
+//! fit in 32 bits in a `ds64` chunk, which `RiffChunks` reads and applies automatically.
+//!
+//! IFF files, such as AIFF, have the same layout with a `FORM` header and big-endian sizes, and
+//! are read the same way.
+//!
+//! ```
+//! use synthrs::riff::RiffChunks;
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let file = BufReader::new(File::open("./tests/assets/sine.wav").unwrap());
+//! let chunks = RiffChunks::new(file).unwrap();
+//! assert_eq!(&chunks.form_type, b"WAVE");
+//!
+//! for chunk in chunks {
+//!     let chunk = chunk.unwrap();
+//!     println!("{}: {} bytes", chunk.id_str(), chunk.data.len());
+//! }
+//! ```
+
+use std::io::{Error, ErrorKind, Read, Result};
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+
+/// Chunk sizes of `0xFFFFFFFF` mean the chunk runs to the end of the stream, as written by
+/// streaming encoders that cannot go back to fill in sizes.
+pub const UNKNOWN_SIZE: u32 = 0xffff_ffff;
+
+/// The ID and size of a chunk, read before its data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkHeader {
+    pub id: [u8; 4],
+    /// Size of the data in bytes, not including the header or pad byte. For RF64 files, this is
+    /// the 64-bit size from the `ds64` chunk.
+    pub size: u64,
+}
+
+impl ChunkHeader {
+    /// Number of bytes following the header, including the pad byte of odd-sized chunks.
+    pub fn padded_size(&self) -> u64 {
+        self.size + self.size % 2
+    }
+
+    /// Returns `true` if the chunk runs to the end of the stream.
+    pub fn is_unknown_size(&self) -> bool {
+        self.size == u64::from(UNKNOWN_SIZE)
+    }
+}
+
+/// 64-bit sizes from the `ds64` chunk of an RF64 or BW64 file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ds64 {
+    pub riff_size: u64,
+    pub data_size: u64,
+    /// Number of frames, replacing the count in the `fact` chunk
+    pub sample_count: u64,
+    /// Sizes of other chunks over 4GiB
+    pub table: Vec<([u8; 4], u64)>,
+}
+
+impl Ds64 {
+    /// Parses the data of a `ds64` chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Ds64> {
+        let mut reader = bytes;
+        let riff_size = reader.read_u64::<LittleEndian>()?;
+        let data_size = reader.read_u64::<LittleEndian>()?;
+        let sample_count = reader.read_u64::<LittleEndian>()?;
+        let table_length = reader.read_u32::<LittleEndian>().unwrap_or(0);
+
+        let mut table = Vec::new();
+        for _ in 0..table_length {
+            let mut id = [0u8; 4];
+            reader.read_exact(&mut id)?;
+            table.push((id, reader.read_u64::<LittleEndian>()?));
+        }
+
+        Ok(Ds64 {
+            riff_size,
+            data_size,
+            sample_count,
+            table,
+        })
+    }
+
+    /// The data of a `ds64` chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(28 + 12 * self.table.len());
+        bytes.extend_from_slice(&self.riff_size.to_le_bytes());
+        bytes.extend_from_slice(&self.data_size.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_count.to_le_bytes());
+        bytes.extend_from_slice(&(self.table.len() as u32).to_le_bytes());
+        for (id, size) in &self.table {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// A chunk and its data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RiffChunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl RiffChunk {
+    pub fn new(id: [u8; 4], data: Vec<u8>) -> RiffChunk {
+        RiffChunk { id, data }
+    }
+
+    /// The chunk ID as text, such as `"fmt "`.
+    pub fn id_str(&self) -> String {
+        String::from_utf8_lossy(&self.id).into_owned()
+    }
+
+    /// The chunk as stored in a file: ID, little-endian size, data, and a pad byte if needed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9 + self.data.len());
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        if self.data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+}
+
+/// Walks the chunks of a RIFF stream in order, either as an iterator of whole chunks, or a header
+/// at a time with `next_header` followed by `read_body` or `skip_body`.
+///
+/// Chunks are read up to the end of the RIFF size, so that trailing data such as ID3 tags is
+/// ignored. Streaming encoders often leave the RIFF size as 0 or `0xFFFFFFFF`, or larger than
+/// the file, in which case chunks are read until the end of the stream instead. A chunk
+/// claiming more data than the stream holds is an error.
+#[derive(Debug)]
+pub struct RiffChunks<R> {
+    reader: R,
+    /// `RIFF`, `RF64`, `BW64`, or `FORM` for big-endian IFF files
+    pub riff_id: [u8; 4],
+    /// Size of everything after the RIFF size field, from the `ds64` chunk for RF64 files
+    pub riff_size: u64,
+    /// Form type, such as `WAVE` or `AIFF`
+    pub form_type: [u8; 4],
+    /// The `ds64` chunk of RF64 and BW64 files
+    pub ds64: Option<Ds64>,
+    /// Sizes are big-endian in IFF files
+    big_endian: bool,
+    // Bytes read so far, including the RIFF header
+    position: u64,
+    finished: bool,
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_size<R>(reader: &mut R, big_endian: bool) -> Result<u32>
+where
+    R: Read,
+{
+    if big_endian {
+        reader.read_u32::<BigEndian>()
+    } else {
+        reader.read_u32::<LittleEndian>()
+    }
+}
+
+impl<R> RiffChunks<R>
+where
+    R: Read,
+{
+    /// Reads the RIFF header from the start of `reader`, and the `ds64` chunk that follows it in
+    /// RF64 and BW64 files.
+    pub fn new(mut reader: R) -> Result<RiffChunks<R>> {
+        let mut riff_id = [0u8; 4];
+        reader.read_exact(&mut riff_id)?;
+        if !matches!(&riff_id, b"RIFF" | b"RF64" | b"BW64" | b"FORM") {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "file is not a RIFF file".to_string(),
+            ));
+        }
+        let big_endian = &riff_id == b"FORM";
+        let riff_size = read_size(&mut reader, big_endian)?;
+        let mut form_type = [0u8; 4];
+        reader.read_exact(&mut form_type)?;
+
+        let mut chunks = RiffChunks {
+            reader,
+            riff_id,
+            riff_size: u64::from(riff_size),
+            form_type,
+            ds64: None,
+            big_endian,
+            position: 12,
+            finished: false,
+        };
+
+        if matches!(&riff_id, b"RF64" | b"BW64") {
+            let ds64 = match chunks.next_header()? {
+                Some(header) if &header.id == b"ds64" => Ds64::parse(&chunks.read_body(&header)?)?,
+                _ => {
+                    return Err(invalid_data(
+                        "RF64 file is missing its ds64 chunk".to_string(),
+                    ))
+                }
+            };
+            if riff_size == UNKNOWN_SIZE {
+                chunks.riff_size = ds64.riff_size;
+            }
+            chunks.ds64 = Some(ds64);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Reads the header of the next chunk, or `None` at the end of the stream.
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+        if self.finished {
+            return Ok(None);
+        }
+        if let Some(end) = self.end() {
+            if self.position >= end {
+                self.finished = true;
+                return Ok(None);
+            }
+        }
+
+        let mut id = [0u8; 4];
+        let mut read = 0;
+        while read < 4 {
+            match self.reader.read(&mut id[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        match read {
+            0 => {
+                self.finished = true;
+                Ok(None)
+            }
+            4 => {
+                let size = read_size(&mut self.reader, self.big_endian).map_err(|_| {
+                    invalid_data(format!(
+                        "chunk `{}` header is truncated",
+                        String::from_utf8_lossy(&id)
+                    ))
+                })?;
+                self.position += 8;
+                let size = match self.ds64 {
+                    Some(ref ds64) if size == UNKNOWN_SIZE => {
+                        if &id == b"data" {
+                            ds64.data_size
+                        } else {
+                            ds64.table
+                                .iter()
+                                .find(|(table_id, _)| *table_id == id)
+                                .map_or(u64::from(size), |&(_, size)| size)
+                        }
+                    }
+                    _ => u64::from(size),
+                };
+                Ok(Some(ChunkHeader { id, size }))
+            }
+            // Some encoders leave a few bytes of trailing garbage
+            _ => {
+                self.finished = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the data of the chunk whose header was just read, and its pad byte.
+    pub fn read_body(&mut self, header: &ChunkHeader) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+
+        if header.is_unknown_size() {
+            self.reader.read_to_end(&mut data)?;
+            self.finished = true;
+            return Ok(data);
+        }
+
+        // Not preallocated, as the size may be corrupt
+        (&mut self.reader)
+            .take(header.size)
+            .read_to_end(&mut data)?;
+        if (data.len() as u64) < header.size {
+            return Err(invalid_data(format!(
+                "chunk `{}` is truncated: expected {} bytes but got {}",
+                String::from_utf8_lossy(&header.id),
+                header.size,
+                data.len()
+            )));
+        }
+        self.position += header.size;
+        self.skip_padding(header)?;
+
+        Ok(data)
+    }
+
+    /// Skips the data of the chunk whose header was just read, and its pad byte.
+    pub fn skip_body(&mut self, header: &ChunkHeader) -> Result<()> {
+        if header.is_unknown_size() {
+            std::io::copy(&mut self.reader, &mut std::io::sink())?;
+            self.finished = true;
+            return Ok(());
+        }
+
+        let skipped = std::io::copy(
+            &mut (&mut self.reader).take(header.size),
+            &mut std::io::sink(),
+        )?;
+        if skipped < header.size {
+            return Err(invalid_data(format!(
+                "chunk `{}` is truncated: expected {} bytes but got {}",
+                String::from_utf8_lossy(&header.id),
+                header.size,
+                skipped
+            )));
+        }
+        self.position += header.size;
+        self.skip_padding(header)
+    }
+
+    /// Pad bytes missing at the very end of a stream are tolerated.
+    fn skip_padding(&mut self, header: &ChunkHeader) -> Result<()> {
+        if header.size % 2 == 1 {
+            let mut pad = [0u8; 1];
+            if self.reader.read(&mut pad)? == 0 {
+                self.finished = true;
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    /// End of the RIFF data, if the RIFF size was filled in. Sizes past the end of the stream
+    /// are caught by reading to the end of the stream.
+    fn end(&self) -> Option<u64> {
+        match self.riff_size {
+            0 => None,
+            size if size == u64::from(UNKNOWN_SIZE) => None,
+            size => Some(size + 8),
+        }
+    }
+
+    /// Returns `true` for IFF files, such as AIFF, whose sizes are big-endian.
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    /// Returns the underlying reader, positioned after the last header or body read.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reading from the underlying reader directly stops the end of the RIFF data from being
+    /// tracked, so only do so once done with the chunks.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+}
+
+impl<R> Iterator for RiffChunks<R>
+where
+    R: Read,
+{
+    type Item = Result<RiffChunk>;
+
+    fn next(&mut self) -> Option<Result<RiffChunk>> {
+        let header = match self.next_header() {
+            Ok(Some(header)) => header,
+            Ok(None) => return None,
+            Err(e) => {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        };
+
+        match self.read_body(&header) {
+            Ok(data) => Some(Ok(RiffChunk::new(header.id, data))),
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn riff(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = b"WAVE".to_vec();
+        for (id, data) in chunks {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+
+    #[test]
+    fn test_riff_chunks() {
+        let file = riff(&[(b"fmt ", &[1, 2]), (b"JUNK", &[3, 4, 5]), (b"data", &[6])]);
+        let chunks = RiffChunks::new(&file[..]).unwrap();
+        assert_eq!(chunks.riff_size as usize, file.len() - 8);
+        assert_eq!(chunks.ds64, None);
+        assert_eq!(&chunks.form_type, b"WAVE");
+
+        let chunks: Vec<RiffChunk> = chunks.map(|chunk| chunk.unwrap()).collect();
+        assert_eq!(
+            chunks,
+            vec![
+                RiffChunk::new(*b"fmt ", vec![1, 2]),
+                RiffChunk::new(*b"JUNK", vec![3, 4, 5]),
+                RiffChunk::new(*b"data", vec![6]),
+            ]
+        );
+
+        // Missing final pad byte and trailing garbage
+        let mut file = riff(&[(b"data", &[1, 2, 3])]);
+        file.pop();
+        let chunks: Vec<RiffChunk> = RiffChunks::new(&file[..])
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        assert_eq!(chunks, vec![RiffChunk::new(*b"data", vec![1, 2, 3])]);
+
+        let mut file = riff(&[(b"data", &[1, 2])]);
+        file.extend_from_slice(&[0, 0]);
+        assert_eq!(RiffChunks::new(&file[..]).unwrap().count(), 1);
+
+        // Trailing data after the RIFF size is ignored, such as an ID3 tag
+        let mut file = riff(&[(b"data", &[1, 2, 3])]);
+        file.extend_from_slice(b"ID3\x04\0\0\0\0\0\x0aTIT2 junk");
+        let chunks: Vec<RiffChunk> = RiffChunks::new(&file[..])
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        assert_eq!(chunks, vec![RiffChunk::new(*b"data", vec![1, 2, 3])]);
+
+        // Streamed files with no RIFF size, or one past the end, are read to the end
+        for &size in &[0, UNKNOWN_SIZE, 1000] {
+            let mut file = riff(&[(b"fmt ", &[1, 2]), (b"data", &[3, 4])]);
+            file[4..8].copy_from_slice(&size.to_le_bytes());
+            assert_eq!(RiffChunks::new(&file[..]).unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_riff_chunk_validation() {
+        assert!(RiffChunks::new(&b"RIFX\0\0\0\0WAVE"[..]).is_err());
+
+        // Size larger than the stream
+        let mut file = riff(&[(b"data", &[1, 2, 3, 4])]);
+        file[16] = 100;
+        let mut chunks = RiffChunks::new(&file[..]).unwrap();
+        assert_eq!(
+            chunks.next().unwrap().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert!(chunks.next().is_none());
+
+        // Unknown size runs to the end of the stream
+        let mut file = riff(&[(b"LIST", &[9]), (b"data", &[1, 2, 3, 4])]);
+        file[26..30].copy_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        let mut chunks = RiffChunks::new(&file[..]).unwrap();
+        let header = chunks.next_header().unwrap().unwrap();
+        chunks.skip_body(&header).unwrap();
+        assert_eq!(
+            chunks.next().unwrap().unwrap(),
+            RiffChunk::new(*b"data", vec![1, 2, 3, 4])
+        );
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_rf64_chunks() {
+        let ds64 = Ds64 {
+            riff_size: 0x1_0000_0000,
+            data_size: 4,
+            sample_count: 2,
+            table: vec![(*b"LIST", 2)],
+        };
+        let mut file = riff(&[
+            (b"ds64", &ds64.to_bytes()),
+            (b"LIST", &[1, 2]),
+            (b"data", &[1, 2, 3, 4]),
+        ]);
+        file[..8].copy_from_slice(b"RF64\xff\xff\xff\xff");
+        let length = file.len();
+        file[length - 8..length - 4].copy_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        file[64..68].copy_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+
+        let mut chunks = RiffChunks::new(&file[..]).unwrap();
+        assert_eq!(&chunks.riff_id, b"RF64");
+        assert_eq!(chunks.riff_size, 0x1_0000_0000);
+        assert_eq!(chunks.ds64, Some(ds64));
+        assert_eq!(
+            chunks.next_header().unwrap().unwrap(),
+            ChunkHeader {
+                id: *b"LIST",
+                size: 2
+            }
+        );
+        chunks
+            .skip_body(&ChunkHeader {
+                id: *b"LIST",
+                size: 2,
+            })
+            .unwrap();
+        assert_eq!(
+            chunks.next().unwrap().unwrap(),
+            RiffChunk::new(*b"data", vec![1, 2, 3, 4])
+        );
+
+        file[12..16].copy_from_slice(b"JUNK");
+        assert!(RiffChunks::new(&file[..]).is_err());
+    }
+
+    #[test]
+    fn test_iff_chunks() {
+        let file = b"FORM\0\0\0\x14AIFFCOMM\0\0\0\x01\x07\0SSND\0\0\0\x02\x01\x02";
+        let mut chunks = RiffChunks::new(&file[..]).unwrap();
+        assert!(chunks.is_big_endian());
+        assert_eq!(chunks.riff_size, 20);
+        assert_eq!(&chunks.form_type, b"AIFF");
+        assert_eq!(
+            chunks.next().unwrap().unwrap(),
+            RiffChunk::new(*b"COMM", vec![7])
+        );
+        assert_eq!(
+            chunks.next().unwrap().unwrap(),
+            RiffChunk::new(*b"SSND", vec![1, 2])
+        );
+        assert!(chunks.next().is_none());
+
+        // A corrupt size is an error rather than a huge allocation
+        let file = b"FORM\0\0\0\x14AIFFCOMM\xff\xff\xff\xfe\x07\0";
+        let mut chunks = RiffChunks::new(&file[..]).unwrap();
+        assert_eq!(
+            chunks.next().unwrap().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
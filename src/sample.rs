@@ -1,8 +1,8 @@
 //! Functions for dealing with creating samples for sample-synthesis generators
 
+use std::f64::consts::PI;
 use std::io::{Cursor, Result};
 
-use crate::synthesizer::unquantize_samples;
 use crate::writer::{read_wav, read_wav_file, Wave};
 
 /// Given a `crate::writer::Wave`, extract a `Vec<f64>` of samples from it and the size of that vec
@@ -15,7 +15,7 @@ use crate::writer::{read_wav, read_wav_file, Wave};
 /// let (samples, num_samples) = samples_from_wave(wave);
 /// ```
 pub fn samples_from_wave(wave: Wave) -> (Vec<f64>, usize) {
-    let samples = unquantize_samples(&wave.pcm);
+    let samples = wave.samples();
     let length = samples.len();
     (samples, length)
 }
@@ -55,3 +55,93 @@ pub fn samples_from_wave_file(filepath: &str) -> Result<(Vec<f64>, usize)> {
     let wave = read_wav_file(filepath)?;
     Ok(samples_from_wave(wave))
 }
+
+/// Resamples `samples` recorded at `from_rate` to `to_rate` using windowed sinc interpolation.
+/// When downsampling, the interpolation kernel is widened to act as an anti-aliasing filter.
+///
+/// ```
+/// use synthrs::sample::resample;
+/// use synthrs::synthesizer::make_samples;
+/// use synthrs::wave::sine_wave;
+///
+/// let samples = make_samples(0.1, 22_050, sine_wave(440.0));
+/// let resampled = resample(&samples, 22_050, 44_100);
+/// assert_eq!(resampled.len(), 4410);
+/// ```
+pub fn resample(samples: &[f64], from_rate: usize, to_rate: usize) -> Vec<f64> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    // Number of zero crossings of the sinc on either side of the interpolated point
+    let zero_crossings = 16.0;
+    let ratio = to_rate as f64 / from_rate as f64;
+    let cutoff = ratio.min(1.0);
+    let half_width = zero_crossings / cutoff;
+
+    let output_length = (samples.len() as f64 * ratio).round() as usize;
+
+    (0..output_length)
+        .map(|i| {
+            let t = i as f64 / ratio;
+            let start = (t - half_width).ceil().max(0.0) as usize;
+            let end = ((t + half_width).floor() as usize).min(samples.len() - 1);
+
+            (start..=end).fold(0.0, |acc, j| {
+                let x = t - j as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * cutoff * x).sin() / (PI * cutoff * x)
+                };
+                // Hann window spanning the kernel
+                let window = 0.5 + 0.5 * (PI * x / half_width).cos();
+                acc + samples[j] * cutoff * sinc * window
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesizer::make_samples;
+    use crate::wave::sine_wave;
+
+    fn zero_crossings(samples: &[f64]) -> usize {
+        samples
+            .windows(2)
+            .filter(|w| w[0].is_sign_negative() != w[1].is_sign_negative())
+            .count()
+    }
+
+    #[test]
+    fn test_resample_preserves_frequency() {
+        let samples = make_samples(1.0, 22_050, sine_wave(441.0));
+        let upsampled = resample(&samples, 22_050, 44_100);
+        let downsampled = resample(&samples, 22_050, 11_025);
+
+        assert_eq!(upsampled.len(), 44_100);
+        assert_eq!(downsampled.len(), 11_025);
+        assert!((zero_crossings(&upsampled) as isize - 882).abs() <= 2);
+        assert!((zero_crossings(&downsampled) as isize - 882).abs() <= 2);
+
+        // Away from the edges the interpolated values should follow the original sine
+        for (i, sample) in upsampled.iter().enumerate().skip(1000).take(1000) {
+            let expected = sine_wave(441.0)(i as f64 / 44_100.0);
+            assert!((sample - expected).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_resample_removes_frequencies_above_nyquist() {
+        let samples = make_samples(1.0, 44_100, sine_wave(15_000.0));
+        let downsampled = resample(&samples, 44_100, 22_050);
+        let peak = downsampled
+            .iter()
+            .skip(100)
+            .take(20_000)
+            .fold(0.0f64, |acc, s| acc.max(s.abs()));
+        assert!(peak < 0.05);
+    }
+}
@@ -23,10 +23,13 @@ use std::mem::size_of;
 use num::traits::{Bounded, FromPrimitive, Num, ToPrimitive, Zero};
 use num::Float;
 
+use crate::automation::{self, AutomationLane};
 use crate::errors::SynthrsError;
 use crate::filter;
+use crate::g711::Law;
 use crate::midi;
 use crate::music;
+use crate::writer::{PcmData, SampleFormat};
 
 /// Quantizes a `f64` sample into `T`.
 /// Convert from [-1.0f64, 1.0] to take up full quantization range of type `T`.
@@ -60,13 +63,14 @@ where
 /// assert_eq!(unquantize(&0.0f32), 0.0f64);
 /// assert_eq!(unquantize(&0u8), 0.0f64);
 /// assert_eq!(unquantize(&0i16), 0.0f64);
+/// assert_eq!(unquantize(&-32768i16), -1.0f64);
 /// ```
 pub fn unquantize<T>(input: &T) -> f64
 where
     T: Num + ToPrimitive + Bounded + Zero,
 {
     let quantization_levels = 2.0.powf(size_of::<T>() as f64 * 8.0 - 1.0);
-    T::to_f64(&input).unwrap_or(0.0) * (quantization_levels / 2.0)
+    T::to_f64(input).unwrap_or(0.0) / quantization_levels
 }
 
 /// Quantizes a `Vec<f64>` of samples into `Vec<T>`.
@@ -99,7 +103,34 @@ pub fn unquantize_samples<T>(input: &[T]) -> Vec<f64>
 where
     T: Num + ToPrimitive + Bounded + Zero,
 {
-    input.iter().map(|s| unquantize::<T>(&*s)).collect()
+    input.iter().map(|s| unquantize::<T>(s)).collect()
+}
+
+/// Quantizes samples to 16-bit, then compands them to 8-bit G.711 A-law or μ-law codes, for
+/// telephony. Samples are clamped to [-1.0, 1.0] and rounded, as when writing G.711 WAV files.
+///
+/// ```
+/// use synthrs::g711::Law;
+/// use synthrs::synthesizer::{make_samples, quantize_samples_g711, unquantize_samples_g711};
+/// use synthrs::wave::sine_wave;
+///
+/// let samples = make_samples(0.1, 8000, sine_wave(440.0));
+/// let codes = quantize_samples_g711(&samples, Law::MuLaw);
+/// let decoded = unquantize_samples_g711(&codes, Law::MuLaw);
+/// assert!((decoded[10] - samples[10]).abs() < 0.01);
+/// ```
+pub fn quantize_samples_g711(input: &[f64], law: Law) -> Vec<u8> {
+    PcmData::from_f64(input, SampleFormat::I16)
+        .to_i16()
+        .into_iter()
+        .map(|sample| law.encode(sample))
+        .collect()
+}
+
+/// Expands G.711 codes to 16-bit, then reverses quantization into a `Vec<f64>`.
+pub fn unquantize_samples_g711(input: &[u8], law: Law) -> Vec<f64> {
+    let samples: Vec<i16> = input.iter().map(|&code| law.decode(code)).collect();
+    unquantize_samples(&samples)
 }
 
 /// Invokes the waveform function `f` at time `t` to return the amplitude at that time.
@@ -233,6 +264,31 @@ where
     make_samples_from_midi(instrument, sample_rate, use_envelope, song)
 }
 
+/// Options for rendering MIDI with `make_samples_from_midi_with_options`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// Applies a basic attack/decay envelope to each note
+    pub use_envelope: bool,
+    /// Length in seconds of the fades at the start and end of each note, which reduce clicks
+    /// when notes start or stop abruptly. 0.0 disables fading.
+    pub declick_time: f64,
+}
+
+impl RenderOptions {
+    pub fn new(use_envelope: bool) -> RenderOptions {
+        RenderOptions {
+            use_envelope,
+            declick_time: 0.0,
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions::new(false)
+    }
+}
+
 // This is really awful, is there a more elegant way to do this?
 /// Generates samples from a MIDI file. Supports only one instrument. Instrument can be any generator.
 ///
@@ -269,11 +325,98 @@ pub fn make_samples_from_midi<F1, F2>(
 where
     F1: Fn(f64) -> F2,
     F2: Fn(f64) -> f64,
+{
+    make_samples_from_midi_with_options(
+        instrument,
+        sample_rate,
+        &RenderOptions::new(use_envelope),
+        song,
+    )
+}
+
+/// Generates samples from a MIDI file, like `make_samples_from_midi`, with more options.
+///
+/// ```
+/// use synthrs::synthesizer::{make_samples_from_midi_with_options, RenderOptions};
+/// use synthrs::midi;
+/// use synthrs::wave;
+///
+/// let song = midi::read_midi_file("tests/assets/test.mid").unwrap();
+///
+/// // Fade each note in and out over 5ms
+/// let options = RenderOptions {
+///     declick_time: 0.005,
+///     ..RenderOptions::default()
+/// };
+///
+/// let samples = make_samples_from_midi_with_options(
+///     wave::square_wave,
+///     44_100,
+///     &options,
+///     song,
+/// ).unwrap();
+/// ```
+pub fn make_samples_from_midi_with_options<F1, F2>(
+    instrument: F1,
+    sample_rate: usize,
+    options: &RenderOptions,
+    song: midi::MidiSong,
+) -> Result<Vec<f64>, SynthrsError>
+where
+    F1: Fn(f64) -> F2,
+    F2: Fn(f64) -> f64,
+{
+    make_samples_from_midi_with_automation(
+        |frequency, _: &[f64]| instrument(frequency),
+        sample_rate,
+        options,
+        &[],
+        song,
+    )
+}
+
+/// Generates samples from a MIDI file, like `make_samples_from_midi_with_options`, with
+/// parameters driven by automation lanes.
+///
+/// `instrument` is given the frequency of each note and the value of each lane at every sample,
+/// in the same order as `lanes`.
+///
+/// ```
+/// use synthrs::automation::{AutomationLane, Curve, TimeBase};
+/// use synthrs::synthesizer::{make_samples_from_midi_with_automation, RenderOptions};
+/// use synthrs::midi;
+/// use synthrs::wave;
+///
+/// let song = midi::read_midi_file("tests/assets/test.mid").unwrap();
+///
+/// // Brighten the bell over the first eight beats
+/// let decay = AutomationLane::new(TimeBase::from_song(&song))
+///     .with_point(0.0, 0.1, Curve::Exponential)
+///     .with_point(8.0 * song.time_unit as f64, 1.0, Curve::Linear);
+///
+/// let samples = make_samples_from_midi_with_automation(
+///     |frequency: f64, values: &[f64]| wave::bell(frequency, 0.003, values[0]),
+///     44_100,
+///     &RenderOptions::default(),
+///     &[decay],
+///     song,
+/// ).unwrap();
+/// ```
+pub fn make_samples_from_midi_with_automation<F1, F2>(
+    instrument: F1,
+    sample_rate: usize,
+    options: &RenderOptions,
+    lanes: &[AutomationLane],
+    song: midi::MidiSong,
+) -> Result<Vec<f64>, SynthrsError>
+where
+    F1: Fn(f64, &[f64]) -> F2,
+    F2: Fn(f64) -> f64,
 {
     let length = (60.0 * song.max_time as f64) / (song.bpm * song.time_unit as f64);
 
-    // midi note, velocity, start_tick, i, ticks_left
-    type TickNote = (u8, u8, usize, usize, usize);
+    // midi note, velocity, start_tick, end_tick
+    type TickNote = (u8, u8, usize, usize);
 
     // Each tick (=audio sample) can have multiple notes active
     let mut notes_on_for_ticks: Vec<Vec<TickNote>> = Vec::new();
@@ -300,55 +443,41 @@ where
                     }
                 }
 
-                for (i, on_notes) in notes_on_for_ticks
+                for on_notes in notes_on_for_ticks
                     .iter_mut()
-                    .enumerate()
                     .take(end_tick)
                     .skip(start_tick)
                 {
-                    let ticks_left = end_tick - i;
-                    on_notes.push((note as u8, velocity as u8, start_tick, i, ticks_left));
+                    on_notes.push((note as u8, velocity as u8, start_tick, end_tick));
                 }
             }
         }
     }
 
-    let midi_frequency_function = |t: f64| -> f64 {
+    let midi_frequency_function = |t: f64, values: &[f64]| -> f64 {
         let tick = (t * song.bpm * song.time_unit as f64 / 60.0) as usize;
         let mut out = 0.0;
 
         if tick < notes_on_for_ticks.len() {
-            for &(note, velocity, start_tick, _ticks_elasped, _ticks_left) in
-                &notes_on_for_ticks[tick]
-            {
+            for &(note, velocity, start_tick, end_tick) in &notes_on_for_ticks[tick] {
                 let frequency = music::note_midi(440.0, note as usize);
                 // TODO: split loudness into a util module
                 let loudness = (6.908 * (f64::from(velocity) / 255.0)).exp() / 1000.0;
 
-                let start_t = start_tick as f64 * 60.0 / song.bpm as f64 / song.time_unit as f64;
+                let start_t = start_tick as f64 * 60.0 / song.bpm / song.time_unit as f64;
+                let end_t = end_tick as f64 * 60.0 / song.bpm / song.time_unit as f64;
                 let relative_t = t - start_t;
 
-                out += loudness * (instrument)(frequency)(relative_t);
+                // Reduce clicks when notes start and stop
+                let fade = filter::fade_gain(relative_t, end_t - t, options.declick_time);
+                out += fade * loudness * (instrument)(frequency, values)(relative_t);
 
-                if use_envelope {
+                if options.use_envelope {
                     // TODO: make this an option
                     let attack = 0.01;
                     let decay = 1.0;
 
                     out *= filter::envelope(relative_t, attack, decay);
-
-                    // TODO: make this an option, since it's awful for sine wave
-                    // // Reduce clicks when changing notes
-                    // // Ideally phase shift, but this is a simple solution
-                    // let fade_ticks = 20;
-
-                    // if ticks_elasped < fade_ticks {
-                    //     let ratio = ticks_elasped as f64 / fade_ticks as f64;
-                    //     out *= ratio * ratio;
-                    // } else if ticks_left < fade_ticks {
-                    //     let ratio = ticks_left as f64 / fade_ticks as f64;
-                    //     out *= ratio * ratio;
-                    // }
                 }
             }
         }
@@ -358,10 +487,12 @@ where
 
     let num_samples = (sample_rate as f64 * length).floor() as usize;
     let mut samples: Vec<f64> = Vec::with_capacity(num_samples);
+    let mut values = Vec::with_capacity(lanes.len());
 
     for i in 0usize..num_samples {
         let t = i as f64 / sample_rate as f64;
-        samples.push(midi_frequency_function(t));
+        automation::values_at_seconds(lanes, t, &mut values);
+        samples.push(midi_frequency_function(t, &values));
     }
 
     Ok(peak_normalize(&samples))
@@ -369,8 +500,6 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::i16;
-    use std::i8;
 
     use super::*;
     use crate::wave::sine_wave;
@@ -396,6 +525,25 @@ mod tests {
         // assert_eq!(u8::MAX, quantize::<u8>(1.0)); // TODO: Make quantization work for unsigned types
     }
 
+    #[test]
+    fn test_unquantize_roundtrip() {
+        for &sample in &[-0.75f64, -0.1, 0.0, 0.3, 0.999] {
+            let roundtrip = unquantize(&quantize::<i16>(sample));
+            assert!((roundtrip - sample).abs() < 1.0 / 16_384.0);
+        }
+    }
+
+    #[test]
+    fn test_quantize_samples_g711() {
+        use crate::g711::linear_to_ulaw;
+
+        // Out of range samples are clipped rather than wrapping to zero
+        assert_eq!(
+            quantize_samples_g711(&[1.5, -1.5, 0.5], Law::MuLaw),
+            vec![0x80, 0x00, linear_to_ulaw(16_384)]
+        );
+    }
+
     #[test]
     #[allow(clippy::approx_constant, clippy::float_cmp)]
     fn test_samples_iterator() {
@@ -415,4 +563,74 @@ mod tests {
             samples
         );
     }
+
+    #[test]
+    fn test_declicked_midi_render() {
+        let song = crate::midi::read_midi_file("tests/assets/multitrack.mid").unwrap();
+        let max_step = |samples: &[f64]| {
+            samples
+                .windows(2)
+                .fold(0.0f64, |max, w| (w[1] - w[0]).abs().max(max))
+        };
+
+        // A constant instrument jumps as notes start and stop
+        let organ = |_frequency: f64| |_t: f64| 1.0;
+        let clicky = make_samples_from_midi(organ, 44_100, false, song.clone()).unwrap();
+        let options = RenderOptions {
+            declick_time: 0.005,
+            ..RenderOptions::default()
+        };
+        let declicked = make_samples_from_midi_with_options(organ, 44_100, &options, song).unwrap();
+
+        assert_eq!(clicky.len(), declicked.len());
+        assert!(max_step(&declicked) < max_step(&clicky) / 10.0);
+    }
+
+    #[test]
+    fn test_automated_midi_render() {
+        use crate::automation::{Curve, TimeBase};
+
+        let song = crate::midi::read_midi_file("tests/assets/multitrack.mid").unwrap();
+        let organ = |_frequency: f64| |_t: f64| 1.0;
+        let plain = make_samples_from_midi(organ, 44_100, false, song.clone()).unwrap();
+        let (song_bpm, song_time_unit) = (song.bpm, song.time_unit as f64);
+
+        // Full gain until halfway through the song in ticks, then silence
+        let time_base = TimeBase::from_song(&song);
+        let switch_tick = (song.max_time / 2) as f64;
+        let gain = AutomationLane::new(time_base)
+            .with_point(0.0, 1.0, Curve::Step)
+            .with_point(switch_tick, 0.0, Curve::Step);
+        let automated = make_samples_from_midi_with_automation(
+            |_frequency: f64, values: &[f64]| {
+                let gain = values[0];
+                move |_t: f64| gain
+            },
+            44_100,
+            &RenderOptions::default(),
+            &[gain],
+            song,
+        )
+        .unwrap();
+
+        assert_eq!(plain.len(), automated.len());
+
+        // The first sample at or after the switch tick
+        let switch = (0..plain.len())
+            .find(|&i| time_base.from_seconds(i as f64 / 44_100.0) >= switch_tick)
+            .unwrap();
+        let seconds = switch_tick * 60.0 / (song_bpm * song_time_unit);
+        assert!((switch as f64 - seconds * 44_100.0).abs() <= 1.0);
+        assert!(plain[switch - 1].abs() > 0.0 && plain[switch].abs() > 0.0);
+
+        // Unchanged before the switch, apart from normalising to the louder first half
+        let peak = plain[..switch]
+            .iter()
+            .fold(0.0f64, |max, x| x.abs().max(max));
+        assert!(plain[..switch]
+            .iter()
+            .zip(&automated)
+            .all(|(a, b)| (a / peak - b).abs() < 1e-9));
+        assert!(automated[switch..].iter().all(|&x| x == 0.0));
+    }
 }
@@ -45,11 +45,7 @@ pub fn triangle_wave(frequency: f64) -> impl Fn(f64) -> f64 {
 }
 
 pub fn tangent_wave(frequency: f64) -> impl Fn(f64) -> f64 {
-    move |t| {
-        (((t * frequency * PI) - 0.5).tan() / 4.0)
-            .max(-1.0)
-            .min(1.0)
-    }
+    move |t| (((t * frequency * PI) - 0.5).tan() / 4.0).clamp(-1.0, 1.0)
 }
 
 pub fn bell(frequency: f64, attack: f64, decay: f64) -> impl Fn(f64) -> f64 {
@@ -120,8 +116,9 @@ pub fn karplus_strong<F: Fn(f64) -> f64>(
     }
 }
 
+/// White noise with a uniform distribution over [-1.0, 1.0).
 pub fn noise() -> impl Fn(f64) -> f64 {
-    |_t| rand::random::<f64>()
+    |_t| rand::random::<f64>() * 2.0 - 1.0
 }
 
 /// `sampler` creates a a generator function given a bunch of samples. Different frequencies are
@@ -143,6 +140,7 @@ pub fn noise() -> impl Fn(f64) -> f64 {
 /// let frequency_to_generate = 110.0;
 /// let sampler = wave::sampler(frequency, &piano_sample, sample_length, 110.0, 44_100)
 /// ```
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn sampler(
     frequency: f64,
     samples: *const Vec<f64>,
@@ -272,4 +270,13 @@ mod tests {
         assert_eq!(delayed(17.0), 7.0);
         assert_eq!(delayed(19.0), 11.0);
     }
+
+    #[test]
+    fn test_noise_is_zero_mean() {
+        let samples: Vec<f64> = (0..100_000).map(|i| noise()(i as f64)).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.01);
+        assert!(samples.iter().all(|&x| (-1.0..1.0).contains(&x)));
+        assert!(samples.iter().any(|&x| x < -0.9) && samples.iter().any(|&x| x > 0.9));
+    }
 }
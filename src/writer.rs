@@ -7,12 +7,22 @@
 //! * Little-endian
 //! * 1 Channel (Mono)
 //! * Sample rate: 44_100Hz (or whatever your samples generated have)
+//!
+//! These are the settings for `write_pcm`. Other encodings, byte orders and channel counts can
+//! be written and read with `write_raw_pcm` and `read_raw_pcm` and a `RawPcmSpec`, and the
+//! same settings then chosen in Audacity. Sun/NeXT `.au` files, which carry their own header,
+//! are written and read with `write_au` and `read_au`.
 
+use std::convert::TryFrom;
 use std::fs::OpenOptions;
-use std::io::{BufReader, Error, Read, Result, Write};
+use std::io::{BufReader, Error, Read, Result, Seek, SeekFrom, Write};
 use std::path::Path;
 
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::g711::Law;
+use crate::metadata::{CuePoint, LoopType, SampleLoop, SamplerInfo, WavMetadata};
+use crate::riff::{Ds64, RiffChunk, RiffChunks};
 
 /// Creates a file at `filename` and writes a bunch of `&[i16]` samples to it as a PCM file.
 /// See module documentation for PCM settings.
@@ -33,7 +43,7 @@ pub fn write_pcm_file(filename: &str, samples: &[i16]) -> Result<()> {
         .write(true)
         .truncate(true)
         .create(true)
-        .open(&path)?;
+        .open(path)?;
     write_pcm(&mut f, samples)
 }
 
@@ -86,7 +96,7 @@ pub fn write_wav_file(filename: &str, sample_rate: usize, samples: &[i16]) -> Re
         .write(true)
         .truncate(true)
         .create(true)
-        .open(&path)?;
+        .open(path)?;
     write_wav(&mut f, sample_rate, samples)
 }
 
@@ -111,238 +121,3372 @@ pub fn write_wav<W>(writer: &mut W, sample_rate: usize, samples: &[i16]) -> Resu
 where
     W: Write,
 {
-    // See: http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html
-    // Some WAV header fields
-    let channels = 1;
-    let bit_depth = 16;
-    let subchunk_2_size = samples.len() * channels * bit_depth / 8;
-    let chunk_size = 36 + subchunk_2_size as i32;
-    let byte_rate = (sample_rate * channels * bit_depth / 8) as i32;
-    let block_align = (channels * bit_depth / 8) as i16;
+    write_wav_with_spec(writer, &WavSpec::new(1, sample_rate), samples)
+}
 
-    writer.write_i32::<BigEndian>(0x5249_4646)?; // ChunkID, RIFF
-    writer.write_i32::<LittleEndian>(chunk_size)?; // ChunkSize
-    writer.write_i32::<BigEndian>(0x5741_5645)?; // Format, WAVE
+/// Audio with one or more channels, stored interleaved: one sample for each channel in turn,
+/// frame by frame.
+///
+/// ```
+/// use synthrs::writer::AudioBuffer;
+///
+/// let left = vec![1, 2, 3];
+/// let right = vec![-1, -2, -3];
+/// let stereo = AudioBuffer::from_planar(&[left, right]);
+///
+/// assert_eq!(stereo.channels(), 2);
+/// assert_eq!(stereo.frames(), 3);
+/// assert_eq!(stereo.interleaved(), &[1, -1, 2, -2, 3, -3]);
+/// assert_eq!(stereo.channel(1), vec![-1, -2, -3]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioBuffer<T> {
+    channels: usize,
+    samples: Vec<T>,
+}
 
-    writer.write_i32::<BigEndian>(0x666d_7420)?; // Subchunk1ID, fmt
-    writer.write_i32::<LittleEndian>(16)?; // Subchunk1Size, 16 for PCM
-    writer.write_i16::<LittleEndian>(1)?; // AudioFormat, PCM = 1 (linear quantization)
-    writer.write_i16::<LittleEndian>(channels as i16)?; // NumChannels
-    writer.write_i32::<LittleEndian>(sample_rate as i32)?; // SampleRate
-    writer.write_i32::<LittleEndian>(byte_rate)?; // ByteRate
-    writer.write_i16::<LittleEndian>(block_align)?; // BlockAlign
-    writer.write_i16::<LittleEndian>(bit_depth as i16)?; // BitsPerSample
+impl<T: Copy> AudioBuffer<T> {
+    /// Creates a buffer from interleaved samples. The number of samples must be a multiple of
+    /// `channels`.
+    pub fn from_interleaved(channels: usize, samples: Vec<T>) -> AudioBuffer<T> {
+        assert!(channels > 0, "audio must have at least one channel");
+        assert!(
+            samples.len().is_multiple_of(channels),
+            "interleaved samples must contain whole frames"
+        );
+        AudioBuffer { channels, samples }
+    }
 
-    writer.write_i32::<BigEndian>(0x6461_7461)?; // Subchunk2ID, data
-    writer.write_i32::<LittleEndian>(subchunk_2_size as i32)?; // Subchunk2Size, number of bytes in the data
+    /// Creates a buffer from one `Vec` or slice of samples per channel, all of the same length.
+    pub fn from_planar<C>(channels: &[C]) -> AudioBuffer<T>
+    where
+        C: AsRef<[T]>,
+    {
+        assert!(!channels.is_empty(), "audio must have at least one channel");
+        let frames = channels[0].as_ref().len();
+        assert!(
+            channels
+                .iter()
+                .all(|channel| channel.as_ref().len() == frames),
+            "all channels must be the same length"
+        );
 
-    for sample in samples {
-        writer.write_i16::<LittleEndian>(*sample)?
+        let samples = (0..frames)
+            .flat_map(|i| channels.iter().map(move |channel| channel.as_ref()[i]))
+            .collect();
+        AudioBuffer {
+            channels: channels.len(),
+            samples,
+        }
     }
 
-    Ok(())
+    /// Creates a single channel buffer.
+    pub fn from_mono(samples: Vec<T>) -> AudioBuffer<T> {
+        AudioBuffer::from_interleaved(1, samples)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Number of frames, or samples per channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn interleaved(&self) -> &[T] {
+        &self.samples
+    }
+
+    pub fn into_interleaved(self) -> Vec<T> {
+        self.samples
+    }
+
+    /// Samples of frame `index`, one per channel.
+    pub fn frame(&self, index: usize) -> &[T] {
+        &self.samples[index * self.channels..(index + 1) * self.channels]
+    }
+
+    /// Iterates over frames, each a slice with one sample per channel.
+    pub fn iter_frames(&self) -> std::slice::Chunks<'_, T> {
+        self.samples.chunks(self.channels)
+    }
+
+    /// Copies out the samples of one channel.
+    pub fn channel(&self, channel: usize) -> Vec<T> {
+        assert!(channel < self.channels, "channel out of range");
+        self.samples
+            .iter()
+            .skip(channel)
+            .step_by(self.channels)
+            .cloned()
+            .collect()
+    }
+
+    /// Copies out every channel.
+    pub fn to_planar(&self) -> Vec<Vec<T>> {
+        (0..self.channels)
+            .map(|channel| self.channel(channel))
+            .collect()
+    }
 }
 
-// Borrowing of packed &wave.pcm is unsafe
-// #[repr(C, packed)]
-/// Representation of a WAV file. Does not contain fields for extended WAV formats.
-#[repr(C)]
-#[derive(Debug, Clone)]
-pub struct Wave {
-    pub chunk_id: i32,
-    pub chunk_size: i32,
-    pub format: i32,
-    pub subchunk_1_id: i32,
-    pub subchunk_1_size: i32,
-    /// 1 = PCM
-    pub audio_format: i16,
-    pub num_channels: i16,
-    pub sample_rate: i32,
-    pub byte_rate: i32,
-    pub block_align: i16,
-    pub bits_per_sample: i16,
-    pub subchunk_2_id: i32,
-    pub subchunk_2_size: i32,
-    pub pcm: Vec<i16>,
+/// Speaker position bits for `WavSpec::channel_mask`, as used by `WAVE_FORMAT_EXTENSIBLE`.
+pub mod speaker {
+    pub const FRONT_LEFT: u32 = 0x1;
+    pub const FRONT_RIGHT: u32 = 0x2;
+    pub const FRONT_CENTER: u32 = 0x4;
+    pub const LOW_FREQUENCY: u32 = 0x8;
+    pub const BACK_LEFT: u32 = 0x10;
+    pub const BACK_RIGHT: u32 = 0x20;
+    pub const FRONT_LEFT_OF_CENTER: u32 = 0x40;
+    pub const FRONT_RIGHT_OF_CENTER: u32 = 0x80;
+    pub const BACK_CENTER: u32 = 0x100;
+    pub const SIDE_LEFT: u32 = 0x200;
+    pub const SIDE_RIGHT: u32 = 0x400;
 }
 
-/// Reads a wave file given a file path. Convenience wrapper around `crate::writer::read_wav_file`.
-/// ```
-/// use synthrs::writer;
-///
-/// let wave = writer::read_wav_file("./tests/assets/sine.wav").unwrap();
-/// assert_eq!(wave.num_channels, 1);
-/// ```
-pub fn read_wav_file(filename: &str) -> Result<Wave> {
-    let path = Path::new(filename);
-    let file = OpenOptions::new().read(true).open(&path)?;
-    let mut reader = BufReader::new(file);
-    read_wav(&mut reader)
+/// The usual speaker layout for a number of channels: mono, stereo, 3.0, quad, 5.0, 5.1, 6.1
+/// and 7.1. Other channel counts fill speaker positions in order, or have no positions if there
+/// are more channels than positions.
+pub fn default_channel_mask(channels: usize) -> u32 {
+    use self::speaker::*;
+
+    match channels {
+        1 => FRONT_CENTER,
+        2 => FRONT_LEFT | FRONT_RIGHT,
+        3 => FRONT_LEFT | FRONT_RIGHT | FRONT_CENTER,
+        4 => FRONT_LEFT | FRONT_RIGHT | BACK_LEFT | BACK_RIGHT,
+        5 => FRONT_LEFT | FRONT_RIGHT | FRONT_CENTER | BACK_LEFT | BACK_RIGHT,
+        6 => FRONT_LEFT | FRONT_RIGHT | FRONT_CENTER | LOW_FREQUENCY | BACK_LEFT | BACK_RIGHT,
+        7 => {
+            FRONT_LEFT
+                | FRONT_RIGHT
+                | FRONT_CENTER
+                | LOW_FREQUENCY
+                | BACK_CENTER
+                | SIDE_LEFT
+                | SIDE_RIGHT
+        }
+        8 => {
+            FRONT_LEFT
+                | FRONT_RIGHT
+                | FRONT_CENTER
+                | LOW_FREQUENCY
+                | BACK_LEFT
+                | BACK_RIGHT
+                | SIDE_LEFT
+                | SIDE_RIGHT
+        }
+        n if n < 18 => (1 << n) - 1,
+        _ => 0,
+    }
 }
 
-/// Reads a wave file. Only supports mono 16-bit, little-endian, signed PCM WAV files
-///
-/// ### Useful commands:
-///
-/// * Use `ffmpeg -i .\example.wav` to inspect a wav
-/// * Use `ffmpeg -i "dirty.wav" -f wav -flags +bitexact -acodec pcm_s16le -ar 44100 -ac 1 "clean.wav"` to clean a WAV
-///
-/// ```
-/// use std::path::Path;
-/// use std::fs::OpenOptions;
-/// use std::io::BufReader;
-/// use synthrs::writer;
-///
-/// let path = Path::new("./tests/assets/sine.wav");
-/// let file = OpenOptions::new().read(true).open(&path).unwrap();
-/// let mut reader = BufReader::new(file);
-///
-/// let wave = writer::read_wav(&mut reader).unwrap();
-/// assert_eq!(wave.num_channels, 1);
-/// ```
-// For -acodec values, see https://trac.ffmpeg.org/wiki/audio%20types
-pub fn read_wav<R>(reader: &mut R) -> Result<Wave>
+/// Layout of the audio in a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WavSpec {
+    pub channels: usize,
+    pub sample_rate: usize,
+    /// Speaker positions of the channels, using the bits in `speaker`. If `None`, uses
+    /// `default_channel_mask`. Files with more than two channels or a mask set are written as
+    /// `WAVE_FORMAT_EXTENSIBLE`.
+    pub channel_mask: Option<u32>,
+}
+
+impl WavSpec {
+    pub fn new(channels: usize, sample_rate: usize) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate,
+            channel_mask: None,
+        }
+    }
+
+    fn is_extensible(&self) -> bool {
+        self.channels > 2 || self.channel_mask.is_some()
+    }
+}
+
+/// `KSDATAFORMAT_SUBTYPE_PCM`, as stored in a `WAVE_FORMAT_EXTENSIBLE` fmt chunk. Other
+/// SubFormats, such as IEEE float, replace the leading `AudioFormat`.
+const SUBFORMAT_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+/// Storage format of samples in a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit integer, centred on 128
+    U8,
+    /// Signed 16-bit integer
+    I16,
+    /// Signed 24-bit integer, packed into 3 bytes
+    I24,
+    /// Signed 32-bit integer
+    I32,
+    /// 32-bit IEEE float
+    F32,
+    /// 64-bit IEEE float
+    F64,
+}
+
+impl SampleFormat {
+    pub fn bits_per_sample(self) -> usize {
+        match self {
+            SampleFormat::U8 => 8,
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    pub fn bytes_per_sample(self) -> usize {
+        self.bits_per_sample() / 8
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, SampleFormat::F32 | SampleFormat::F64)
+    }
+}
+
+/// Scales a sample in [-1.0, 1.0] to a signed integer of `bits` bits, clipping.
+fn scale_sample(sample: f64, bits: i32) -> f64 {
+    let max = 2.0f64.powi(bits - 1) - 1.0;
+    (sample.clamp(-1.0, 1.0) * max).round()
+}
+
+/// Writes a single sample in [-1.0, 1.0] as `format`, little-endian.
+fn write_sample_le<W>(writer: &mut W, format: SampleFormat, sample: f64) -> Result<()>
 where
-    R: Read,
+    W: Write,
 {
-    let chunk_id = reader.read_i32::<BigEndian>()?; // ChunkID, RIFF
-    let chunk_size = reader.read_i32::<LittleEndian>()?; // ChunkSize
-    let format = reader.read_i32::<BigEndian>()?; // Format, WAVE
-    if format != 0x5741_5645 {
-        return Err(Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "file is not a WAV".to_string(),
-        ));
+    match format {
+        SampleFormat::U8 => writer.write_u8((scale_sample(sample, 8) + 128.0) as u8),
+        SampleFormat::I16 => writer.write_i16::<LittleEndian>(scale_sample(sample, 16) as i16),
+        SampleFormat::I24 => writer.write_i24::<LittleEndian>(scale_sample(sample, 24) as i32),
+        SampleFormat::I32 => writer.write_i32::<LittleEndian>(scale_sample(sample, 32) as i32),
+        SampleFormat::F32 => writer.write_f32::<LittleEndian>(sample as f32),
+        SampleFormat::F64 => writer.write_f64::<LittleEndian>(sample),
     }
+}
 
-    let subchunk_1_id = reader.read_i32::<BigEndian>()?; // Subchunk1ID, fmt
-    let subchunk_1_size = reader.read_i32::<LittleEndian>()?; // Subchunk1Size, Chunk size: 16, 18 or 40
+/// Samples in their stored format. 24-bit samples are held in the low bits of an `i32`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PcmData {
+    U8(Vec<u8>),
+    I16(Vec<i16>),
+    I24(Vec<i32>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
 
-    let audio_format = reader.read_i16::<LittleEndian>()?; // AudioFormat, PCM = 1 (linear quantization)
-    if audio_format != 1 {
-        return Err(Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!(
-                "only 16-bit little-endian signed PCM WAV supported, audio_format: {}",
-                audio_format
-            ),
-        ));
-    }
+impl PcmData {
+    /// Converts samples in [-1.0, 1.0] to `format`, clipping anything outside that range for
+    /// integer formats.
+    ///
+    /// ```
+    /// use synthrs::writer::{PcmData, SampleFormat};
+    ///
+    /// let data = PcmData::from_f64(&[-1.0, 0.0, 1.0], SampleFormat::I24);
+    /// assert_eq!(data, PcmData::I24(vec![-8_388_607, 0, 8_388_607]));
+    /// ```
+    pub fn from_f64(samples: &[f64], format: SampleFormat) -> PcmData {
+        let scale = |bits: i32| {
+            samples
+                .iter()
+                .map(move |&sample| scale_sample(sample, bits))
+        };
 
-    let num_channels = reader.read_i16::<LittleEndian>()?; // NumChannels
-    let sample_rate = reader.read_i32::<LittleEndian>()?; // SampleRate
-    let byte_rate = reader.read_i32::<LittleEndian>()?; // ByteRate
-    let block_align = reader.read_i16::<LittleEndian>()?; // BlockAlign
-    let bits_per_sample = reader.read_i16::<LittleEndian>()?; // BitsPerSample
+        match format {
+            SampleFormat::U8 => PcmData::U8(scale(8).map(|x| (x + 128.0) as u8).collect()),
+            SampleFormat::I16 => PcmData::I16(scale(16).map(|x| x as i16).collect()),
+            SampleFormat::I24 => PcmData::I24(scale(24).map(|x| x as i32).collect()),
+            SampleFormat::I32 => PcmData::I32(scale(32).map(|x| x as i32).collect()),
+            SampleFormat::F32 => PcmData::F32(samples.iter().map(|&x| x as f32).collect()),
+            SampleFormat::F64 => PcmData::F64(samples.to_vec()),
+        }
+    }
 
-    let extra_bytes = subchunk_1_size - 16;
+    /// Converts samples to `f64`, with integer formats scaled to [-1.0, 1.0).
+    pub fn to_f64(&self) -> Vec<f64> {
+        match self {
+            PcmData::U8(samples) => samples
+                .iter()
+                .map(|&x| (f64::from(x) - 128.0) / 128.0)
+                .collect(),
+            PcmData::I16(samples) => samples.iter().map(|&x| f64::from(x) / 32_768.0).collect(),
+            PcmData::I24(samples) => samples
+                .iter()
+                .map(|&x| f64::from(x) / 8_388_608.0)
+                .collect(),
+            PcmData::I32(samples) => samples
+                .iter()
+                .map(|&x| f64::from(x) / 2_147_483_648.0)
+                .collect(),
+            PcmData::F32(samples) => samples.iter().map(|&x| f64::from(x)).collect(),
+            PcmData::F64(samples) => samples.clone(),
+        }
+    }
 
-    if extra_bytes > 0 {
-        let extension_size = reader.read_i16::<LittleEndian>()?; // Size of the extension (0 or 22)
-        if extension_size == 22 {
-            // TODO: Add this to `Wave` as optionals
-            let _valid_bits_per_sample = reader.read_i16::<LittleEndian>()?;
-            let _speaker_mask = reader.read_i16::<LittleEndian>()?;
-            let _subformat = reader.read_i16::<BigEndian>()?;
-        } else if extension_size != 0 {
-            return Err(Error::new(
-                std::io::ErrorKind::InvalidInput,
-                format!(
-                    "unexpected fmt chunk extension size: should be 0 or 22 but got: {}",
-                    extension_size
-                ),
-            ));
+    /// Converts samples to signed 16-bit, keeping the most significant bits of deeper formats.
+    pub fn to_i16(&self) -> Vec<i16> {
+        match self {
+            PcmData::U8(samples) => samples.iter().map(|&x| (i16::from(x) - 128) << 8).collect(),
+            PcmData::I16(samples) => samples.clone(),
+            PcmData::I24(samples) => samples.iter().map(|&x| (x >> 8) as i16).collect(),
+            PcmData::I32(samples) => samples.iter().map(|&x| (x >> 16) as i16).collect(),
+            PcmData::F32(_) | PcmData::F64(_) => self
+                .to_f64()
+                .iter()
+                .map(|x| (x.clamp(-1.0, 1.0) * 32_767.0).round() as i16)
+                .collect(),
         }
     }
 
-    let subchunk_2_id = reader.read_i32::<BigEndian>()?; // Subchunk2ID, data
-    if subchunk_2_id != 0x6461_7461 {
-        return Err(Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!(
-                "unexpected subchunk name: expecting data, got: {}",
-                subchunk_2_id
-            ),
-        ));
+    pub fn format(&self) -> SampleFormat {
+        match self {
+            PcmData::U8(_) => SampleFormat::U8,
+            PcmData::I16(_) => SampleFormat::I16,
+            PcmData::I24(_) => SampleFormat::I24,
+            PcmData::I32(_) => SampleFormat::I32,
+            PcmData::F32(_) => SampleFormat::F32,
+            PcmData::F64(_) => SampleFormat::F64,
+        }
     }
-    let subchunk_2_size = reader.read_i32::<LittleEndian>()?; // Subchunk2Size, number of bytes in the data
 
-    let mut pcm: Vec<i16> = Vec::with_capacity(2 * subchunk_2_size as usize);
+    pub fn len(&self) -> usize {
+        match self {
+            PcmData::U8(samples) => samples.len(),
+            PcmData::I16(samples) => samples.len(),
+            PcmData::I24(samples) | PcmData::I32(samples) => samples.len(),
+            PcmData::F32(samples) => samples.len(),
+            PcmData::F64(samples) => samples.len(),
+        }
+    }
 
-    // `reader.read_into_i16(&pcm)` doesn't seem to work here due to bad input?
-    // It just does nothing and &pcm is left empty after that.
-    for _i in 0..subchunk_2_size {
-        let sample = reader.read_i16::<LittleEndian>().unwrap_or(0);
-        pcm.push(sample);
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    let wave = Wave {
-        chunk_id,
-        chunk_size,
-        format,
-        subchunk_1_id,
-        subchunk_1_size,
-        audio_format,
-        num_channels,
-        sample_rate,
-        byte_rate,
-        block_align,
-        bits_per_sample,
-        subchunk_2_id,
-        subchunk_2_size,
-        pcm,
-    };
+    /// Writes samples in little-endian byte order.
+    fn write_le<W>(&self, writer: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        match self {
+            PcmData::U8(samples) => writer.write_all(samples)?,
+            PcmData::I16(samples) => {
+                for &sample in samples {
+                    writer.write_i16::<LittleEndian>(sample)?;
+                }
+            }
+            PcmData::I24(samples) => {
+                for &sample in samples {
+                    writer.write_i24::<LittleEndian>(sample)?;
+                }
+            }
+            PcmData::I32(samples) => {
+                for &sample in samples {
+                    writer.write_i32::<LittleEndian>(sample)?;
+                }
+            }
+            PcmData::F32(samples) => {
+                for &sample in samples {
+                    writer.write_f32::<LittleEndian>(sample)?;
+                }
+            }
+            PcmData::F64(samples) => {
+                for &sample in samples {
+                    writer.write_f64::<LittleEndian>(sample)?;
+                }
+            }
+        }
 
-    Ok(wave)
+        Ok(())
+    }
+
+    /// Reads samples of `format` from little-endian bytes, ignoring any trailing partial sample.
+    fn read_le(bytes: &[u8], format: SampleFormat) -> PcmData {
+        let samples = bytes.chunks_exact(format.bytes_per_sample());
+        match format {
+            SampleFormat::U8 => PcmData::U8(bytes.to_vec()),
+            SampleFormat::I16 => PcmData::I16(samples.map(LittleEndian::read_i16).collect()),
+            SampleFormat::I24 => PcmData::I24(samples.map(LittleEndian::read_i24).collect()),
+            SampleFormat::I32 => PcmData::I32(samples.map(LittleEndian::read_i32).collect()),
+            SampleFormat::F32 => PcmData::F32(samples.map(LittleEndian::read_f32).collect()),
+            SampleFormat::F64 => PcmData::F64(samples.map(LittleEndian::read_f64).collect()),
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Sample encodings of raw PCM data, as listed in Audacity's raw data import dialog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RawEncoding {
+    Signed8,
+    Unsigned8,
+    Signed16,
+    Unsigned16,
+    Signed24,
+    Unsigned24,
+    Signed32,
+    Unsigned32,
+    Float32,
+    Float64,
+    /// G.711 μ-law, see `crate::g711`
+    MuLaw,
+    /// G.711 A-law, see `crate::g711`
+    ALaw,
+}
 
-    #[test]
-    fn test_read_wav() {
-        // 1 second at 44,100Hz, PCM 16 (2-byte)
-        let wave = read_wav_file("./tests/assets/sine.wav").unwrap();
+impl RawEncoding {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            RawEncoding::Signed8 | RawEncoding::Unsigned8 => 1,
+            RawEncoding::MuLaw | RawEncoding::ALaw => 1,
+            RawEncoding::Signed16 | RawEncoding::Unsigned16 => 2,
+            RawEncoding::Signed24 | RawEncoding::Unsigned24 => 3,
+            RawEncoding::Signed32 | RawEncoding::Unsigned32 | RawEncoding::Float32 => 4,
+            RawEncoding::Float64 => 8,
+        }
+    }
 
-        assert_eq!(wave.format, 0x5741_5645); // WAVE
-        assert_eq!(wave.audio_format, 1);
-        assert_eq!(wave.num_channels, 1);
-        assert_eq!(wave.sample_rate, 44_100);
-        assert_eq!(wave.byte_rate, 88_200);
-        assert_eq!(wave.block_align, 2);
-        assert_eq!(wave.bits_per_sample, 16);
-        assert_eq!(wave.subchunk_2_size, 88_200);
-        assert_eq!(wave.pcm.len(), 88_200);
+    /// The encoding WAV files use for `format`, where 8-bit samples are unsigned.
+    fn for_wav(format: SampleFormat) -> RawEncoding {
+        match format {
+            SampleFormat::U8 => RawEncoding::Unsigned8,
+            SampleFormat::I16 => RawEncoding::Signed16,
+            SampleFormat::I24 => RawEncoding::Signed24,
+            SampleFormat::I32 => RawEncoding::Signed32,
+            SampleFormat::F32 => RawEncoding::Float32,
+            SampleFormat::F64 => RawEncoding::Float64,
+        }
     }
 
-    #[test]
-    fn test_write_read_wav() {
-        use crate::synthesizer::{make_samples, quantize_samples};
-        use crate::wave::sine_wave;
-        use crate::writer::write_wav;
-        use std::io::{Cursor, Seek, SeekFrom};
+    /// The G.711 law of companded encodings.
+    fn law(self) -> Option<Law> {
+        match self {
+            RawEncoding::MuLaw => Some(Law::MuLaw),
+            RawEncoding::ALaw => Some(Law::ALaw),
+            _ => None,
+        }
+    }
 
-        let output_buffer: Vec<u8> = Vec::new();
-        let mut output_writer = Cursor::new(output_buffer);
+    /// The `SampleFormat` with the same size, or `None` for companded encodings.
+    fn pcm_format(self) -> Option<SampleFormat> {
+        match self {
+            RawEncoding::Signed8 | RawEncoding::Unsigned8 => Some(SampleFormat::U8),
+            RawEncoding::Signed16 | RawEncoding::Unsigned16 => Some(SampleFormat::I16),
+            RawEncoding::Signed24 | RawEncoding::Unsigned24 => Some(SampleFormat::I24),
+            RawEncoding::Signed32 | RawEncoding::Unsigned32 => Some(SampleFormat::I32),
+            RawEncoding::Float32 => Some(SampleFormat::F32),
+            RawEncoding::Float64 => Some(SampleFormat::F64),
+            RawEncoding::MuLaw | RawEncoding::ALaw => None,
+        }
+    }
 
-        write_wav(
-            &mut output_writer,
-            44_100,
-            &quantize_samples::<i16>(&make_samples(0.1, 44_100, sine_wave(440.0))),
+    /// Whether the encoding differs from its `SampleFormat` in signedness.
+    fn flips_sign(self) -> bool {
+        matches!(
+            self,
+            RawEncoding::Signed8
+                | RawEncoding::Unsigned16
+                | RawEncoding::Unsigned24
+                | RawEncoding::Unsigned32
         )
-        .unwrap();
+    }
+}
 
-        let _ = output_writer.seek(SeekFrom::Start(0));
-        let wave = read_wav(&mut output_writer).unwrap();
-        assert_eq!(wave.format, 0x5741_5645); // WAVE
-        assert_eq!(wave.audio_format, 1);
-        assert_eq!(wave.num_channels, 1);
-        assert_eq!(wave.sample_rate, 44_100);
-        assert_eq!(wave.byte_rate, 88_200);
-        assert_eq!(wave.block_align, 2);
-        assert_eq!(wave.bits_per_sample, 16);
-        assert_eq!(wave.subchunk_2_size, 8820);
-        assert_eq!(wave.pcm.len(), 8820);
+/// Byte order of multi-byte samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Layout of raw PCM data, matching the Audacity import settings. The sample rate is not stored
+/// in raw data, and must be known separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawPcmSpec {
+    pub encoding: RawEncoding,
+    pub endianness: Endianness,
+    pub channels: usize,
+}
+
+impl RawPcmSpec {
+    pub fn new(encoding: RawEncoding, endianness: Endianness, channels: usize) -> RawPcmSpec {
+        RawPcmSpec {
+            encoding,
+            endianness,
+            channels,
+        }
+    }
+}
+
+impl Default for RawPcmSpec {
+    /// Signed 16-bit, little-endian mono, as written by `write_pcm`.
+    fn default() -> RawPcmSpec {
+        RawPcmSpec::new(RawEncoding::Signed16, Endianness::Little, 1)
+    }
+}
+
+/// Flips the sign bit of each little-endian sample, converting between signed and unsigned.
+fn flip_sign_bits(bytes: &mut [u8], bytes_per_sample: usize) {
+    for sample in bytes.chunks_exact_mut(bytes_per_sample) {
+        sample[bytes_per_sample - 1] ^= 0x80;
+    }
+}
+
+/// Encodes samples in [-1.0, 1.0] as `encoding`.
+fn encode_raw(samples: &[f64], encoding: RawEncoding, endianness: Endianness) -> Result<Vec<u8>> {
+    let format = match (encoding.pcm_format(), encoding.law()) {
+        (Some(format), _) => format,
+        (None, law) => {
+            let law = law.expect("encoding is either PCM or companded");
+            return Ok(PcmData::from_f64(samples, SampleFormat::I16)
+                .to_i16()
+                .into_iter()
+                .map(|sample| law.encode(sample))
+                .collect());
+        }
+    };
+
+    let mut bytes = Vec::with_capacity(samples.len() * encoding.bytes_per_sample());
+    PcmData::from_f64(samples, format).write_le(&mut bytes)?;
+    if encoding.flips_sign() {
+        flip_sign_bits(&mut bytes, encoding.bytes_per_sample());
+    }
+    if endianness == Endianness::Big {
+        swap_sample_bytes(&mut bytes, encoding.bytes_per_sample());
+    }
+    Ok(bytes)
+}
+
+/// Decodes whole samples of `encoding`, with companded encodings expanded to 16-bit.
+fn decode_raw(bytes: &[u8], encoding: RawEncoding, endianness: Endianness) -> PcmData {
+    let width = encoding.bytes_per_sample();
+    let mut bytes = bytes[..bytes.len() / width * width].to_vec();
+
+    match encoding.pcm_format() {
+        Some(format) => {
+            if endianness == Endianness::Big {
+                swap_sample_bytes(&mut bytes, width);
+            }
+            if encoding.flips_sign() {
+                flip_sign_bits(&mut bytes, width);
+            }
+            PcmData::read_le(&bytes, format)
+        }
+        None => {
+            let law = encoding.law().expect("encoding is either PCM or companded");
+            PcmData::I16(bytes.into_iter().map(|code| law.decode(code)).collect())
+        }
+    }
+}
+
+fn check_frames(num_samples: usize, channels: usize) -> Result<()> {
+    if channels == 0 || !num_samples.is_multiple_of(channels) {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{} samples cannot be split into frames of {} channels",
+                num_samples, channels
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Creates a file at `filename` and writes interleaved samples to it as raw PCM.
+/// See `write_raw_pcm`.
+pub fn write_raw_pcm_file(filename: &str, spec: &RawPcmSpec, samples: &[f64]) -> Result<()> {
+    let path = Path::new(filename);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    write_raw_pcm(&mut f, spec, samples)
+}
+
+/// Writes interleaved samples in [-1.0, 1.0] to a `Write` as raw PCM laid out as in `spec`.
+/// Import the result in Audacity with the same encoding, byte order and channels.
+///
+/// ```
+/// use synthrs::synthesizer::make_samples;
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::{read_raw_pcm, write_raw_pcm, Endianness, RawEncoding, RawPcmSpec};
+///
+/// let samples = make_samples(0.1, 44_100, sine_wave(440.0));
+/// let spec = RawPcmSpec::new(RawEncoding::Unsigned24, Endianness::Big, 1);
+///
+/// let mut output = Vec::new();
+/// write_raw_pcm(&mut output, &spec, &samples).unwrap();
+/// assert_eq!(output.len(), 4410 * 3);
+///
+/// let read = read_raw_pcm(&mut &output[..], &spec).unwrap();
+/// assert!((read[100] - samples[100]).abs() < 1e-6);
+/// ```
+pub fn write_raw_pcm<W>(writer: &mut W, spec: &RawPcmSpec, samples: &[f64]) -> Result<()>
+where
+    W: Write,
+{
+    check_frames(samples.len(), spec.channels)?;
+    writer.write_all(&encode_raw(samples, spec.encoding, spec.endianness)?)
+}
+
+/// Reads a raw PCM file given a file path. See `read_raw_pcm`.
+pub fn read_raw_pcm_file(filename: &str, spec: &RawPcmSpec) -> Result<Vec<f64>> {
+    let path = Path::new(filename);
+    let file = OpenOptions::new().read(true).open(path)?;
+    let mut reader = BufReader::new(file);
+    read_raw_pcm(&mut reader, spec)
+}
+
+/// Reads raw PCM laid out as in `spec` until the end of `reader`, returning interleaved samples
+/// in [-1.0, 1.0). A partial frame at the end is dropped.
+pub fn read_raw_pcm<R>(reader: &mut R, spec: &RawPcmSpec) -> Result<Vec<f64>>
+where
+    R: Read,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let frame_size = spec.encoding.bytes_per_sample() * spec.channels.max(1);
+    bytes.truncate(bytes.len() / frame_size * frame_size);
+    Ok(decode_raw(&bytes, spec.encoding, spec.endianness).to_f64())
+}
+
+/// Largest size that fits in the 32-bit size fields of a RIFF file
+const RIFF_SIZE_LIMIT: u64 = 0xffff_ffff;
+
+/// Size of a `ds64` chunk without a table, including its header
+const DS64_CHUNK_SIZE: u64 = 36;
+
+/// Container of a WAV header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HeaderLayout {
+    /// RIFF, or RF64 if the sizes do not fit
+    Auto,
+    /// RIFF with a `JUNK` chunk reserving space for a `ds64` chunk, so that the header can be
+    /// promoted to RF64 later
+    Reserved,
+    /// RF64, with sizes in a `ds64` chunk
+    Rf64,
+}
+
+/// Writes the RIFF header, fmt chunk, `metadata` chunks already serialized, fact chunk for float
+/// and G.711 encodings, and the start of the data chunk for `num_samples` samples.
+fn write_wav_header<W>(
+    writer: &mut W,
+    spec: &WavSpec,
+    encoding: RawEncoding,
+    num_samples: u64,
+    layout: HeaderLayout,
+    metadata: &[u8],
+) -> Result<()>
+where
+    W: Write,
+{
+    // See: http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html
+    // Some WAV header fields
+    let channels = spec.channels;
+    let bit_depth = encoding.bytes_per_sample() * 8;
+    // AudioFormat: PCM = 1 (linear quantization), IEEE float = 3, A-law = 6, μ-law = 7
+    let audio_format: u16 = match encoding {
+        RawEncoding::Float32 | RawEncoding::Float64 => 3,
+        RawEncoding::ALaw => 6,
+        RawEncoding::MuLaw => 7,
+        _ => 1,
+    };
+    let is_pcm = audio_format == 1;
+    if channels == 0 || !num_samples.is_multiple_of(channels as u64) {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{} samples cannot be split into frames of {} channels",
+                num_samples, channels
+            ),
+        ));
+    }
+
+    // Non-PCM formats need a cbSize field, even if there is no extension, and a fact chunk
+    let subchunk_1_size = match (spec.is_extensible(), is_pcm) {
+        (true, _) => 40,
+        (false, false) => 18,
+        (false, true) => 16,
+    };
+    let fact_size = if is_pcm { 0 } else { 12 };
+    let subchunk_2_size = num_samples * bit_depth as u64 / 8;
+    let padding = subchunk_2_size % 2;
+    let frames = num_samples / channels as u64;
+    let byte_rate = (spec.sample_rate * channels * bit_depth / 8) as i32;
+    let block_align = (channels * bit_depth / 8) as i16;
+
+    let riff_size =
+        4 + 8 + subchunk_1_size + metadata.len() as u64 + fact_size + 8 + subchunk_2_size + padding;
+    let layout = match layout {
+        HeaderLayout::Auto if riff_size > RIFF_SIZE_LIMIT => HeaderLayout::Rf64,
+        layout => layout,
+    };
+    let riff_size = match layout {
+        HeaderLayout::Auto => riff_size,
+        HeaderLayout::Reserved | HeaderLayout::Rf64 => riff_size + DS64_CHUNK_SIZE,
+    };
+    // Sizes that do not fit are stored in the ds64 chunk instead
+    let size_field = |size: u64| match layout {
+        HeaderLayout::Rf64 => crate::riff::UNKNOWN_SIZE,
+        _ => size as u32,
+    };
+
+    if layout == HeaderLayout::Rf64 {
+        writer.write_all(b"RF64")?; // ChunkID
+    } else {
+        writer.write_i32::<BigEndian>(0x5249_4646)?; // ChunkID, RIFF
+    }
+    writer.write_u32::<LittleEndian>(size_field(riff_size))?; // ChunkSize
+    writer.write_i32::<BigEndian>(0x5741_5645)?; // Format, WAVE
+
+    match layout {
+        HeaderLayout::Auto => {}
+        HeaderLayout::Reserved => {
+            writer.write_all(b"JUNK")?;
+            writer.write_u32::<LittleEndian>(DS64_CHUNK_SIZE as u32 - 8)?;
+            writer.write_all(&[0; DS64_CHUNK_SIZE as usize - 8])?;
+        }
+        HeaderLayout::Rf64 => {
+            let ds64 = Ds64 {
+                riff_size,
+                data_size: subchunk_2_size,
+                sample_count: frames,
+                table: Vec::new(),
+            };
+            writer.write_all(b"ds64")?;
+            writer.write_u32::<LittleEndian>(DS64_CHUNK_SIZE as u32 - 8)?;
+            writer.write_all(&ds64.to_bytes())?;
+        }
+    }
+
+    writer.write_i32::<BigEndian>(0x666d_7420)?; // Subchunk1ID, fmt
+    writer.write_i32::<LittleEndian>(subchunk_1_size as i32)?; // Subchunk1Size, 16 for PCM, 18 for other formats, 40 for extensible
+    if spec.is_extensible() {
+        writer.write_u16::<LittleEndian>(0xfffe)?; // AudioFormat, WAVE_FORMAT_EXTENSIBLE
+    } else {
+        writer.write_u16::<LittleEndian>(audio_format)?; // AudioFormat
+    }
+    writer.write_i16::<LittleEndian>(channels as i16)?; // NumChannels
+    writer.write_i32::<LittleEndian>(spec.sample_rate as i32)?; // SampleRate
+    writer.write_i32::<LittleEndian>(byte_rate)?; // ByteRate
+    writer.write_i16::<LittleEndian>(block_align)?; // BlockAlign
+    writer.write_i16::<LittleEndian>(bit_depth as i16)?; // BitsPerSample
+
+    if spec.is_extensible() {
+        let channel_mask = spec
+            .channel_mask
+            .unwrap_or_else(|| default_channel_mask(channels));
+        writer.write_u16::<LittleEndian>(22)?; // Size of the extension
+        writer.write_u16::<LittleEndian>(bit_depth as u16)?; // ValidBitsPerSample
+        writer.write_u32::<LittleEndian>(channel_mask)?; // ChannelMask
+
+        // The SubFormat GUID is the PCM one with its first two bytes set to the AudioFormat
+        let mut subformat = SUBFORMAT_PCM;
+        LittleEndian::write_u16(&mut subformat[..2], audio_format);
+        writer.write_all(&subformat)?; // SubFormat
+    } else if !is_pcm {
+        writer.write_u16::<LittleEndian>(0)?; // Size of the extension
+    }
+
+    writer.write_all(metadata)?;
+
+    if !is_pcm {
+        writer.write_i32::<BigEndian>(0x6661_6374)?; // fact
+        writer.write_i32::<LittleEndian>(4)?;
+        writer.write_u32::<LittleEndian>(size_field(frames))?; // Number of frames
+    }
+
+    writer.write_i32::<BigEndian>(0x6461_7461)?; // Subchunk2ID, data
+    writer.write_u32::<LittleEndian>(size_field(subchunk_2_size))?; // Subchunk2Size, number of bytes in the data
+
+    Ok(())
+}
+
+/// Writes interleaved `&[i16]` samples with the channel layout in `spec` to a `Write`.
+///
+/// ```
+/// use std::io::Cursor;
+/// use synthrs::writer::{speaker, write_wav_with_spec, WavSpec};
+///
+/// // Two channels routed to the side speakers
+/// let spec = WavSpec {
+///     channel_mask: Some(speaker::SIDE_LEFT | speaker::SIDE_RIGHT),
+///     ..WavSpec::new(2, 44_100)
+/// };
+///
+/// let mut output_writer = Cursor::new(Vec::new());
+/// write_wav_with_spec(&mut output_writer, &spec, &[0, 0, 100, -100]).unwrap();
+/// ```
+pub fn write_wav_with_spec<W>(writer: &mut W, spec: &WavSpec, samples: &[i16]) -> Result<()>
+where
+    W: Write,
+{
+    write_wav_header(
+        writer,
+        spec,
+        RawEncoding::Signed16,
+        samples.len() as u64,
+        HeaderLayout::Auto,
+        &[],
+    )?;
+
+    for sample in samples {
+        writer.write_i16::<LittleEndian>(*sample)?
+    }
+
+    Ok(())
+}
+
+/// Writes interleaved samples in any `SampleFormat` with the channel layout in `spec` to a
+/// `Write`. Float formats are written as `WAVE_FORMAT_IEEE_FLOAT` with a `fact` chunk.
+///
+/// ```
+/// use std::io::Cursor;
+/// use synthrs::synthesizer::make_samples;
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::{write_wav_data, PcmData, SampleFormat, WavSpec};
+///
+/// let samples = make_samples(0.1, 96_000, sine_wave(440.0));
+///
+/// // 24-bit, 96kHz
+/// let mut output_writer = Cursor::new(Vec::new());
+/// write_wav_data(
+///     &mut output_writer,
+///     &WavSpec::new(1, 96_000),
+///     &PcmData::from_f64(&samples, SampleFormat::I24),
+/// ).unwrap();
+/// ```
+pub fn write_wav_data<W>(writer: &mut W, spec: &WavSpec, data: &PcmData) -> Result<()>
+where
+    W: Write,
+{
+    write_wav_data_with_metadata(writer, spec, data, &WavMetadata::default())
+}
+
+/// Writes samples as in `write_wav_data`, with `metadata` chunks between the fmt and data
+/// chunks. See `crate::metadata`.
+pub fn write_wav_data_with_metadata<W>(
+    writer: &mut W,
+    spec: &WavSpec,
+    data: &PcmData,
+    metadata: &WavMetadata,
+) -> Result<()>
+where
+    W: Write,
+{
+    write_wav_header(
+        writer,
+        spec,
+        RawEncoding::for_wav(data.format()),
+        data.len() as u64,
+        HeaderLayout::Auto,
+        &metadata.to_bytes(),
+    )?;
+    data.write_le(writer)?;
+
+    // Chunks are padded to an even length
+    if data.len() * data.format().bytes_per_sample() % 2 == 1 {
+        writer.write_u8(0)?;
+    }
+
+    Ok(())
+}
+
+/// Creates a file at `filename` and writes samples in any `SampleFormat` to it as a WAVE file.
+/// See `write_wav_data`.
+pub fn write_wav_data_file(filename: &str, spec: &WavSpec, data: &PcmData) -> Result<()> {
+    let path = Path::new(filename);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    write_wav_data(&mut f, spec, data)
+}
+
+/// Writes interleaved samples in [-1.0, 1.0] to a `Write` as a G.711 A-law or μ-law WAVE file,
+/// as used by telephony systems. These are usually 8kHz mono.
+///
+/// ```
+/// use synthrs::g711::Law;
+/// use synthrs::synthesizer::make_samples;
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::{read_wav, write_wav_g711, WavSpec};
+///
+/// let samples = make_samples(0.1, 8000, sine_wave(440.0));
+///
+/// let mut output = Vec::new();
+/// write_wav_g711(&mut output, &WavSpec::new(1, 8000), Law::MuLaw, &samples).unwrap();
+///
+/// let wave = read_wav(&mut &output[..]).unwrap();
+/// assert_eq!(wave.audio_format, 7);
+/// assert_eq!(wave.bits_per_sample, 8);
+/// assert_eq!(wave.pcm.len(), 800);
+/// ```
+pub fn write_wav_g711<W>(writer: &mut W, spec: &WavSpec, law: Law, samples: &[f64]) -> Result<()>
+where
+    W: Write,
+{
+    let encoding = match law {
+        Law::MuLaw => RawEncoding::MuLaw,
+        Law::ALaw => RawEncoding::ALaw,
+    };
+    write_wav_header(
+        writer,
+        spec,
+        encoding,
+        samples.len() as u64,
+        HeaderLayout::Auto,
+        &[],
+    )?;
+    writer.write_all(&encode_raw(samples, encoding, Endianness::Little)?)?;
+
+    // Chunks are padded to an even length
+    if samples.len() % 2 == 1 {
+        writer.write_u8(0)?;
+    }
+
+    Ok(())
+}
+
+/// Creates a file at `filename` and writes samples to it as a G.711 WAVE file.
+/// See `write_wav_g711`.
+pub fn write_wav_g711_file(
+    filename: &str,
+    spec: &WavSpec,
+    law: Law,
+    samples: &[f64],
+) -> Result<()> {
+    let path = Path::new(filename);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    write_wav_g711(&mut f, spec, law, samples)
+}
+
+/// Patches fields of a WAV header written at `start`, then returns to the end.
+type HeaderPatcher<W> = fn(&mut W, u64, &[(u64, Vec<u8>)]) -> Result<()>;
+
+fn patch_header<W>(writer: &mut W, start: u64, fields: &[(u64, Vec<u8>)]) -> Result<()>
+where
+    W: Write + Seek,
+{
+    let end = writer.stream_position()?;
+    for (offset, bytes) in fields {
+        writer.seek(SeekFrom::Start(start + offset))?;
+        writer.write_all(bytes)?;
+    }
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Writes a WAV file incrementally, without needing all samples in memory.
+///
+/// A provisional header is written first, and its sizes are filled in by `finalize`, or when
+/// the writer is dropped. Space is reserved in the header so that files which grow past the
+/// 4GiB limit of RIFF are written as RF64 instead. Writers that cannot seek, such as pipes, can
+/// be used with `new_unseekable`, which writes sizes of `0xFFFFFFFF` as streaming encoders do.
+///
+/// ```
+/// use std::io::Cursor;
+/// use synthrs::synthesizer::SamplesIter;
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::{SampleFormat, WavSpec, WavWriter};
+///
+/// let mut output_writer = Cursor::new(Vec::new());
+/// let mut wav = WavWriter::new(&mut output_writer, WavSpec::new(1, 44_100), SampleFormat::I24)
+///     .unwrap();
+///
+/// // One second at a time, for ten seconds
+/// let mut sine = SamplesIter::new(44_100, Box::new(sine_wave(440.0)));
+/// for _ in 0..10 {
+///     wav.write_iter(sine.by_ref().take(44_100)).unwrap();
+/// }
+/// wav.finalize().unwrap();
+/// ```
+pub struct WavWriter<W>
+where
+    W: Write,
+{
+    /// Only `None` once moved out by `into_inner`
+    writer: Option<W>,
+    spec: WavSpec,
+    format: SampleFormat,
+    start: u64,
+    header_length: u64,
+    samples_written: u64,
+    finalized: bool,
+    patcher: Option<HeaderPatcher<W>>,
+    /// RIFF sizes above this are promoted to RF64
+    riff_size_limit: u64,
+}
+
+impl<W> WavWriter<W>
+where
+    W: Write + Seek,
+{
+    /// Writes a provisional header at the current position of `writer`.
+    pub fn new(mut writer: W, spec: WavSpec, format: SampleFormat) -> Result<WavWriter<W>> {
+        let start = writer.stream_position()?;
+        let mut wav = WavWriter::with_patcher(writer, spec, format, Some(patch_header::<W>), &[])?;
+        wav.start = start;
+        Ok(wav)
+    }
+
+    /// Writes a provisional header with `metadata` chunks before the samples.
+    pub fn new_with_metadata(
+        mut writer: W,
+        spec: WavSpec,
+        format: SampleFormat,
+        metadata: &WavMetadata,
+    ) -> Result<WavWriter<W>> {
+        let start = writer.stream_position()?;
+        let mut wav = WavWriter::with_patcher(
+            writer,
+            spec,
+            format,
+            Some(patch_header::<W>),
+            &metadata.to_bytes(),
+        )?;
+        wav.start = start;
+        Ok(wav)
+    }
+}
+
+impl<W> WavWriter<W>
+where
+    W: Write,
+{
+    /// Writes a header with unknown sizes of `0xFFFFFFFF`, for writers that cannot seek. Most
+    /// readers, including `read_wav`, then read samples until the end of the stream.
+    pub fn new_unseekable(writer: W, spec: WavSpec, format: SampleFormat) -> Result<WavWriter<W>> {
+        WavWriter::with_patcher(writer, spec, format, None, &[])
+    }
+
+    fn with_patcher(
+        mut writer: W,
+        spec: WavSpec,
+        format: SampleFormat,
+        patcher: Option<HeaderPatcher<W>>,
+        metadata: &[u8],
+    ) -> Result<WavWriter<W>> {
+        let layout = if patcher.is_some() {
+            HeaderLayout::Reserved
+        } else {
+            HeaderLayout::Auto
+        };
+        let mut header = Vec::new();
+        write_wav_header(
+            &mut header,
+            &spec,
+            RawEncoding::for_wav(format),
+            0,
+            layout,
+            metadata,
+        )?;
+        let header_length = header.len() as u64;
+
+        if patcher.is_none() {
+            let unknown = crate::riff::UNKNOWN_SIZE.to_le_bytes();
+            let length = header.len();
+            header[4..8].copy_from_slice(&unknown);
+            header[length - 4..].copy_from_slice(&unknown);
+            if format.is_float() {
+                header[length - 12..length - 8].copy_from_slice(&unknown);
+            }
+        }
+        writer.write_all(&header)?;
+
+        Ok(WavWriter {
+            writer: Some(writer),
+            spec,
+            format,
+            start: 0,
+            header_length,
+            samples_written: 0,
+            finalized: false,
+            patcher,
+            riff_size_limit: RIFF_SIZE_LIMIT,
+        })
+    }
+
+    fn writer(&mut self) -> &mut W {
+        self.writer.as_mut().expect("WAV writer has been moved out")
+    }
+
+    fn check_open(&self) -> Result<()> {
+        if self.finalized {
+            Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "WAV writer has already been finalized".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes a sample in [-1.0, 1.0], converted to the writer's `SampleFormat`. Samples of
+    /// multi-channel audio are interleaved.
+    pub fn write_sample(&mut self, sample: f64) -> Result<()> {
+        self.check_open()?;
+        let format = self.format;
+        write_sample_le(self.writer(), format, sample)?;
+        self.samples_written += 1;
+        Ok(())
+    }
+
+    /// Writes interleaved samples in [-1.0, 1.0].
+    pub fn write_samples(&mut self, samples: &[f64]) -> Result<()> {
+        self.write_iter(samples.iter().cloned())
+    }
+
+    /// Writes interleaved samples in [-1.0, 1.0] from an iterator, such as a `SamplesIter`.
+    pub fn write_iter<I>(&mut self, samples: I) -> Result<()>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        for sample in samples {
+            self.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    /// Writes samples already in the writer's `SampleFormat`.
+    pub fn write_data(&mut self, data: &PcmData) -> Result<()> {
+        self.check_open()?;
+        if data.format() != self.format {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "expected {:?} samples but got {:?}",
+                    self.format,
+                    data.format()
+                ),
+            ));
+        }
+        data.write_le(self.writer())?;
+        self.samples_written += data.len() as u64;
+        Ok(())
+    }
+
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    pub fn samples_written(&self) -> u64 {
+        self.samples_written
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.samples_written / self.spec.channels as u64
+    }
+
+    /// Completes the last frame with silence if needed, pads the data chunk, and fills in the
+    /// header sizes. Nothing more can be written afterwards.
+    pub fn finalize(&mut self) -> Result<()> {
+        if self.finalized {
+            return Ok(());
+        }
+        self.finalized = true;
+
+        let channels = self.spec.channels as u64;
+        while !self.samples_written.is_multiple_of(channels) {
+            let format = self.format;
+            write_sample_le(self.writer(), format, 0.0)?;
+            self.samples_written += 1;
+        }
+
+        let data_size = self.samples_written * self.format.bytes_per_sample() as u64;
+        if data_size % 2 == 1 {
+            self.writer().write_u8(0)?;
+        }
+
+        if let Some(patcher) = self.patcher {
+            let riff_size = self.header_length - 8 + data_size + data_size % 2;
+            let frames = self.samples_written / channels;
+            let data_size_offset = self.header_length - 4;
+            let fact_offset = self.header_length - 12;
+
+            let fields = if riff_size > self.riff_size_limit {
+                // Promote to RF64, replacing the reserved JUNK chunk with ds64
+                let ds64 = Ds64 {
+                    riff_size,
+                    data_size,
+                    sample_count: frames,
+                    table: Vec::new(),
+                };
+                let unknown = crate::riff::UNKNOWN_SIZE.to_le_bytes().to_vec();
+                let mut fields = vec![
+                    (0, b"RF64".to_vec()),
+                    (4, unknown.clone()),
+                    (12, b"ds64".to_vec()),
+                    (20, ds64.to_bytes()),
+                    (data_size_offset, unknown.clone()),
+                ];
+                if self.format.is_float() {
+                    fields.push((fact_offset, unknown));
+                }
+                fields
+            } else {
+                let mut fields = vec![
+                    (4, (riff_size as u32).to_le_bytes().to_vec()),
+                    (data_size_offset, (data_size as u32).to_le_bytes().to_vec()),
+                ];
+                if self.format.is_float() {
+                    fields.push((fact_offset, (frames as u32).to_le_bytes().to_vec()));
+                }
+                fields
+            };
+
+            let start = self.start;
+            patcher(self.writer(), start, &fields)?;
+        }
+
+        self.writer().flush()
+    }
+
+    /// Finalizes the file and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.finalize()?;
+        Ok(self.writer.take().expect("WAV writer has been moved out"))
+    }
+}
+
+impl<W> Drop for WavWriter<W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finalize();
+        }
+    }
+}
+
+/// Writes interleaved `&[i16]` samples with `channels` channels to a `Write`.
+///
+/// ```
+/// use std::io::Cursor;
+/// use synthrs::writer::write_wav_interleaved;
+///
+/// // Left, right, left, right...
+/// let samples = vec![0, 0, 1000, -1000, 2000, -2000];
+///
+/// let mut output_writer = Cursor::new(Vec::new());
+/// write_wav_interleaved(&mut output_writer, 44_100, 2, &samples).unwrap();
+/// ```
+pub fn write_wav_interleaved<W>(
+    writer: &mut W,
+    sample_rate: usize,
+    channels: usize,
+    samples: &[i16],
+) -> Result<()>
+where
+    W: Write,
+{
+    write_wav_with_spec(writer, &WavSpec::new(channels, sample_rate), samples)
+}
+
+/// Writes one `&[i16]` per channel to a `Write`. All channels must be the same length.
+///
+/// ```
+/// use std::io::Cursor;
+/// use synthrs::filter::pan;
+/// use synthrs::synthesizer::{make_samples, quantize_samples};
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::write_wav_planar;
+///
+/// let samples = make_samples(0.1, 44_100, sine_wave(440.0));
+/// let (left, right) = pan(&samples, -0.5);
+///
+/// let mut output_writer = Cursor::new(Vec::new());
+/// write_wav_planar(
+///     &mut output_writer,
+///     44_100,
+///     &[quantize_samples::<i16>(&left), quantize_samples::<i16>(&right)],
+/// ).unwrap();
+/// ```
+pub fn write_wav_planar<W, C>(writer: &mut W, sample_rate: usize, channels: &[C]) -> Result<()>
+where
+    W: Write,
+    C: AsRef<[i16]>,
+{
+    if channels.is_empty() {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "audio must have at least one channel".to_string(),
+        ));
+    }
+    let frames = channels[0].as_ref().len();
+    if channels
+        .iter()
+        .any(|channel| channel.as_ref().len() != frames)
+    {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "all channels must be the same length".to_string(),
+        ));
+    }
+
+    write_wav_buffer(writer, sample_rate, &AudioBuffer::from_planar(channels))
+}
+
+/// Writes an `AudioBuffer` to a `Write`.
+pub fn write_wav_buffer<W>(
+    writer: &mut W,
+    sample_rate: usize,
+    buffer: &AudioBuffer<i16>,
+) -> Result<()>
+where
+    W: Write,
+{
+    write_wav_interleaved(writer, sample_rate, buffer.channels(), buffer.interleaved())
+}
+
+/// Creates a file at `filename` and writes an `AudioBuffer` to it as a WAVE file.
+pub fn write_wav_buffer_file(
+    filename: &str,
+    sample_rate: usize,
+    buffer: &AudioBuffer<i16>,
+) -> Result<()> {
+    let path = Path::new(filename);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    write_wav_buffer(&mut f, sample_rate, buffer)
+}
+
+// Borrowing of packed &wave.pcm is unsafe
+// #[repr(C, packed)]
+/// Representation of a WAV file.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct Wave {
+    pub chunk_id: i32,
+    pub chunk_size: i32,
+    pub format: i32,
+    pub subchunk_1_id: i32,
+    pub subchunk_1_size: i32,
+    /// 1 = PCM, 3 = IEEE float, 6 = A-law, 7 = μ-law, -2 (0xFFFE) = extensible
+    pub audio_format: i16,
+    pub num_channels: i16,
+    pub sample_rate: i32,
+    pub byte_rate: i32,
+    pub block_align: i16,
+    pub bits_per_sample: i16,
+    /// Significant bits in each sample, from a `WAVE_FORMAT_EXTENSIBLE` fmt chunk
+    pub valid_bits_per_sample: Option<i16>,
+    /// Speaker positions of the channels, from a `WAVE_FORMAT_EXTENSIBLE` fmt chunk
+    pub channel_mask: Option<u32>,
+    pub subchunk_2_id: i32,
+    pub subchunk_2_size: i32,
+    /// Samples converted to signed 16-bit
+    pub pcm: Vec<i16>,
+    /// Samples in their stored format
+    pub data: PcmData,
+    /// Tags, markers and loops from `LIST`, `bext`, `cue ` and `smpl` chunks
+    pub metadata: WavMetadata,
+    /// Chunks other than `fmt `, `fact`, `data` and metadata, such as `JUNK`, in file order
+    pub chunks: Vec<RiffChunk>,
+    /// 64-bit sizes of RF64 and BW64 files, where `chunk_size` and `subchunk_2_size` are -1
+    pub ds64: Option<Ds64>,
+}
+
+impl Wave {
+    /// Interleaved samples as `f64`, in [-1.0, 1.0) for integer formats.
+    pub fn samples(&self) -> Vec<f64> {
+        self.data.to_f64()
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.data.format()
+    }
+}
+
+/// Checks a sample rate and channel count read from a file header, returning the sample rate,
+/// channel count, byte rate and block align as stored in a `Wave`. Values which do not fit in
+/// those fields are an error rather than overflowing.
+fn frame_fields(
+    sample_rate: i64,
+    num_channels: i64,
+    bytes_per_sample: usize,
+) -> Result<(i32, i16, i32, i16)> {
+    let invalid = |message: String| Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let sample_rate = i32::try_from(sample_rate)
+        .ok()
+        .filter(|&rate| rate >= 0)
+        .ok_or_else(|| invalid(format!("unsupported sample rate {}", sample_rate)))?;
+    let channels = i16::try_from(num_channels)
+        .ok()
+        .filter(|&channels| channels > 0)
+        .ok_or_else(|| invalid(format!("unsupported channel count {}", num_channels)))?;
+    let block_align = i16::try_from(i64::from(channels) * bytes_per_sample as i64)
+        .map_err(|_| invalid(format!("{} channels is too many", channels)))?;
+    let byte_rate = i32::try_from(i64::from(sample_rate) * i64::from(block_align))
+        .map_err(|_| invalid(format!("sample rate {} is too high", sample_rate)))?;
+
+    Ok((sample_rate, channels, byte_rate, block_align))
+}
+
+/// Reads a wave file given a file path. Convenience wrapper around `crate::writer::read_wav_file`.
+/// ```
+/// use synthrs::writer;
+///
+/// let wave = writer::read_wav_file("./tests/assets/sine.wav").unwrap();
+/// assert_eq!(wave.num_channels, 1);
+/// ```
+pub fn read_wav_file(filename: &str) -> Result<Wave> {
+    let path = Path::new(filename);
+    let file = OpenOptions::new().read(true).open(path)?;
+    let mut reader = BufReader::new(file);
+    read_wav(&mut reader)
+}
+
+/// Reads a wave file. Supports little-endian, unsigned 8-bit, signed 16, 24 and 32-bit integer,
+/// 32 and 64-bit float and G.711 A-law and μ-law WAV files, including `WAVE_FORMAT_EXTENSIBLE`,
+/// with any number of channels. G.711 samples are expanded to `PcmData::I16`.
+///
+/// ### Useful commands:
+///
+/// * Use `ffmpeg -i .\example.wav` to inspect a wav
+/// * Use `ffmpeg -i "dirty.wav" -f wav -flags +bitexact -acodec pcm_s16le -ar 44100 -ac 1 "clean.wav"` to clean a WAV
+///
+/// ```
+/// use std::path::Path;
+/// use std::fs::OpenOptions;
+/// use std::io::BufReader;
+/// use synthrs::writer;
+///
+/// let path = Path::new("./tests/assets/sine.wav");
+/// let file = OpenOptions::new().read(true).open(path).unwrap();
+/// let mut reader = BufReader::new(file);
+///
+/// let wave = writer::read_wav(&mut reader).unwrap();
+/// assert_eq!(wave.num_channels, 1);
+/// ```
+// For -acodec values, see https://trac.ffmpeg.org/wiki/audio%20types
+pub fn read_wav<R>(reader: &mut R) -> Result<Wave>
+where
+    R: Read,
+{
+    let mut chunks = RiffChunks::new(reader)?;
+    if &chunks.form_type != b"WAVE" {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "file is not a WAV".to_string(),
+        ));
+    }
+
+    let mut fmt: Option<(FmtChunk, i32)> = None;
+    let mut data: Option<(PcmData, i32)> = None;
+    let mut metadata = WavMetadata::default();
+    let mut other_chunks = Vec::new();
+
+    while let Some(header) = chunks.next_header()? {
+        match &header.id {
+            b"fmt " => {
+                let bytes = chunks.read_body(&header)?;
+                fmt = Some((FmtChunk::parse(&bytes)?, header.size as i32));
+            }
+            b"data" if data.is_none() => {
+                let encoding = match fmt {
+                    Some((ref fmt, _)) => fmt.encoding,
+                    None => {
+                        return Err(Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "data chunk found before fmt chunk".to_string(),
+                        ))
+                    }
+                };
+                let bytes = chunks.read_body(&header)?;
+                let samples = decode_raw(&bytes, encoding, Endianness::Little);
+                data = Some((samples, bytes.len() as i32));
+            }
+            // Only holds the number of frames, which is known from the data chunk
+            b"fact" => chunks.skip_body(&header)?,
+            _ => {
+                let chunk = RiffChunk::new(header.id, chunks.read_body(&header)?);
+                // A malformed metadata chunk is kept as-is rather than failing the whole file
+                if !metadata.read_chunk(&chunk).unwrap_or(false) {
+                    other_chunks.push(chunk);
+                }
+            }
+        }
+    }
+
+    let (fmt, subchunk_1_size) = fmt.ok_or_else(|| {
+        Error::new(
+            std::io::ErrorKind::InvalidData,
+            "missing fmt chunk".to_string(),
+        )
+    })?;
+    let (data, subchunk_2_size) = data.ok_or_else(|| {
+        Error::new(
+            std::io::ErrorKind::InvalidData,
+            "missing data chunk".to_string(),
+        )
+    })?;
+
+    let wave = Wave {
+        chunk_id: BigEndian::read_i32(&chunks.riff_id),
+        chunk_size: if chunks.ds64.is_some() {
+            -1
+        } else {
+            chunks.riff_size as i32
+        },
+        format: BigEndian::read_i32(&chunks.form_type),
+        subchunk_1_id: 0x666d_7420,
+        subchunk_1_size,
+        audio_format: fmt.audio_format,
+        num_channels: fmt.num_channels,
+        sample_rate: fmt.sample_rate,
+        byte_rate: fmt.byte_rate,
+        block_align: fmt.block_align,
+        bits_per_sample: fmt.bits_per_sample,
+        valid_bits_per_sample: fmt.valid_bits_per_sample,
+        channel_mask: fmt.channel_mask,
+        subchunk_2_id: 0x6461_7461,
+        subchunk_2_size: if chunks.ds64.is_some() {
+            -1
+        } else {
+            subchunk_2_size
+        },
+        pcm: data.to_i16(),
+        data,
+        metadata,
+        chunks: other_chunks,
+        ds64: chunks.ds64.clone(),
+    };
+
+    Ok(wave)
+}
+
+/// Reads a single little-endian sample of `format` as `f64`, scaled as in `PcmData::to_f64`.
+fn read_sample_le<R>(reader: &mut R, format: SampleFormat) -> Result<f64>
+where
+    R: Read,
+{
+    Ok(match format {
+        SampleFormat::U8 => (f64::from(reader.read_u8()?) - 128.0) / 128.0,
+        SampleFormat::I16 => f64::from(reader.read_i16::<LittleEndian>()?) / 32_768.0,
+        SampleFormat::I24 => f64::from(reader.read_i24::<LittleEndian>()?) / 8_388_608.0,
+        SampleFormat::I32 => f64::from(reader.read_i32::<LittleEndian>()?) / 2_147_483_648.0,
+        SampleFormat::F32 => f64::from(reader.read_f32::<LittleEndian>()?),
+        SampleFormat::F64 => reader.read_f64::<LittleEndian>()?,
+    })
+}
+
+/// Reads a WAV file lazily, decoding samples only as they are requested.
+///
+/// The header is parsed up to the start of the data chunk, so the format and duration are
+/// known without reading any samples. Chunks after the data chunk are not read.
+///
+/// ```
+/// use synthrs::writer::WavReader;
+///
+/// let mut wav = WavReader::open("./tests/assets/sine.wav").unwrap();
+/// assert_eq!(wav.spec().channels, 1);
+/// assert_eq!(wav.duration(), 1.0);
+///
+/// // Skip the first half second, then read the next 100 samples
+/// wav.seek(22_050).unwrap();
+/// let samples: Vec<f64> = wav.samples().take(100).map(|sample| sample.unwrap()).collect();
+/// ```
+#[derive(Debug)]
+pub struct WavReader<R> {
+    reader: R,
+    fmt: FmtChunk,
+    metadata: WavMetadata,
+    chunks: Vec<RiffChunk>,
+    data_start: u64,
+    /// Number of whole samples in the data chunk
+    data_samples: u64,
+    /// Index of the next sample to read
+    position: u64,
+}
+
+impl WavReader<BufReader<std::fs::File>> {
+    /// Opens the WAV file at `filename`.
+    pub fn open(filename: &str) -> Result<WavReader<BufReader<std::fs::File>>> {
+        let file = OpenOptions::new().read(true).open(Path::new(filename))?;
+        WavReader::new(BufReader::new(file))
+    }
+}
+
+impl<R> WavReader<R>
+where
+    R: Read + Seek,
+{
+    /// Reads the header of the WAV file starting at the current position of `reader`.
+    pub fn new(reader: R) -> Result<WavReader<R>> {
+        let mut riff = RiffChunks::new(reader)?;
+        if &riff.form_type != b"WAVE" {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "file is not a WAV".to_string(),
+            ));
+        }
+
+        let mut fmt = None;
+        let mut metadata = WavMetadata::default();
+        let mut chunks = Vec::new();
+
+        let data_header = loop {
+            let header = riff.next_header()?.ok_or_else(|| {
+                Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "missing data chunk".to_string(),
+                )
+            })?;
+            match &header.id {
+                b"fmt " => fmt = Some(FmtChunk::parse(&riff.read_body(&header)?)?),
+                b"data" => break header,
+                b"fact" => riff.skip_body(&header)?,
+                _ => {
+                    let chunk = RiffChunk::new(header.id, riff.read_body(&header)?);
+                    // A malformed metadata chunk is kept as-is rather than failing the whole file
+                    if !metadata.read_chunk(&chunk).unwrap_or(false) {
+                        chunks.push(chunk);
+                    }
+                }
+            }
+        };
+        let fmt = fmt.ok_or_else(|| {
+            Error::new(
+                std::io::ErrorKind::InvalidData,
+                "data chunk found before fmt chunk".to_string(),
+            )
+        })?;
+
+        let mut reader = riff.into_inner();
+        let data_start = reader.stream_position()?;
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(data_start))?;
+
+        // Unknown sizes run to the end of the stream, and truncated files stop there too
+        let available = stream_end - data_start;
+        let data_bytes = if data_header.is_unknown_size() {
+            available
+        } else {
+            data_header.size.min(available)
+        };
+
+        Ok(WavReader {
+            reader,
+            metadata,
+            chunks,
+            data_start,
+            data_samples: data_bytes / fmt.encoding.bytes_per_sample() as u64,
+            position: 0,
+            fmt,
+        })
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        WavSpec {
+            channels: self.fmt.num_channels.max(1) as usize,
+            sample_rate: self.fmt.sample_rate as usize,
+            channel_mask: self.fmt.channel_mask,
+        }
+    }
+
+    /// Format of the decoded samples. G.711 samples are decoded as `SampleFormat::I16`.
+    pub fn sample_format(&self) -> SampleFormat {
+        self.fmt.sample_format
+    }
+
+    /// Metadata in chunks before the data chunk.
+    pub fn metadata(&self) -> &WavMetadata {
+        &self.metadata
+    }
+
+    /// Chunks before the data chunk, other than `fmt `, `fact` and metadata.
+    pub fn chunks(&self) -> &[RiffChunk] {
+        &self.chunks
+    }
+
+    /// Total number of samples, across all channels.
+    pub fn len(&self) -> u64 {
+        self.data_samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data_samples == 0
+    }
+
+    /// Total number of frames, or samples per channel.
+    pub fn frames(&self) -> u64 {
+        self.data_samples / self.spec().channels as u64
+    }
+
+    /// Length in seconds.
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.spec().sample_rate as f64
+    }
+
+    /// Index of the next frame to be read. Partway through a frame if `samples` has stopped
+    /// between channels.
+    pub fn position(&self) -> u64 {
+        self.position / self.spec().channels as u64
+    }
+
+    /// Moves to the start of frame `frame`, or the end if past the end.
+    pub fn seek(&mut self, frame: u64) -> Result<()> {
+        let position = frame
+            .saturating_mul(self.spec().channels as u64)
+            .min(self.data_samples);
+        let offset = position * self.fmt.encoding.bytes_per_sample() as u64;
+        self.reader
+            .seek(SeekFrom::Start(self.data_start + offset))?;
+        self.position = position;
+        Ok(())
+    }
+
+    /// Reads the next sample, or `None` at the end of the data.
+    pub fn read_sample(&mut self) -> Result<Option<f64>> {
+        if self.position >= self.data_samples {
+            return Ok(None);
+        }
+        let sample = match self.fmt.encoding.law() {
+            Some(law) => f64::from(law.decode(self.reader.read_u8()?)) / 32_768.0,
+            None => read_sample_le(&mut self.reader, self.fmt.sample_format)?,
+        };
+        self.position += 1;
+        Ok(Some(sample))
+    }
+
+    /// Reads the next frame, one sample per channel, or `None` at the end of the data.
+    pub fn read_frame(&mut self) -> Result<Option<Vec<f64>>> {
+        let channels = self.spec().channels;
+        let mut frame = Vec::with_capacity(channels);
+        for _ in 0..channels {
+            match self.read_sample()? {
+                Some(sample) => frame.push(sample),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(frame))
+    }
+
+    /// Iterates over the remaining interleaved samples.
+    pub fn samples(&mut self) -> WavSamples<'_, R> {
+        WavSamples { reader: self }
+    }
+
+    /// Iterates over the remaining frames, each with one sample per channel.
+    pub fn iter_frames(&mut self) -> WavFrames<'_, R> {
+        WavFrames { reader: self }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Iterator over the samples of a `WavReader`, created by `WavReader::samples`.
+pub struct WavSamples<'a, R> {
+    reader: &'a mut WavReader<R>,
+}
+
+impl<'a, R> Iterator for WavSamples<'a, R>
+where
+    R: Read + Seek,
+{
+    type Item = Result<f64>;
+
+    fn next(&mut self) -> Option<Result<f64>> {
+        self.reader.read_sample().transpose()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.reader.data_samples - self.reader.position) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Iterator over the frames of a `WavReader`, created by `WavReader::iter_frames`.
+pub struct WavFrames<'a, R> {
+    reader: &'a mut WavReader<R>,
+}
+
+impl<'a, R> Iterator for WavFrames<'a, R>
+where
+    R: Read + Seek,
+{
+    type Item = Result<Vec<f64>>;
+
+    fn next(&mut self) -> Option<Result<Vec<f64>>> {
+        self.reader.read_frame().transpose()
+    }
+}
+
+/// Contents of a WAV fmt chunk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FmtChunk {
+    audio_format: i16,
+    num_channels: i16,
+    sample_rate: i32,
+    byte_rate: i32,
+    block_align: i16,
+    bits_per_sample: i16,
+    valid_bits_per_sample: Option<i16>,
+    channel_mask: Option<u32>,
+    /// Format of the decoded samples
+    sample_format: SampleFormat,
+    /// Encoding of the samples in the data chunk
+    encoding: RawEncoding,
+}
+
+impl FmtChunk {
+    fn parse(bytes: &[u8]) -> Result<FmtChunk> {
+        let mut reader = bytes;
+        let audio_format = reader.read_i16::<LittleEndian>()?; // AudioFormat, PCM = 1 (linear quantization)
+        let num_channels = reader.read_i16::<LittleEndian>()?; // NumChannels
+        let sample_rate = reader.read_i32::<LittleEndian>()?; // SampleRate
+        let byte_rate = reader.read_i32::<LittleEndian>()?; // ByteRate
+        let block_align = reader.read_i16::<LittleEndian>()?; // BlockAlign
+        let bits_per_sample = reader.read_i16::<LittleEndian>()?; // BitsPerSample
+
+        let mut valid_bits_per_sample = None;
+        let mut channel_mask = None;
+        let mut subformat = None;
+
+        // Size of the extension (0 or 22), which is absent from 16 byte PCM fmt chunks
+        let extension_size = reader.read_u16::<LittleEndian>().unwrap_or(0);
+        if extension_size >= 22 && reader.len() >= 22 {
+            valid_bits_per_sample = Some(reader.read_i16::<LittleEndian>()?);
+            channel_mask = Some(reader.read_u32::<LittleEndian>()?);
+            let mut guid = [0u8; 16];
+            reader.read_exact(&mut guid)?;
+            subformat = Some(guid);
+        } else if audio_format as u16 == 0xfffe {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "unexpected fmt chunk extension size: should be 22 but got: {}",
+                    extension_size
+                ),
+            ));
+        }
+
+        // SubFormat GUIDs differ from the PCM one only in their leading AudioFormat
+        let format_tag = match (audio_format as u16, subformat) {
+            (0xfffe, Some(guid)) if guid[2..] == SUBFORMAT_PCM[2..] => {
+                LittleEndian::read_u16(&guid[..2])
+            }
+            (format_tag, _) => format_tag,
+        };
+        let encoding = match (format_tag, bits_per_sample) {
+            (1, 8) => RawEncoding::Unsigned8,
+            (1, 16) => RawEncoding::Signed16,
+            (1, 24) => RawEncoding::Signed24,
+            (1, 32) => RawEncoding::Signed32,
+            (3, 32) => RawEncoding::Float32,
+            (3, 64) => RawEncoding::Float64,
+            (6, 8) => RawEncoding::ALaw,
+            (7, 8) => RawEncoding::MuLaw,
+            (1, _) | (3, _) | (6, _) | (7, _) => {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "unsupported bits per sample for audio_format {}: {}",
+                        format_tag, bits_per_sample
+                    ),
+                ))
+            }
+            _ => {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "only integer PCM, IEEE float and G.711 WAV supported, audio_format: {}",
+                        audio_format
+                    ),
+                ))
+            }
+        };
+        // G.711 samples are expanded to 16-bit
+        let sample_format = encoding.pcm_format().unwrap_or(SampleFormat::I16);
+
+        Ok(FmtChunk {
+            audio_format,
+            num_channels,
+            sample_rate,
+            byte_rate,
+            block_align,
+            bits_per_sample,
+            valid_bits_per_sample,
+            channel_mask,
+            sample_format,
+            encoding,
+        })
+    }
+}
+
+/// Encodes `value` as an 80-bit IEEE 754 extended precision float, as used for AIFF sample rates.
+fn f64_to_extended(value: f64) -> [u8; 10] {
+    let mut bytes = [0u8; 10];
+    if value == 0.0 || !value.is_finite() {
+        return bytes;
+    }
+
+    // Normalise so that the explicit integer bit of the mantissa is set
+    let mut mantissa = value.abs();
+    let mut exponent: i32 = 16_383 + 63;
+    while mantissa < 9_223_372_036_854_775_808.0 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    while mantissa >= 18_446_744_073_709_551_616.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    }
+
+    let sign = if value < 0.0 { 0x8000 } else { 0 };
+    BigEndian::write_u16(&mut bytes[0..2], sign | exponent as u16);
+    BigEndian::write_u64(&mut bytes[2..10], mantissa as u64);
+    bytes
+}
+
+/// Decodes an 80-bit IEEE 754 extended precision float.
+fn extended_to_f64(bytes: &[u8; 10]) -> f64 {
+    let sign_exponent = BigEndian::read_u16(&bytes[0..2]);
+    let mantissa = BigEndian::read_u64(&bytes[2..10]);
+    if mantissa == 0 {
+        return 0.0;
+    }
+
+    let exponent = i32::from(sign_exponent & 0x7fff) - 16_383 - 63;
+    let value = mantissa as f64 * 2f64.powi(exponent);
+    if sign_exponent & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Reverses the bytes of each sample, converting between little and big-endian.
+fn swap_sample_bytes(bytes: &mut [u8], bytes_per_sample: usize) {
+    for sample in bytes.chunks_exact_mut(bytes_per_sample) {
+        sample.reverse();
+    }
+}
+
+/// Reads a Pascal string: a length byte then text, padded to an even total length.
+fn read_pascal_string(reader: &mut &[u8]) -> Result<String> {
+    let length = reader.read_u8()? as usize;
+    if length > reader.len() {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            "string is truncated".to_string(),
+        ));
+    }
+    let text = String::from_utf8_lossy(&reader[..length]).into_owned();
+    // The length byte and text are padded to an even length
+    let padded = (length + 1 - length % 2).min(reader.len());
+    *reader = &reader[padded..];
+    Ok(text)
+}
+
+fn write_pascal_string(bytes: &mut Vec<u8>, text: &str) {
+    let text = &text.as_bytes()[..text.len().min(255)];
+    bytes.push(text.len() as u8);
+    bytes.extend_from_slice(text);
+    if text.len().is_multiple_of(2) {
+        bytes.push(0);
+    }
+}
+
+/// AIFF `INST` loop play modes
+const AIFF_NO_LOOPING: i16 = 0;
+const AIFF_FORWARD_LOOPING: i16 = 1;
+const AIFF_FORWARD_BACKWARD_LOOPING: i16 = 2;
+
+/// Reads an AIFF file given a file path. See `read_aiff`.
+///
+/// ```
+/// use synthrs::writer::{read_aiff_file, write_aiff_file, PcmData, SampleFormat, WavSpec};
+///
+/// write_aiff_file(
+///     "out/aiff.aiff",
+///     &WavSpec::new(1, 44_100),
+///     &PcmData::from_f64(&[0.0, 0.5, -0.5], SampleFormat::I16),
+/// ).unwrap();
+///
+/// let wave = read_aiff_file("out/aiff.aiff").unwrap();
+/// assert_eq!(wave.pcm, vec![0, 16_384, -16_384]);
+/// ```
+pub fn read_aiff_file(filename: &str) -> Result<Wave> {
+    let path = Path::new(filename);
+    let file = OpenOptions::new().read(true).open(path)?;
+    let mut reader = BufReader::new(file);
+    read_aiff(&mut reader)
+}
+
+/// Reads an AIFF or AIFF-C file into a `Wave`. Supports big-endian signed 8, 16, 24 and 32-bit
+/// integer samples, and AIFF-C `sowt` (little-endian) and `fl32`/`fl64` (float) compression
+/// types. Sample sizes that are not a whole number of bytes are read as the next size up, with
+/// `valid_bits_per_sample` set.
+///
+/// `MARK` markers are read into `metadata.cue_points`, and the base note and sustain and release
+/// loops of an `INST` chunk into `metadata.sampler`. Loops are identified by the ID of their
+/// start marker. Other chunks, such as `NAME` or `COMT`, are kept in `chunks`.
+///
+/// In the `Wave`, `chunk_id` is `FORM`, `format` is `AIFF` or `AIFC`, `subchunk_1_id` is `COMM`
+/// and `subchunk_2_id` is `SSND`. 8-bit samples are converted to the unsigned `PcmData::U8`.
+pub fn read_aiff<R>(reader: &mut R) -> Result<Wave>
+where
+    R: Read,
+{
+    let invalid = |message: &str| Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+
+    let riff = RiffChunks::new(reader)?;
+    let form_id = riff.riff_id;
+    let form_size = riff.riff_size;
+    let form_type = riff.form_type;
+    if &form_id != b"FORM" || (&form_type != b"AIFF" && &form_type != b"AIFC") {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "file is not an AIFF".to_string(),
+        ));
+    }
+    let chunks = riff.collect::<Result<Vec<RiffChunk>>>()?;
+
+    let comm = chunks
+        .iter()
+        .find(|chunk| &chunk.id == b"COMM")
+        .ok_or_else(|| invalid("missing COMM chunk"))?;
+    let subchunk_1_size = comm.data.len() as i32;
+    let mut comm_reader = &comm.data[..];
+    let num_channels = comm_reader.read_i16::<BigEndian>()?;
+    let _num_frames = comm_reader.read_u32::<BigEndian>()?;
+    let sample_size = comm_reader.read_i16::<BigEndian>()?;
+    let mut rate = [0u8; 10];
+    comm_reader.read_exact(&mut rate)?;
+    let sample_rate = extended_to_f64(&rate).round();
+    let compression = if &form_type == b"AIFC" {
+        let mut compression = [0u8; 4];
+        comm_reader.read_exact(&mut compression)?;
+        compression
+    } else {
+        *b"NONE"
+    };
+
+    let bytes_per_sample = (sample_size.max(1) as usize).div_ceil(8);
+    let (format, little_endian) = match (&compression, bytes_per_sample) {
+        (b"NONE", 1) | (b"twos", 1) | (b"sowt", 1) => (SampleFormat::U8, false),
+        (b"NONE", 2) | (b"twos", 2) => (SampleFormat::I16, false),
+        (b"NONE", 3) | (b"in24", 3) => (SampleFormat::I24, false),
+        (b"NONE", 4) | (b"in32", 4) => (SampleFormat::I32, false),
+        (b"sowt", 2) => (SampleFormat::I16, true),
+        (b"sowt", 3) => (SampleFormat::I24, true),
+        (b"sowt", 4) => (SampleFormat::I32, true),
+        (b"fl32", _) | (b"FL32", _) => (SampleFormat::F32, false),
+        (b"fl64", _) | (b"FL64", _) => (SampleFormat::F64, false),
+        _ => {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported AIFF compression {} with {}-bit samples",
+                    String::from_utf8_lossy(&compression),
+                    sample_size
+                ),
+            ))
+        }
+    };
+    // Out of range rates saturate, and are then rejected
+    let (sample_rate, num_channels, byte_rate, block_align) = frame_fields(
+        sample_rate as i64,
+        i64::from(num_channels),
+        format.bytes_per_sample(),
+    )?;
+    let valid_bits_per_sample = if !format.is_float() && !(sample_size as usize).is_multiple_of(8) {
+        Some(sample_size)
+    } else {
+        None
+    };
+
+    let ssnd = chunks
+        .iter()
+        .find(|chunk| &chunk.id == b"SSND")
+        .ok_or_else(|| invalid("missing SSND chunk"))?;
+    let mut ssnd_reader = &ssnd.data[..];
+    let offset = ssnd_reader.read_u32::<BigEndian>()? as usize;
+    let _block_size = ssnd_reader.read_u32::<BigEndian>()?;
+    let mut bytes = ssnd_reader[offset.min(ssnd_reader.len())..].to_vec();
+    let whole_samples = bytes.len() / format.bytes_per_sample();
+    bytes.truncate(whole_samples * format.bytes_per_sample());
+    let subchunk_2_size = bytes.len() as i32;
+    if !little_endian {
+        swap_sample_bytes(&mut bytes, format.bytes_per_sample());
+    }
+    if format == SampleFormat::U8 {
+        // AIFF 8-bit samples are signed
+        for byte in bytes.iter_mut() {
+            *byte ^= 0x80;
+        }
+    }
+    let data = PcmData::read_le(&bytes, format);
+
+    let mut metadata = WavMetadata::default();
+    if let Some(mark) = chunks.iter().find(|chunk| &chunk.id == b"MARK") {
+        let mut mark_reader = &mark.data[..];
+        let count = mark_reader.read_u16::<BigEndian>()?;
+        for _ in 0..count {
+            let id = u32::from(mark_reader.read_u16::<BigEndian>()?);
+            let position = mark_reader.read_u32::<BigEndian>()?;
+            let name = read_pascal_string(&mut mark_reader)?;
+            metadata.cue_points.push(CuePoint {
+                id,
+                position,
+                label: if name.is_empty() { None } else { Some(name) },
+            });
+        }
+    }
+    if let Some(inst) = chunks.iter().find(|chunk| &chunk.id == b"INST") {
+        if inst.data.len() < 20 {
+            return Err(invalid("INST chunk is too short"));
+        }
+        // Detune is in cents, from -50 to 50, and the pitch fraction in 2^32ths of a semitone
+        // above the unity note, so negative detunes are a fraction above the note below
+        let (note, detune) = match (inst.data[0], inst.data[1] as i8) {
+            (note, detune) if detune < 0 && note > 0 => (note - 1, 100.0 + f64::from(detune)),
+            (note, detune) => (note, f64::from(detune.max(0))),
+        };
+        let mut sampler = SamplerInfo::new(sample_rate.max(1) as usize, u32::from(note));
+        sampler.midi_pitch_fraction = (detune / 100.0 * 4_294_967_296.0).round() as u32;
+
+        for loop_bytes in inst.data[8..20].chunks_exact(6) {
+            let play_mode = BigEndian::read_i16(&loop_bytes[0..2]);
+            let begin_id = u32::from(BigEndian::read_u16(&loop_bytes[2..4]));
+            let end_id = u32::from(BigEndian::read_u16(&loop_bytes[4..6]));
+            let loop_type = match play_mode {
+                AIFF_NO_LOOPING => continue,
+                AIFF_FORWARD_LOOPING => LoopType::Forward,
+                AIFF_FORWARD_BACKWARD_LOOPING => LoopType::PingPong,
+                other => LoopType::Other(other as u32),
+            };
+            let position = |id: u32| {
+                metadata
+                    .cue_points
+                    .iter()
+                    .find(|cue| cue.id == id)
+                    .map(|cue| cue.position)
+            };
+            if let (Some(start), Some(end)) = (position(begin_id), position(end_id)) {
+                // AIFF loops end before the end marker, smpl loops on the last frame
+                sampler.loops.push(SampleLoop {
+                    loop_type,
+                    ..SampleLoop::forward(begin_id, start, end.saturating_sub(1))
+                });
+            }
+        }
+        metadata.sampler = Some(sampler);
+    }
+
+    let other_chunks = chunks
+        .into_iter()
+        .filter(|chunk| !matches!(&chunk.id, b"COMM" | b"SSND" | b"MARK" | b"INST" | b"FVER"))
+        .collect();
+
+    let wave = Wave {
+        chunk_id: BigEndian::read_i32(&form_id),
+        chunk_size: form_size as i32,
+        format: BigEndian::read_i32(&form_type),
+        subchunk_1_id: BigEndian::read_i32(b"COMM"),
+        subchunk_1_size,
+        audio_format: if format.is_float() { 3 } else { 1 },
+        num_channels,
+        sample_rate,
+        byte_rate,
+        block_align,
+        bits_per_sample: format.bits_per_sample() as i16,
+        valid_bits_per_sample,
+        channel_mask: None,
+        subchunk_2_id: BigEndian::read_i32(b"SSND"),
+        subchunk_2_size,
+        pcm: data.to_i16(),
+        data,
+        metadata,
+        chunks: other_chunks,
+        ds64: None,
+    };
+
+    Ok(wave)
+}
+
+/// Creates a file at `filename` and writes samples to it as an AIFF file. See `write_aiff`.
+pub fn write_aiff_file(filename: &str, spec: &WavSpec, data: &PcmData) -> Result<()> {
+    let path = Path::new(filename);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    write_aiff(&mut f, spec, data)
+}
+
+/// Writes interleaved samples to a `Write` as an AIFF file. Integer formats are written as
+/// big-endian AIFF, and float formats as AIFF-C with the `fl32` or `fl64` compression type.
+/// The channel mask in `spec` is not stored.
+///
+/// ```
+/// use synthrs::synthesizer::make_samples;
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::{read_aiff, write_aiff, PcmData, SampleFormat, WavSpec};
+///
+/// let samples = make_samples(0.1, 48_000, sine_wave(440.0));
+///
+/// let mut output = Vec::new();
+/// write_aiff(
+///     &mut output,
+///     &WavSpec::new(1, 48_000),
+///     &PcmData::from_f64(&samples, SampleFormat::I24),
+/// ).unwrap();
+///
+/// let wave = read_aiff(&mut &output[..]).unwrap();
+/// assert_eq!(wave.sample_rate, 48_000);
+/// assert_eq!(wave.sample_format(), SampleFormat::I24);
+/// ```
+pub fn write_aiff<W>(writer: &mut W, spec: &WavSpec, data: &PcmData) -> Result<()>
+where
+    W: Write,
+{
+    write_aiff_with_metadata(writer, spec, data, &WavMetadata::default())
+}
+
+/// Writes interleaved integer samples to a `Write` as little-endian AIFF-C, with the `sowt`
+/// compression type, as written by many Mac applications. Float formats are always big-endian
+/// in AIFF-C, and are rejected.
+///
+/// ```
+/// use synthrs::writer::{read_aiff, write_aiff_sowt, PcmData, WavSpec};
+///
+/// let mut output = Vec::new();
+/// write_aiff_sowt(&mut output, &WavSpec::new(1, 44_100), &PcmData::I16(vec![1, -2])).unwrap();
+/// assert_eq!(&output[output.len() - 4..], &[1, 0, 0xfe, 0xff]);
+///
+/// let wave = read_aiff(&mut &output[..]).unwrap();
+/// assert_eq!(wave.pcm, vec![1, -2]);
+/// ```
+pub fn write_aiff_sowt<W>(writer: &mut W, spec: &WavSpec, data: &PcmData) -> Result<()>
+where
+    W: Write,
+{
+    if data.format().is_float() {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "sowt AIFF-C only holds integer samples".to_string(),
+        ));
+    }
+    write_aiff_chunks(
+        writer,
+        spec,
+        data,
+        &WavMetadata::default(),
+        Endianness::Little,
+    )
+}
+
+/// Writes samples as in `write_aiff`, with `metadata.cue_points` as `MARK` markers and
+/// `metadata.sampler` as an `INST` chunk.
+///
+/// `INST` holds a sustain and a release loop, so only the first two loops are written. Loops
+/// are forward unless `LoopType::PingPong`. Markers are added for loop points that do not
+/// already have one. Other metadata has no AIFF equivalent and is not written.
+pub fn write_aiff_with_metadata<W>(
+    writer: &mut W,
+    spec: &WavSpec,
+    data: &PcmData,
+    metadata: &WavMetadata,
+) -> Result<()>
+where
+    W: Write,
+{
+    write_aiff_chunks(writer, spec, data, metadata, Endianness::Big)
+}
+
+/// Writes an AIFF file, or a `sowt` AIFF-C file for little-endian integer samples.
+fn write_aiff_chunks<W>(
+    writer: &mut W,
+    spec: &WavSpec,
+    data: &PcmData,
+    metadata: &WavMetadata,
+    endianness: Endianness,
+) -> Result<()>
+where
+    W: Write,
+{
+    let format = data.format();
+    // AIFF-C is needed for compression types other than big-endian integers
+    let is_aifc = format.is_float() || endianness == Endianness::Little;
+    let channels = spec.channels;
+    if channels == 0 || !data.len().is_multiple_of(channels) {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{} samples cannot be split into frames of {} channels",
+                data.len(),
+                channels
+            ),
+        ));
+    }
+
+    let mut chunks = Vec::new();
+
+    let mut comm = Vec::new();
+    comm.write_i16::<BigEndian>(channels as i16)?;
+    comm.write_u32::<BigEndian>((data.len() / channels) as u32)?;
+    comm.write_i16::<BigEndian>(format.bits_per_sample() as i16)?;
+    comm.extend_from_slice(&f64_to_extended(spec.sample_rate as f64));
+    if is_aifc {
+        // AIFF-C requires a version chunk
+        chunks.push(RiffChunk::new(
+            *b"FVER",
+            0xA280_5140u32.to_be_bytes().to_vec(),
+        ));
+        let (compression, name) = match format {
+            SampleFormat::F32 => (b"fl32", "32-bit floating point"),
+            SampleFormat::F64 => (b"fl64", "64-bit floating point"),
+            _ => (b"sowt", ""),
+        };
+        comm.extend_from_slice(compression);
+        write_pascal_string(&mut comm, name);
+    }
+    chunks.push(RiffChunk::new(*b"COMM", comm));
+
+    let mut markers: Vec<(u32, u32, String)> = metadata
+        .cue_points
+        .iter()
+        .map(|cue| {
+            let label = cue.label.clone().unwrap_or_default();
+            (cue.id, cue.position, label)
+        })
+        .collect();
+    let mut marker_for = |position: u32| {
+        if let Some(marker) = markers.iter().find(|marker| marker.1 == position) {
+            return marker.0;
+        }
+        let id = markers.iter().map(|marker| marker.0).max().unwrap_or(0) + 1;
+        markers.push((id, position, String::new()));
+        id
+    };
+
+    let inst = metadata.sampler.as_ref().map(|sampler| {
+        // Detune is from -50 to 50 cents, so fractions over half a semitone detune the next note
+        let cents = (f64::from(sampler.midi_pitch_fraction) / 4_294_967_296.0 * 100.0).round();
+        let (note, detune) = if cents > 50.0 {
+            (sampler.midi_unity_note.saturating_add(1), cents - 100.0)
+        } else {
+            (sampler.midi_unity_note, cents)
+        };
+        let mut inst = vec![
+            note.min(127) as u8,
+            detune as i8 as u8,
+            0,   // Lowest note
+            127, // Highest note
+            1,   // Lowest velocity
+            127, // Highest velocity
+            0,   // Gain
+            0,
+        ];
+        for index in 0..2 {
+            match sampler.loops.get(index) {
+                Some(sample_loop) => {
+                    let play_mode = match sample_loop.loop_type {
+                        LoopType::PingPong => AIFF_FORWARD_BACKWARD_LOOPING,
+                        _ => AIFF_FORWARD_LOOPING,
+                    };
+                    let begin = marker_for(sample_loop.start);
+                    let end = marker_for(sample_loop.end + 1);
+                    inst.extend_from_slice(&play_mode.to_be_bytes());
+                    inst.extend_from_slice(&(begin as u16).to_be_bytes());
+                    inst.extend_from_slice(&(end as u16).to_be_bytes());
+                }
+                None => inst.extend_from_slice(&[0; 6]),
+            }
+        }
+        inst
+    });
+
+    if !markers.is_empty() {
+        let mut mark = (markers.len() as u16).to_be_bytes().to_vec();
+        for (id, position, label) in &markers {
+            mark.extend_from_slice(&(*id as u16).to_be_bytes());
+            mark.extend_from_slice(&position.to_be_bytes());
+            write_pascal_string(&mut mark, label);
+        }
+        chunks.push(RiffChunk::new(*b"MARK", mark));
+    }
+    if let Some(inst) = inst {
+        chunks.push(RiffChunk::new(*b"INST", inst));
+    }
+
+    let mut samples = Vec::with_capacity(data.len() * format.bytes_per_sample());
+    data.write_le(&mut samples)?;
+    if endianness == Endianness::Big {
+        swap_sample_bytes(&mut samples, format.bytes_per_sample());
+    }
+    if format == SampleFormat::U8 {
+        for byte in samples.iter_mut() {
+            *byte ^= 0x80;
+        }
+    }
+    let mut ssnd = vec![0u8; 8]; // Offset and block size
+    ssnd.extend(samples);
+    chunks.push(RiffChunk::new(*b"SSND", ssnd));
+
+    // IFF chunks are laid out as in RIFF, but with big-endian sizes
+    let mut body = Vec::new();
+    for chunk in &chunks {
+        let start = body.len();
+        body.extend(chunk.to_bytes());
+        BigEndian::write_u32(&mut body[start + 4..start + 8], chunk.data.len() as u32);
+    }
+
+    writer.write_all(b"FORM")?;
+    writer.write_u32::<BigEndian>(body.len() as u32 + 4)?;
+    writer.write_all(if is_aifc { b"AIFC" } else { b"AIFF" })?;
+    writer.write_all(&body)?;
+
+    Ok(())
+}
+
+/// `.au` magic number, `.snd`
+const AU_MAGIC: [u8; 4] = *b".snd";
+/// `.au` header length, including a four byte annotation
+const AU_HEADER_LENGTH: u32 = 28;
+
+/// The `.au` encoding code for `encoding`, if `.au` supports it.
+fn au_encoding_code(encoding: RawEncoding) -> Option<u32> {
+    match encoding {
+        RawEncoding::MuLaw => Some(1),
+        RawEncoding::Signed8 => Some(2),
+        RawEncoding::Signed16 => Some(3),
+        RawEncoding::Signed24 => Some(4),
+        RawEncoding::Signed32 => Some(5),
+        RawEncoding::Float32 => Some(6),
+        RawEncoding::Float64 => Some(7),
+        RawEncoding::ALaw => Some(27),
+        _ => None,
+    }
+}
+
+/// Reads a Sun/NeXT `.au` or `.snd` file given a file path. See `read_au`.
+pub fn read_au_file(filename: &str) -> Result<Wave> {
+    let path = Path::new(filename);
+    let file = OpenOptions::new().read(true).open(path)?;
+    let mut reader = BufReader::new(file);
+    read_au(&mut reader)
+}
+
+/// Reads a Sun/NeXT `.au` or `.snd` file into a `Wave`. Supports μ-law, A-law, signed 8, 16, 24
+/// and 32-bit integer, and 32 and 64-bit float encodings. Data of unknown size (`0xFFFFFFFF`)
+/// is read until the end of the stream.
+///
+/// μ-law and A-law samples are expanded to `PcmData::I16`, with `audio_format` set to the WAV
+/// codes 7 and 6 and `bits_per_sample` to 8. Signed 8-bit samples are converted to the unsigned
+/// `PcmData::U8`. A text annotation in the header is read into `metadata.info.comment`.
+///
+/// In the `Wave`, `chunk_id` is `.snd`, `subchunk_1_size` is the header length and
+/// `subchunk_2_size` is the data size. Other RIFF fields are 0.
+pub fn read_au<R>(reader: &mut R) -> Result<Wave>
+where
+    R: Read,
+{
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != AU_MAGIC {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "file is not an AU".to_string(),
+        ));
+    }
+
+    let data_offset = reader.read_u32::<BigEndian>()?;
+    let data_size = reader.read_u32::<BigEndian>()?;
+    let code = reader.read_u32::<BigEndian>()?;
+    let sample_rate = reader.read_u32::<BigEndian>()?;
+    let num_channels = reader.read_u32::<BigEndian>()?;
+
+    let encoding = [
+        RawEncoding::MuLaw,
+        RawEncoding::ALaw,
+        RawEncoding::Signed8,
+        RawEncoding::Signed16,
+        RawEncoding::Signed24,
+        RawEncoding::Signed32,
+        RawEncoding::Float32,
+        RawEncoding::Float64,
+    ]
+    .iter()
+    .cloned()
+    .find(|&encoding| au_encoding_code(encoding) == Some(code))
+    .ok_or_else(|| {
+        Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unsupported AU encoding {}", code),
+        )
+    })?;
+
+    if data_offset < 24 {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("AU data offset {} is inside the header", data_offset),
+        ));
+    }
+    // Not preallocated, as the offset may be corrupt
+    let annotation_length = u64::from(data_offset - 24);
+    let mut annotation = Vec::new();
+    reader
+        .take(annotation_length)
+        .read_to_end(&mut annotation)?;
+    if (annotation.len() as u64) < annotation_length {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            "AU header is truncated".to_string(),
+        ));
+    }
+    let annotation_end = annotation
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(annotation.len());
+    let mut metadata = WavMetadata::default();
+    if annotation_end > 0 {
+        metadata.info.comment =
+            Some(String::from_utf8_lossy(&annotation[..annotation_end]).into_owned());
+    }
+
+    let mut bytes = Vec::new();
+    if data_size == crate::riff::UNKNOWN_SIZE {
+        reader.read_to_end(&mut bytes)?;
+    } else {
+        // Stop early at the end of truncated files
+        reader.take(u64::from(data_size)).read_to_end(&mut bytes)?;
+    }
+    let data = decode_raw(&bytes, encoding, Endianness::Big);
+
+    let (audio_format, bits_per_sample) = match encoding {
+        RawEncoding::MuLaw => (7, 8),
+        RawEncoding::ALaw => (6, 8),
+        _ if data.format().is_float() => (3, data.format().bits_per_sample() as i16),
+        _ => (1, data.format().bits_per_sample() as i16),
+    };
+    let (sample_rate, num_channels, byte_rate, block_align) = frame_fields(
+        i64::from(sample_rate),
+        i64::from(num_channels),
+        encoding.bytes_per_sample(),
+    )?;
+
+    let wave = Wave {
+        chunk_id: BigEndian::read_i32(&magic),
+        chunk_size: 0,
+        format: 0,
+        subchunk_1_id: 0,
+        subchunk_1_size: data_offset as i32,
+        audio_format,
+        num_channels,
+        sample_rate,
+        byte_rate,
+        block_align,
+        bits_per_sample,
+        valid_bits_per_sample: None,
+        channel_mask: None,
+        subchunk_2_id: 0,
+        subchunk_2_size: (bytes.len() / encoding.bytes_per_sample() * encoding.bytes_per_sample())
+            as i32,
+        pcm: data.to_i16(),
+        data,
+        metadata,
+        chunks: Vec::new(),
+        ds64: None,
+    };
+
+    Ok(wave)
+}
+
+/// Creates a file at `filename` and writes samples to it as a `.au` file. See `write_au`.
+pub fn write_au_file(
+    filename: &str,
+    spec: &WavSpec,
+    encoding: RawEncoding,
+    samples: &[f64],
+) -> Result<()> {
+    let path = Path::new(filename);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    write_au(&mut f, spec, encoding, samples)
+}
+
+/// Writes interleaved samples in [-1.0, 1.0] to a `Write` as a Sun/NeXT `.au` file. `.au` data
+/// is big-endian and signed, so unsigned encodings are rejected. The channel mask in `spec` is
+/// not stored.
+///
+/// ```
+/// use synthrs::synthesizer::make_samples;
+/// use synthrs::wave::sine_wave;
+/// use synthrs::writer::{read_au, write_au, RawEncoding, WavSpec};
+///
+/// // Telephone quality
+/// let samples = make_samples(0.1, 8000, sine_wave(440.0));
+///
+/// let mut output = Vec::new();
+/// write_au(&mut output, &WavSpec::new(1, 8000), RawEncoding::MuLaw, &samples).unwrap();
+/// assert_eq!(output.len(), 28 + 800);
+///
+/// let wave = read_au(&mut &output[..]).unwrap();
+/// assert_eq!(wave.sample_rate, 8000);
+/// assert_eq!(wave.audio_format, 7);
+/// ```
+pub fn write_au<W>(
+    writer: &mut W,
+    spec: &WavSpec,
+    encoding: RawEncoding,
+    samples: &[f64],
+) -> Result<()>
+where
+    W: Write,
+{
+    let code = au_encoding_code(encoding).ok_or_else(|| {
+        Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{:?} cannot be stored in an AU file", encoding),
+        )
+    })?;
+    check_frames(samples.len(), spec.channels)?;
+    let data = encode_raw(samples, encoding, Endianness::Big)?;
+
+    writer.write_all(&AU_MAGIC)?;
+    writer.write_u32::<BigEndian>(AU_HEADER_LENGTH)?;
+    writer.write_u32::<BigEndian>(data.len() as u32)?;
+    writer.write_u32::<BigEndian>(code)?;
+    writer.write_u32::<BigEndian>(spec.sample_rate as u32)?;
+    writer.write_u32::<BigEndian>(spec.channels as u32)?;
+    writer.write_all(&[0; 4])?; // Empty annotation
+    writer.write_all(&data)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_wav() {
+        // 1 second at 44,100Hz, PCM 16 (2-byte)
+        let wave = read_wav_file("./tests/assets/sine.wav").unwrap();
+
+        assert_eq!(wave.format, 0x5741_5645); // WAVE
+        assert_eq!(wave.audio_format, 1);
+        assert_eq!(wave.num_channels, 1);
+        assert_eq!(wave.sample_rate, 44_100);
+        assert_eq!(wave.byte_rate, 88_200);
+        assert_eq!(wave.block_align, 2);
+        assert_eq!(wave.bits_per_sample, 16);
+        assert_eq!(wave.subchunk_2_size, 88_200);
+        assert_eq!(wave.pcm.len(), 44_100);
+    }
+
+    #[test]
+    fn test_write_read_wav() {
+        use crate::synthesizer::{make_samples, quantize_samples};
+        use crate::wave::sine_wave;
+        use crate::writer::write_wav;
+        use std::io::{Cursor, Seek, SeekFrom};
+
+        let output_buffer: Vec<u8> = Vec::new();
+        let mut output_writer = Cursor::new(output_buffer);
+
+        write_wav(
+            &mut output_writer,
+            44_100,
+            &quantize_samples::<i16>(&make_samples(0.1, 44_100, sine_wave(440.0))),
+        )
+        .unwrap();
+
+        let _ = output_writer.seek(SeekFrom::Start(0));
+        let wave = read_wav(&mut output_writer).unwrap();
+        assert_eq!(wave.format, 0x5741_5645); // WAVE
+        assert_eq!(wave.audio_format, 1);
+        assert_eq!(wave.num_channels, 1);
+        assert_eq!(wave.sample_rate, 44_100);
+        assert_eq!(wave.byte_rate, 88_200);
+        assert_eq!(wave.block_align, 2);
+        assert_eq!(wave.bits_per_sample, 16);
+        assert_eq!(wave.subchunk_2_size, 8820);
+        assert_eq!(wave.pcm.len(), 4410);
+    }
+
+    #[test]
+    fn test_write_stereo_wav() {
+        let buffer = AudioBuffer::from_planar(&[vec![1i16, 2], vec![-1, -2]]);
+        let mut output = Vec::new();
+        write_wav_buffer(&mut output, 48_000, &buffer).unwrap();
+
+        assert_eq!(output.len(), 44 + 8);
+        let mut reader = &output[..];
+        let wave = read_wav(&mut reader).unwrap();
+        assert_eq!(wave.audio_format, 1);
+        assert_eq!(wave.pcm, vec![1, -1, 2, -2]);
+        assert_eq!(wave.num_channels, 2);
+        assert_eq!(wave.byte_rate, 192_000);
+        assert_eq!(wave.block_align, 4);
+        assert_eq!(wave.chunk_size as usize, output.len() - 8);
+        assert_eq!(&output[44..], &[1, 0, 255, 255, 2, 0, 254, 255]);
+    }
+
+    #[test]
+    fn test_write_extensible_wav() {
+        let samples: Vec<i16> = (0..12).collect();
+        let mut output = Vec::new();
+        write_wav_interleaved(&mut output, 44_100, 6, &samples).unwrap();
+
+        let mut reader = &output[..];
+        assert_eq!(reader.read_u32::<BigEndian>().unwrap(), 0x5249_4646); // RIFF
+        assert_eq!(
+            reader.read_u32::<LittleEndian>().unwrap() as usize,
+            output.len() - 8
+        );
+        reader = &output[16..];
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 40);
+        assert_eq!(reader.read_u16::<LittleEndian>().unwrap(), 0xfffe);
+        assert_eq!(reader.read_u16::<LittleEndian>().unwrap(), 6);
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 44_100);
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 44_100 * 12);
+        assert_eq!(reader.read_u16::<LittleEndian>().unwrap(), 12);
+        assert_eq!(reader.read_u16::<LittleEndian>().unwrap(), 16);
+        assert_eq!(reader.read_u16::<LittleEndian>().unwrap(), 22);
+        assert_eq!(reader.read_u16::<LittleEndian>().unwrap(), 16);
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 0x3f); // 5.1
+        assert_eq!(&reader[..16], &SUBFORMAT_PCM);
+        assert_eq!(&output[60..64], b"data");
+        assert_eq!(output.len(), 68 + 24);
+
+        assert!(write_wav_interleaved(&mut Vec::new(), 44_100, 2, &[0, 1, 2]).is_err());
+        assert!(write_wav_planar(&mut Vec::new(), 44_100, &[vec![0i16], vec![]]).is_err());
+    }
+
+    #[test]
+    fn test_wav_formats_roundtrip() {
+        let samples = vec![-1.0, -0.5, -0.001, 0.0, 0.25, 0.999, 1.0];
+        let formats = [
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::I24,
+            SampleFormat::I32,
+            SampleFormat::F32,
+            SampleFormat::F64,
+        ];
+
+        for &format in formats.iter() {
+            for &channels in &[1, 7] {
+                let data = PcmData::from_f64(&samples, format);
+                let spec = WavSpec::new(channels, 22_050);
+                let mut output = Vec::new();
+                write_wav_data(&mut output, &spec, &data).unwrap();
+
+                let mut reader = &output[..];
+                let wave = read_wav(&mut reader).unwrap();
+                let bytes = samples.len() * format.bytes_per_sample();
+                assert_eq!(output.len() % 2, 0);
+                assert_eq!(wave.chunk_size as usize, output.len() - 8);
+                assert_eq!(wave.subchunk_2_size as usize, bytes);
+                assert_eq!(wave.bits_per_sample as usize, format.bits_per_sample());
+                assert_eq!(wave.num_channels as usize, channels);
+                assert_eq!(wave.channel_mask.is_some(), channels > 2);
+                assert_eq!(wave.data, data, "{:?}", format);
+
+                let tolerance = match format {
+                    SampleFormat::F32 => 1e-7,
+                    SampleFormat::F64 => 0.0,
+                    _ => 1.0 / 2.0f64.powi(format.bits_per_sample() as i32 - 2),
+                };
+                for (a, b) in samples.iter().zip(wave.samples()) {
+                    assert!((a - b).abs() <= tolerance, "{:?}: {} {}", format, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_wav_header() {
+        let mut output = Vec::new();
+        let data = PcmData::F32(vec![0.5, -0.5, 0.25, -0.25]);
+        write_wav_data(&mut output, &WavSpec::new(2, 44_100), &data).unwrap();
+
+        assert_eq!(&output[12..16], b"fmt ");
+        assert_eq!(LittleEndian::read_u32(&output[16..20]), 18);
+        assert_eq!(LittleEndian::read_u16(&output[20..22]), 3);
+        assert_eq!(LittleEndian::read_u16(&output[36..38]), 0);
+        assert_eq!(&output[38..42], b"fact");
+        assert_eq!(LittleEndian::read_u32(&output[46..50]), 2);
+        assert_eq!(&output[50..54], b"data");
+        assert_eq!(output.len(), 58 + 16);
+
+        let wave = read_wav(&mut &output[..]).unwrap();
+        assert_eq!(wave.audio_format, 3);
+        assert_eq!(wave.pcm, vec![16_384, -16_384, 8192, -8192]);
+    }
+
+    #[test]
+    fn test_read_wav_with_other_chunks() {
+        let mut output = Vec::new();
+        write_wav_data(
+            &mut output,
+            &WavSpec::new(1, 8000),
+            &PcmData::U8(vec![1, 2, 3]),
+        )
+        .unwrap();
+
+        // Insert an odd-sized chunk between fmt and data, and another after data
+        let mut file = output[..36].to_vec();
+        file.extend_from_slice(b"JUNK\x03\0\0\0abc\0");
+        // A cue chunk claiming one cue point but holding none
+        file.extend_from_slice(b"cue \x04\0\0\0\x01\0\0\0");
+        file.extend_from_slice(&output[36..]);
+        file.extend_from_slice(b"LIST\x04\0\0\0exif");
+        let riff_size = (file.len() - 8) as u32;
+        file[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let wave = read_wav(&mut &file[..]).unwrap();
+        assert_eq!(wave.data, PcmData::U8(vec![1, 2, 3]));
+        assert_eq!(wave.subchunk_2_size, 3);
+        assert_eq!(
+            wave.chunks,
+            vec![
+                RiffChunk::new(*b"JUNK", b"abc".to_vec()),
+                RiffChunk::new(*b"cue ", vec![1, 0, 0, 0]),
+                RiffChunk::new(*b"LIST", b"exif".to_vec()),
+            ]
+        );
+        assert!(wave.metadata.is_empty());
+
+        let wav = WavReader::new(std::io::Cursor::new(&file[..])).unwrap();
+        assert_eq!(wav.chunks(), &wave.chunks[..2]);
+        assert_eq!(wav.len(), 3);
+
+        // Truncated data
+        let truncated = &output[..output.len() - 2];
+        assert!(read_wav(&mut &truncated[..]).is_err());
+        // No fmt chunk
+        assert!(read_wav(&mut &file[..12]).is_err());
+    }
+
+    #[test]
+    fn test_wav_metadata() {
+        use crate::metadata::{CuePoint, SampleLoop, SamplerInfo};
+        use std::io::Cursor;
+
+        let mut metadata = WavMetadata::default();
+        metadata.info.title = Some("Odd".to_string());
+        metadata.cue_points.push(CuePoint::new(1, 2, "Loop"));
+        let mut sampler = SamplerInfo::new(8000, 60);
+        sampler.loops.push(SampleLoop::forward(1, 2, 3));
+        metadata.sampler = Some(sampler);
+
+        let samples = [0.5, -0.5, 0.25, -0.25, 0.0];
+        let mut output = Cursor::new(Vec::new());
+        {
+            let mut wav = WavWriter::new_with_metadata(
+                &mut output,
+                WavSpec::new(1, 8000),
+                SampleFormat::F32,
+                &metadata,
+            )
+            .unwrap();
+            wav.write_samples(&samples).unwrap();
+        }
+
+        let mut expected = Vec::new();
+        write_wav_data_with_metadata(
+            &mut expected,
+            &WavSpec::new(1, 8000),
+            &PcmData::from_f64(&samples, SampleFormat::F32),
+            &metadata,
+        )
+        .unwrap();
+        assert_eq!(&output.get_ref()[48..], &expected[12..]);
+
+        let wave = read_wav(&mut &expected[..]).unwrap();
+        assert_eq!(wave.metadata, metadata);
+        assert!(wave.chunks.is_empty());
+        assert_eq!(wave.data, PcmData::F32(vec![0.5, -0.5, 0.25, -0.25, 0.0]));
+
+        output.set_position(0);
+        let wav = WavReader::new(output).unwrap();
+        assert_eq!(wav.metadata(), &metadata);
+        assert_eq!(wav.len(), 5);
+    }
+
+    #[test]
+    fn test_aiff() {
+        assert_eq!(
+            f64_to_extended(44_100.0),
+            [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]
+        );
+        for &rate in &[8000.0, 22_050.0, 96_000.0, 0.5] {
+            assert_eq!(extended_to_f64(&f64_to_extended(rate)), rate);
+        }
+
+        let samples = [0.5, -0.5, 0.25, -0.25, 0.0, 0.75];
+        for &format in &[
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::I24,
+            SampleFormat::I32,
+            SampleFormat::F32,
+            SampleFormat::F64,
+        ] {
+            let data = PcmData::from_f64(&samples, format);
+            let mut output = Vec::new();
+            write_aiff(&mut output, &WavSpec::new(2, 22_050), &data).unwrap();
+            assert_eq!(
+                BigEndian::read_u32(&output[4..8]) as usize,
+                output.len() - 8
+            );
+
+            let wave = read_aiff(&mut &output[..]).unwrap();
+            assert_eq!(wave.data, data, "{:?}", format);
+            assert_eq!(wave.num_channels, 2);
+            assert_eq!(wave.sample_rate, 22_050);
+            assert_eq!(
+                &wave.format.to_be_bytes(),
+                if format.is_float() { b"AIFC" } else { b"AIFF" }
+            );
+            assert!(wave.chunks.is_empty());
+        }
+
+        // Channel counts and sample rates which do not fit in a `Wave`
+        let mut output = Vec::new();
+        write_aiff(&mut output, &WavSpec::new(1, 8000), &PcmData::I32(vec![1])).unwrap();
+        for &(channels, rate) in &[(20_000i16, 8000.0), (0, 8000.0), (1, 3e9), (1, -8000.0)] {
+            let mut corrupt = output.clone();
+            corrupt[20..22].copy_from_slice(&channels.to_be_bytes());
+            corrupt[28..38].copy_from_slice(&f64_to_extended(rate));
+            let error = read_aiff(&mut &corrupt[..]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        // Big-endian and little-endian (sowt) 16-bit samples
+        let mut output = Vec::new();
+        write_aiff(
+            &mut output,
+            &WavSpec::new(1, 8000),
+            &PcmData::I16(vec![1, -2]),
+        )
+        .unwrap();
+        assert_eq!(&output[output.len() - 4..], &[0, 1, 0xff, 0xfe]);
+        let mut sowt = b"FORM\0\0\0\0AIFCCOMM\0\0\0\x18".to_vec();
+        sowt.extend_from_slice(&[0, 1, 0, 0, 0, 2, 0, 16]);
+        sowt.extend_from_slice(&f64_to_extended(8000.0));
+        sowt.extend_from_slice(b"sowt\0\0SSND\0\0\0\x0c\0\0\0\0\0\0\0\0");
+        sowt.extend_from_slice(&[1, 0, 0xfe, 0xff]);
+        let wave = read_aiff(&mut &sowt[..]).unwrap();
+        assert_eq!(wave.data, PcmData::I16(vec![1, -2]));
+
+        let data = PcmData::I24(vec![1, -2, 0x12_3456]);
+        let mut output = Vec::new();
+        write_aiff_sowt(&mut output, &WavSpec::new(1, 8000), &data).unwrap();
+        assert_eq!(&output[8..12], b"AIFC");
+        // Nine bytes of samples, then a pad byte
+        assert_eq!(&output[output.len() - 4..], &[0x56, 0x34, 0x12, 0]);
+        assert_eq!(read_aiff(&mut &output[..]).unwrap().data, data);
+        let floats = PcmData::F32(vec![0.5]);
+        assert!(write_aiff_sowt(&mut output, &WavSpec::new(1, 8000), &floats).is_err());
+
+        // A corrupt chunk size is an error rather than a huge allocation
+        let mut corrupt = sowt.clone();
+        corrupt[16..20].copy_from_slice(&[0xff, 0xff, 0xff, 0xfe]);
+        assert!(read_aiff(&mut &corrupt[..]).is_err());
+
+        // Markers and loops
+        let mut metadata = WavMetadata::default();
+        metadata.cue_points.push(CuePoint::new(1, 1, "Start"));
+        metadata.cue_points.push(CuePoint::new(2, 3, "End"));
+        let mut sampler = SamplerInfo::new(8000, 60);
+        sampler.loops.push(SampleLoop::forward(1, 1, 2));
+        sampler.loops.push(SampleLoop {
+            loop_type: LoopType::PingPong,
+            ..SampleLoop::forward(3, 0, 3)
+        });
+        metadata.sampler = Some(sampler);
+
+        let mut output = Vec::new();
+        let data = PcmData::I16(vec![0, 1, 2, 3]);
+        write_aiff_with_metadata(&mut output, &WavSpec::new(1, 8000), &data, &metadata).unwrap();
+        let wave = read_aiff(&mut &output[..]).unwrap();
+        assert_eq!(wave.data, data);
+
+        // Markers were added for the release loop
+        let positions: Vec<(u32, u32)> = wave
+            .metadata
+            .cue_points
+            .iter()
+            .map(|cue| (cue.id, cue.position))
+            .collect();
+        assert_eq!(positions, vec![(1, 1), (2, 3), (3, 0), (4, 4)]);
+        assert_eq!(wave.metadata.cue_points[0].label, Some("Start".to_string()));
+        let sampler = wave.metadata.sampler.unwrap();
+        assert_eq!(sampler.midi_unity_note, 60);
+        assert_eq!(sampler.loops[0], SampleLoop::forward(1, 1, 2));
+        assert_eq!(sampler.loops[1].loop_type, LoopType::PingPong);
+        assert_eq!((sampler.loops[1].start, sampler.loops[1].end), (0, 3));
+
+        // Negative detunes are a pitch fraction above the note below
+        let inst = output.windows(4).position(|id| id == b"INST").unwrap() + 8;
+        for &(note, detune, unity_note, cents) in &[(60, 25, 60, 25.0), (60, -30, 59, 70.0)] {
+            let mut detuned = output.clone();
+            detuned[inst] = note;
+            detuned[inst + 1] = detune as u8;
+            let wave = read_aiff(&mut &detuned[..]).unwrap();
+            let sampler = wave.metadata.sampler.as_ref().unwrap();
+            assert_eq!(sampler.midi_unity_note, unity_note);
+            let fraction = f64::from(sampler.midi_pitch_fraction) / 4_294_967_296.0;
+            assert!((fraction * 100.0 - cents).abs() < 1e-6);
+
+            let mut rewritten = Vec::new();
+            write_aiff_with_metadata(
+                &mut rewritten,
+                &WavSpec::new(1, 8000),
+                &data,
+                &wave.metadata,
+            )
+            .unwrap();
+            assert_eq!(rewritten, detuned);
+        }
+
+        assert!(read_aiff(&mut &output[..20]).is_err());
+        assert!(read_aiff(&mut &b"RIFF\0\0\0\0WAVE"[..]).is_err());
+    }
+
+    #[test]
+    fn test_raw_pcm_and_au() {
+        let samples = [0.5, -0.5, 0.25, -0.25, 0.0, -1.0];
+        let encodings = [
+            RawEncoding::Signed8,
+            RawEncoding::Unsigned8,
+            RawEncoding::Signed16,
+            RawEncoding::Unsigned16,
+            RawEncoding::Signed24,
+            RawEncoding::Unsigned24,
+            RawEncoding::Signed32,
+            RawEncoding::Unsigned32,
+            RawEncoding::Float32,
+            RawEncoding::Float64,
+            RawEncoding::MuLaw,
+            RawEncoding::ALaw,
+        ];
+        for &encoding in &encodings {
+            // Companded encodings lose precision away from zero
+            let tolerance = match encoding {
+                RawEncoding::MuLaw | RawEncoding::ALaw => 0.02,
+                _ => 0.01,
+            };
+            for &endianness in &[Endianness::Little, Endianness::Big] {
+                let spec = RawPcmSpec::new(encoding, endianness, 2);
+                let mut output = Vec::new();
+                write_raw_pcm(&mut output, &spec, &samples).unwrap();
+                assert_eq!(output.len(), 6 * encoding.bytes_per_sample());
+
+                // A partial frame at the end is dropped
+                output.push(0);
+                let read = read_raw_pcm(&mut &output[..], &spec).unwrap();
+                assert_eq!(read.len(), 6);
+                for (a, b) in read.iter().zip(samples.iter()) {
+                    assert!((a - b).abs() < tolerance, "{:?} {} {}", encoding, a, b);
+                }
+            }
+
+            let spec = WavSpec::new(2, 8000);
+            let mut output = Vec::new();
+            match write_au(&mut output, &spec, encoding, &samples) {
+                Ok(()) => {
+                    assert_eq!(&output[..4], b".snd");
+                    let wave = read_au(&mut &output[..]).unwrap();
+                    assert_eq!(wave.num_channels, 2);
+                    assert_eq!(wave.sample_rate, 8000);
+                    let read = wave.samples();
+                    assert_eq!(read.len(), 6);
+                    for (a, b) in read.iter().zip(samples.iter()) {
+                        assert!((a - b).abs() < tolerance, "{:?} {} {}", encoding, a, b);
+                    }
+                }
+                Err(_) => assert!(au_encoding_code(encoding).is_none()),
+            }
+        }
+
+        // Known byte layouts
+        let mut output = Vec::new();
+        let spec = RawPcmSpec::new(RawEncoding::Unsigned16, Endianness::Big, 1);
+        write_raw_pcm(&mut output, &spec, &[0.0, -1.0]).unwrap();
+        assert_eq!(output, vec![0x80, 0, 0, 1]);
+        let mut output = Vec::new();
+        write_raw_pcm(&mut output, &RawPcmSpec::default(), &[0.5]).unwrap();
+        assert_eq!(output, 16_384i16.to_le_bytes());
+        assert!(write_raw_pcm(
+            &mut output,
+            &RawPcmSpec {
+                channels: 2,
+                ..spec
+            },
+            &[0.0]
+        )
+        .is_err());
+
+        // Annotation and unknown data size
+        let mut file = b".snd\0\0\0\x20\xff\xff\xff\xff\0\0\0\x01\0\0\x1f\x40\0\0\0\x01".to_vec();
+        file.extend_from_slice(b"hello\0\0\0");
+        file.extend_from_slice(&[0xff, 0x7f, 0x80]);
+        let wave = read_au(&mut &file[..]).unwrap();
+        assert_eq!(wave.metadata.info.comment, Some("hello".to_string()));
+        assert_eq!(wave.data, PcmData::I16(vec![0, 0, 32_124]));
+        assert_eq!(wave.subchunk_2_size, 3);
+        assert!(read_au(&mut &file[..20]).is_err());
+
+        // Data offsets inside the header, or past the end of the file
+        let mut corrupt = file.clone();
+        corrupt[4..8].copy_from_slice(&16u32.to_be_bytes());
+        assert!(read_au(&mut &corrupt[..]).is_err());
+        corrupt[4..8].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+        assert!(read_au(&mut &corrupt[..]).is_err());
+
+        // Sample rates and channel counts which do not fit in a `Wave`
+        for &(sample_rate, channels) in &[(0x7fff_ffff, 2), (8000, 0x1_0001), (8000, 0)] {
+            let mut corrupt = file.clone();
+            corrupt[16..20].copy_from_slice(&u32::to_be_bytes(sample_rate));
+            corrupt[20..24].copy_from_slice(&u32::to_be_bytes(channels));
+            let error = read_au(&mut &corrupt[..]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_wav_g711() {
+        use std::io::Cursor;
+
+        let samples = [0.5, -0.5, 0.25];
+        for &law in &[Law::MuLaw, Law::ALaw] {
+            let mut output = Vec::new();
+            write_wav_g711(&mut output, &WavSpec::new(1, 8000), law, &samples).unwrap();
+            // 18 byte fmt chunk with no extension, then fact
+            assert_eq!(LittleEndian::read_u32(&output[16..20]), 18);
+            let tag = LittleEndian::read_u16(&output[20..22]);
+            assert_eq!(tag, if law == Law::MuLaw { 7 } else { 6 });
+            assert_eq!(LittleEndian::read_u16(&output[34..36]), 8);
+            assert_eq!(&output[38..42], b"fact");
+            assert_eq!(LittleEndian::read_u32(&output[46..50]), 3);
+            assert_eq!(&output[50..54], b"data");
+            assert_eq!(LittleEndian::read_u32(&output[54..58]), 3);
+            assert_eq!(output.len(), 58 + 4);
+            assert_eq!(
+                LittleEndian::read_u32(&output[4..8]) as usize,
+                output.len() - 8
+            );
+            let codes: Vec<u8> = PcmData::from_f64(&samples, SampleFormat::I16)
+                .to_i16()
+                .into_iter()
+                .map(|sample| law.encode(sample))
+                .collect();
+            assert_eq!(&output[58..61], &codes[..]);
+
+            let wave = read_wav(&mut &output[..]).unwrap();
+            assert_eq!(wave.audio_format as u16, tag);
+            let expected: Vec<i16> = codes.iter().map(|&code| law.decode(code)).collect();
+            assert_eq!(wave.data, PcmData::I16(expected.clone()));
+
+            let mut wav = WavReader::new(Cursor::new(&output)).unwrap();
+            assert_eq!(wav.len(), 3);
+            assert_eq!(wav.sample_format(), SampleFormat::I16);
+            wav.seek(1).unwrap();
+            assert_eq!(
+                wav.read_sample().unwrap(),
+                Some(f64::from(expected[1]) / 32_768.0)
+            );
+
+            // Extensible, with the G.711 SubFormat
+            let spec = WavSpec::new(3, 8000);
+            let mut output = Vec::new();
+            write_wav_g711(&mut output, &spec, law, &[0.0; 6]).unwrap();
+            assert_eq!(LittleEndian::read_u16(&output[20..22]), 0xfffe);
+            assert_eq!(LittleEndian::read_u16(&output[44..46]), tag);
+            let wave = read_wav(&mut &output[..]).unwrap();
+            // A-law has no zero code, and decodes silence as 8
+            assert_eq!(wave.data, PcmData::I16(vec![law.decode(law.encode(0)); 6]));
+        }
+    }
+
+    #[test]
+    fn test_wav_writer() {
+        use std::io::Cursor;
+
+        let samples: Vec<f64> = (0..9).map(|i| i as f64 / 10.0).collect();
+
+        for &format in &[SampleFormat::I16, SampleFormat::U8, SampleFormat::F32] {
+            let spec = WavSpec::new(2, 8000);
+            let mut expected = Vec::new();
+            write_wav_data(
+                &mut expected,
+                &spec,
+                &PcmData::from_f64(&[&samples[..], &[0.0]].concat(), format),
+            )
+            .unwrap();
+
+            // Header written after existing bytes, and sizes patched when dropped
+            let mut output = Cursor::new(b"xx".to_vec());
+            output.seek(SeekFrom::End(0)).unwrap();
+            {
+                let mut wav = WavWriter::new(&mut output, spec, format).unwrap();
+                wav.write_samples(&samples[..4]).unwrap();
+                wav.write_iter(samples[4..].iter().cloned()).unwrap();
+                assert_eq!(wav.samples_written(), 9);
+            }
+            // Identical to a file written all at once, apart from the reserved JUNK chunk
+            let written = &output.get_ref()[2..];
+            assert_eq!(&written[12..16], b"JUNK");
+            assert_eq!(&written[48..], &expected[12..], "{:?}", format);
+            assert_eq!(
+                LittleEndian::read_u32(&written[4..8]),
+                LittleEndian::read_u32(&expected[4..8]) + 36
+            );
+        }
+
+        let mut wav = WavWriter::new(
+            Cursor::new(Vec::new()),
+            WavSpec::new(1, 8000),
+            SampleFormat::I16,
+        )
+        .unwrap();
+        wav.write_data(&PcmData::I16(vec![1, 2])).unwrap();
+        assert!(wav.write_data(&PcmData::U8(vec![1])).is_err());
+        let output = wav.into_inner().unwrap().into_inner();
+        assert_eq!(read_wav(&mut &output[..]).unwrap().pcm, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_unseekable_wav_writer() {
+        let mut output = Vec::new();
+        {
+            let mut wav =
+                WavWriter::new_unseekable(&mut output, WavSpec::new(1, 8000), SampleFormat::F32)
+                    .unwrap();
+            wav.write_samples(&[0.5, -0.5, 0.25]).unwrap();
+            wav.finalize().unwrap();
+            assert!(wav.write_sample(0.0).is_err());
+        }
+
+        assert_eq!(&output[4..8], &[0xff; 4]);
+        assert_eq!(&output[output.len() - 16..output.len() - 12], &[0xff; 4]);
+
+        let wave = read_wav(&mut &output[..]).unwrap();
+        assert_eq!(wave.data, PcmData::F32(vec![0.5, -0.5, 0.25]));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_wav_reader() {
+        use std::io::Cursor;
+
+        let samples: Vec<f64> = (0..3000).map(|i| (i % 200) as f64 / 200.0 - 0.5).collect();
+        let mut output = Cursor::new(Vec::new());
+        {
+            let mut wav =
+                WavWriter::new(&mut output, WavSpec::new(3, 1000), SampleFormat::I24).unwrap();
+            wav.write_samples(&samples).unwrap();
+        }
+        let expected = read_wav(&mut &output.get_ref()[..]).unwrap().samples();
+
+        output.set_position(0);
+        let mut wav = WavReader::new(output).unwrap();
+        assert_eq!(wav.spec().channels, 3);
+        assert_eq!(wav.sample_format(), SampleFormat::I24);
+        assert_eq!(wav.len(), 3000);
+        assert_eq!(wav.frames(), 1000);
+        assert_eq!(wav.duration(), 1.0);
+
+        let all: Vec<f64> = wav.samples().map(|sample| sample.unwrap()).collect();
+        assert_eq!(all, expected);
+        assert!(wav.read_sample().unwrap().is_none());
+
+        wav.seek(500).unwrap();
+        assert_eq!(wav.position(), 500);
+        let frames: Vec<Vec<f64>> = wav.iter_frames().map(|frame| frame.unwrap()).collect();
+        assert_eq!(frames.len(), 500);
+        assert_eq!(frames[1], expected[1503..1506].to_vec());
+
+        wav.seek(5000).unwrap();
+        assert!(wav.read_frame().unwrap().is_none());
+        wav.seek(u64::MAX).unwrap();
+        assert_eq!(wav.position(), 1000);
+        assert!(wav.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rf64() {
+        use std::io::Cursor;
+
+        // Written directly as RF64
+        let data = PcmData::F32(vec![0.5, -0.5, 0.25]);
+        let mut output = Vec::new();
+        write_wav_header(
+            &mut output,
+            &WavSpec::new(1, 8000),
+            RawEncoding::for_wav(data.format()),
+            data.len() as u64,
+            HeaderLayout::Rf64,
+            &[],
+        )
+        .unwrap();
+        data.write_le(&mut output).unwrap();
+
+        let wave = read_wav(&mut &output[..]).unwrap();
+        assert_eq!(wave.chunk_id, 0x5246_3634); // RF64
+        assert_eq!(wave.chunk_size, -1);
+        assert_eq!(wave.subchunk_2_size, -1);
+        assert_eq!(wave.data, data);
+        let ds64 = wave.ds64.unwrap();
+        assert_eq!(ds64.riff_size as usize, output.len() - 8);
+        assert_eq!(ds64.data_size, 12);
+        assert_eq!(ds64.sample_count, 3);
+
+        // Promoted from RIFF when the streaming writer grows past the limit
+        for &limit in &[1000, 10] {
+            let mut wav = WavWriter::new(
+                Cursor::new(Vec::new()),
+                WavSpec::new(2, 8000),
+                SampleFormat::I16,
+            )
+            .unwrap();
+            wav.riff_size_limit = limit;
+            wav.write_samples(&[0.5; 20]).unwrap();
+            let output = wav.into_inner().unwrap().into_inner();
+
+            let wave = read_wav(&mut &output[..]).unwrap();
+            assert_eq!(wave.pcm, vec![16_384; 20]);
+            assert_eq!(wave.ds64.is_some(), limit == 10);
+            let mut reader = WavReader::new(Cursor::new(&output)).unwrap();
+            assert_eq!(reader.frames(), 10);
+            assert_eq!(reader.read_frame().unwrap(), Some(vec![0.5, 0.5]));
+        }
     }
 }